@@ -0,0 +1,115 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+use tokio::time::sleep;
+
+use opifex::{handle, pipeline::Builder, Task};
+
+pub struct Parse;
+
+impl Task for Parse {
+    type Handle = handle::Worker<handle::TwoWay<String, i32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(text) = rx.recv() => {
+                        match text.trim().parse::<i32>() {
+                            Ok(n) => { let _ = hnd.post_message(n).await; }
+                            Err(e) => println!("Oops! {text:?} is not a number: {e}"),
+                        }
+                    }
+                    () = hnd.terminated() => {
+                        println!("Bye from parse stage!");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct Square;
+
+impl Task for Square {
+    type Handle = handle::Worker<handle::TwoWay<i32, i64>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(n) = rx.recv() => {
+                        let n = i64::from(n);
+                        let _ = hnd.post_message(n * n).await;
+                    }
+                    () = hnd.terminated() => {
+                        println!("Bye from square stage!");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct Print;
+
+impl Task for Print {
+    type Handle = handle::Worker<handle::OnEvent<i64>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Ok(n) = rx.recv() => println!("Result is {n}"),
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let pipeline = Builder::new(Parse).then(Square).spawn();
+    let printer = pipeline.on_message(Print);
+
+    for text in ["12", "x", "-3"] {
+        if let Err(e) = pipeline.post_message(text.to_string()).await {
+            eprintln!("Oops! sending a message to the pipeline reports: {e}");
+        }
+    }
+
+    // put to sleep this thread just to let the stages to do the work!
+    sleep(Duration::from_secs(1)).await;
+
+    printer.terminate();
+    pipeline.terminate();
+
+    // waiting to let terminations happens...
+    sleep(Duration::from_millis(500)).await;
+}
@@ -0,0 +1,98 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use serde::{Deserialize, Serialize};
+
+use opifex::{
+    codec::Codec,
+    handle,
+    process::{self, ProcessWorker, Termination},
+    worker::TwoWay,
+    Task,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sum {
+    a: i32,
+    b: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Result {
+    sum: i32,
+}
+
+pub struct Adder {}
+
+impl Task for Adder {
+    type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(Sum { a, b }) = rx.recv() => {
+                        let _ = hnd.post_message(Result { sum: a.saturating_add(b) }).await;
+                    }
+                    () = hnd.terminated() => {
+                        // stdout is reserved to the frames sent to the parent
+                        eprintln!("Worker is terminated. Bye from adder process!");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // the same binary runs as the child process, in the adder role
+    if process::role().as_deref() == Some("adder") {
+        if let Err(e) = process::serve_two_way(Adder {}, Codec::Bincode).await {
+            eprintln!("Oops! serving the adder reports: {e}");
+        }
+        return;
+    }
+
+    let adder_worker = match ProcessWorker::<TwoWay<Sum, Result>>::spawn_role(
+        "adder",
+        Codec::Bincode,
+        Termination::Signal,
+    ) {
+        Ok(worker) => worker,
+        Err(e) => {
+            eprintln!("Oops! spawning the adder process reports: {e}");
+            return;
+        }
+    };
+    let mut results = adder_worker.subscribe();
+
+    for (a, b) in [(24, 28), (123, 45)] {
+        if let Err(e) = adder_worker.post_message(Sum { a, b }).await {
+            eprintln!("Oops! sending a message to the adder process reports: {e}");
+        }
+        match results.recv().await {
+            Ok(Result { sum }) => println!("Result is {sum}"),
+            Err(e) => eprintln!("Oops! receiving a result reports: {e}"),
+        }
+    }
+
+    // terminating the worker terminates the child process too
+    adder_worker.terminate();
+
+    match adder_worker.wait().await {
+        Ok(status) => println!("Adder process exited with {status}"),
+        Err(e) => eprintln!("Oops! waiting for the adder process reports: {e}"),
+    }
+}
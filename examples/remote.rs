@@ -0,0 +1,92 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use opifex::{
+    codec::Codec,
+    handle,
+    remote::{Endpoint, RemoteWorker},
+    worker::{TwoWay, Worker},
+    Task,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sum {
+    a: i32,
+    b: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Result {
+    sum: i32,
+}
+
+pub struct Adder {}
+
+impl Task for Adder {
+    type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(Sum { a, b }) = rx.recv() => {
+                        let _ = hnd.post_message(Result { sum: a.saturating_add(b) }).await;
+                    }
+                    () = hnd.terminated() => {
+                        println!("Worker is terminated. Bye from adder task!");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // the served worker, in a real application it would live in another process
+    let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {});
+    let addr = match adder_worker.serve_tcp("127.0.0.1:0", Codec::Bincode).await {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("Oops! serving the adder reports: {e}");
+            return;
+        }
+    };
+
+    let remote_worker = RemoteWorker::<TwoWay<Sum, Result>>::connect(
+        Endpoint::Tcp(addr.to_string()),
+        Codec::Bincode,
+    );
+    let mut results = remote_worker.subscribe();
+
+    for (a, b) in [(24, 28), (123, 45)] {
+        if let Err(e) = remote_worker.post_message(Sum { a, b }).await {
+            eprintln!("Oops! sending a message to the remote adder reports: {e}");
+        }
+        match results.recv().await {
+            Ok(Result { sum }) => println!("Result is {sum}"),
+            Err(e) => eprintln!("Oops! receiving a result reports: {e}"),
+        }
+    }
+
+    // terminating the remote worker terminates the served one too
+    remote_worker.terminate();
+
+    // waiting to let terminations happens...
+    sleep(Duration::from_millis(500)).await;
+}
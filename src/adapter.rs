@@ -0,0 +1,284 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Adapters between opifex channels and the [`futures`] `Stream` and `Sink`
+//! traits. Available with the `futures` feature.
+//!
+//! * [`worker::Worker<OneWay>`] and [`worker::Worker<TwoWay>`] implement
+//!   `Sink<Message>`, so they can be fed by stream combinators;
+//! * the handle modes expose a `stream` function that, like `receiver`,
+//!   splits the handle in a [`Stream`] of messages and the remaining handle;
+//! * [`ResponseStream`] implements [`Stream`] of responses.
+//!
+//! Every stream finishes as soon as the worker is terminated. A message a
+//! sink can't hand over to the task, because the task is terminated or the
+//! sink is closed, becomes a dead letter, see [`crate::dead_letter`].
+//!
+//! The message and event streams mark the processing of every item for the
+//! watchdog of the worker, see [`crate::watchdog`]: an item is processed from
+//! when it is returned by the stream until the next one is asked for.
+//!
+//! [`worker::Worker<OneWay>`]: crate::worker::Worker
+//! [`worker::Worker<TwoWay>`]: crate::worker::Worker
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{Sink, Stream};
+use tokio::sync::mpsc::Receiver;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::{PollSender, WaitForCancellationFutureOwned};
+
+use crate::{
+    dead_letter::{self, Reason},
+    handle,
+    streaming::ResponseStream,
+    watchdog::MessageGuard,
+    worker,
+    worker::Shared,
+    Error, ErrorKind,
+};
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// here I use a macro because OneWay and TwoWay sinks are identical.
+macro_rules! impl_sink {
+    ($mode:ty $(, $param:ident)*) => {
+        impl<Message: Send + 'static, Ctrl $(, $param)*> Sink<Message> for worker::Worker<$mode, Ctrl> {
+            type Error = Error;
+
+            fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+                let worker = self.get_mut();
+                let mode = &mut worker.mode;
+                let sender = mode
+                    .poll_sender
+                    .get_or_insert_with(|| PollSender::new(mode.sender_to_tsk.clone()));
+                sender
+                    .poll_reserve(cx)
+                    .map_err(|e| worker.shared.error(ErrorKind::Terminated, e))
+            }
+
+            fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<(), Error> {
+                let worker = self.get_mut();
+                let mode = &mut worker.mode;
+                let sender = mode
+                    .poll_sender
+                    .get_or_insert_with(|| PollSender::new(mode.sender_to_tsk.clone()));
+                sender.send_item(msg).map_err(|e| {
+                    let error = worker.shared.error(ErrorKind::Terminated, &e);
+                    // the message is given back when the sink is closed
+                    if let Some(msg) = e.into_inner() {
+                        let _ = dead_letter::post(&worker.shared, msg, Reason::Terminated);
+                    }
+                    error
+                })
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+                // messages are handed over to the channel by start_send
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+                // only the sink side is closed: the worker keeps its own sender
+                // so post_message remains usable.
+                if let Some(sender) = self.get_mut().mode.poll_sender.as_mut() {
+                    sender.close();
+                }
+                Poll::Ready(Ok(()))
+            }
+        }
+    };
+}
+
+impl_sink!(worker::OneWay<Message>);
+impl_sink!(worker::TwoWay<Message, TaskMessage>, TaskMessage);
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// Marks the items returned by a stream for the watchdog of the worker.
+struct Marker {
+    shared: Arc<Shared>,
+    // the item being processed
+    guard: Option<MessageGuard>,
+}
+
+impl Marker {
+    fn new(shared: Arc<Shared>) -> Marker {
+        Marker {
+            shared,
+            guard: None,
+        }
+    }
+
+    // Called when the next item is asked for, with the result of the poll.
+    fn mark<Item>(&mut self, poll: Poll<Option<Item>>) -> Poll<Option<Item>> {
+        self.guard = None;
+        if let Poll::Ready(Some(_)) = poll {
+            self.guard = Some(self.shared.watch().start());
+        }
+        poll
+    }
+}
+
+/// Stream of the messages sent by a worker to its task. It finishes when the
+/// worker is terminated or when the worker is dropped.
+pub struct MessageStream<Message> {
+    receiver: Receiver<Message>,
+    terminated: Pin<Box<WaitForCancellationFutureOwned>>,
+    marker: Marker,
+}
+
+impl<Message> MessageStream<Message> {
+    /// Returns the guard of the message being processed, the last one
+    /// returned by the stream, to know if the watchdog cancelled it. See
+    /// [`MessageGuard::cancelled()`].
+    pub fn current_message(&self) -> Option<&MessageGuard> {
+        self.marker.guard.as_ref()
+    }
+}
+
+impl<Message> Stream for MessageStream<Message> {
+    type Item = Message;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        let this = self.get_mut();
+        if this.terminated.as_mut().poll(cx).is_ready() {
+            return this.marker.mark(Poll::Ready(None));
+        }
+        this.marker.mark(this.receiver.poll_recv(cx))
+    }
+}
+
+/// Stream of the events sent by a two-way task to its subscribers. It finishes
+/// when the subscription is terminated or when the sending task is gone.
+///
+/// Events lost because the subscriber is lagging behind are skipped.
+pub struct EventStream<Event> {
+    // the replayed events not yet returned
+    backlog: VecDeque<Event>,
+    receiver: BroadcastStream<Event>,
+    terminated: Pin<Box<WaitForCancellationFutureOwned>>,
+    marker: Marker,
+}
+
+// the events are never pinned
+impl<Event> Unpin for EventStream<Event> {}
+
+impl<Event> EventStream<Event> {
+    /// Returns the guard of the event being processed, the last one returned
+    /// by the stream, see [`MessageStream::current_message()`].
+    pub fn current_message(&self) -> Option<&MessageGuard> {
+        self.marker.guard.as_ref()
+    }
+}
+
+impl<Event: Clone + Send + 'static> Stream for EventStream<Event> {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        let this = self.get_mut();
+        let poll = if this.terminated.as_mut().poll(cx).is_ready() {
+            Poll::Ready(None)
+        } else if let Some(event) = this.backlog.pop_front() {
+            Poll::Ready(Some(event))
+        } else {
+            loop {
+                match Pin::new(&mut this.receiver).poll_next(cx) {
+                    Poll::Ready(Some(Ok(event))) => break Poll::Ready(Some(event)),
+                    Poll::Ready(Some(Err(_lagged))) => continue,
+                    Poll::Ready(None) => break Poll::Ready(None),
+                    Poll::Pending => break Poll::Pending,
+                }
+            }
+        };
+        this.marker.mark(poll)
+    }
+}
+
+impl<Resp> Stream for ResponseStream<Resp> {
+    type Item = Resp;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Resp>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Message, Ctrl> handle::Worker<handle::OneWay<Message>, Ctrl> {
+    /// Like [`Self::receiver()`] but the messages are returned as a [`Stream`]
+    /// that finishes when the worker is terminated.
+    pub fn stream(
+        self,
+    ) -> (
+        MessageStream<Message>,
+        handle::Worker<handle::Isolated, Ctrl>,
+    ) {
+        let token = self.termination_token.clone();
+        let marker = Marker::new(self.shared.clone());
+        let (receiver, hnd) = self.receiver();
+
+        (
+            MessageStream {
+                receiver,
+                terminated: Box::pin(token.cancelled_owned()),
+                marker,
+            },
+            hnd,
+        )
+    }
+}
+
+impl<InMessage, OutMessage, Ctrl> handle::Worker<handle::TwoWay<InMessage, OutMessage>, Ctrl> {
+    /// Like [`Self::receiver()`] but the messages are returned as a [`Stream`]
+    /// that finishes when the worker is terminated.
+    pub fn stream(
+        self,
+    ) -> (
+        MessageStream<InMessage>,
+        handle::Worker<handle::OneWayBack<OutMessage>, Ctrl>,
+    ) {
+        let token = self.termination_token.clone();
+        let marker = Marker::new(self.shared.clone());
+        let (receiver, hnd) = self.receiver();
+
+        (
+            MessageStream {
+                receiver,
+                terminated: Box::pin(token.cancelled_owned()),
+                marker,
+            },
+            hnd,
+        )
+    }
+}
+
+impl<Event: Clone + Send + 'static, Ctrl> handle::Worker<handle::OnEvent<Event>, Ctrl> {
+    /// Like [`Self::receiver()`] but the events are returned as a [`Stream`]
+    /// that finishes when the subscription is terminated.
+    pub fn stream(self) -> (EventStream<Event>, handle::Worker<handle::Isolated, Ctrl>) {
+        let token = self.termination_token.clone();
+        let marker = Marker::new(self.shared.clone());
+        let (receiver, hnd) = self.receiver();
+
+        (
+            EventStream {
+                backlog: receiver.backlog,
+                receiver: BroadcastStream::new(receiver.receiver),
+                terminated: Box::pin(token.cancelled_owned()),
+                marker,
+            },
+            hnd,
+        )
+    }
+}
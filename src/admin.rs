@@ -0,0 +1,293 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! An HTTP endpoint, bound to localhost, to inspect and control the workers
+//! of a [`HealthRegistry`]. Available with the `admin` feature.
+//!
+//!```rust,no_run
+//! # use opifex::{health::HealthRegistry, worker::TwoWay, Worker};
+//! # use std::future::Future;
+//! # use opifex::{handle, Task};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(Sum { a, b }) = rx.recv().await {
+//! #                 let _ = hnd.post_message(Result { sum: a + b }).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(result) = rx.recv().await {
+//! #                 println!("{result:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::io::Result<()> {
+//! # let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {});
+//! let registry = HealthRegistry::new();
+//! registry.register(&adder_worker);
+//! let admin = registry.serve_admin(9090).await?;
+//! # Ok(())
+//! # }
+//!```
+//!
+//! The endpoint answers with JSON to:
+//! * `GET /workers`: every registered worker, with its mode, status, mailbox
+//!   depth, subscribers and uptime, see [`WorkerInfo`];
+//! * `GET /health/live` and `GET /health/ready`: the liveness and readiness
+//!   of the registry, with status 503 when not live or not ready;
+//! * `POST /workers/{id}/terminate`, `POST /workers/{id}/pause` and
+//!   `POST /workers/{id}/resume`: see [`HealthRegistry::terminate()`] and the
+//!   others.
+//!
+//! There is no authentication: anyone that can connect to localhost can
+//! terminate the workers. Web pages can't: a request is refused when its
+//! `Host` header is not `localhost` or `127.0.0.1`, which stops DNS
+//! rebinding, or when it has an `Origin` header, as the requests sent by
+//! browsers on behalf of a page do.
+
+use std::{
+    fmt::Display,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    health::{HealthRegistry, WorkerInfo},
+    listener::spawn_accept,
+    worker::WorkerId,
+};
+
+// the longest request that is read
+const MAX_REQUEST: u64 = 8 * 1024;
+
+// how long a client can take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the names the endpoint can be reached with
+const LOCAL_HOSTS: [&str; 2] = ["localhost", "127.0.0.1"];
+
+/// A running admin endpoint, that stops accepting connections when dropped.
+pub struct AdminServer {
+    local_addr: SocketAddr,
+    token: CancellationToken,
+}
+
+impl AdminServer {
+    /// Returns the address the endpoint is bound to, useful when binding to
+    /// port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting connections.
+    pub fn shutdown(self) {
+        self.token.cancel();
+    }
+}
+
+impl Drop for AdminServer {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+impl HealthRegistry {
+    /// Serves the admin endpoint of this registry on `127.0.0.1:port`, until
+    /// the returned server is shut down or dropped.
+    pub async fn serve_admin(&self, port: u16) -> io::Result<AdminServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let local_addr = listener.local_addr()?;
+
+        let token = CancellationToken::new();
+        let (registry, port) = (self.clone(), local_addr.port());
+        spawn_accept(listener, token.clone(), move |stream| {
+            // a broken connection concerns only its client
+            tokio::spawn(answer(stream, registry.clone(), port));
+        });
+
+        Ok(AdminServer { local_addr, token })
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// the parts of a request that are used
+struct Request {
+    line: String,
+    host: Option<String>,
+    origin: bool,
+}
+
+// Answers the single request sent on `stream`, to the endpoint on `port`.
+async fn answer(stream: TcpStream, registry: HealthRegistry, port: u16) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST));
+
+    // a client too slow to send its request is dropped
+    let Ok(request) = timeout(READ_TIMEOUT, read_request(&mut reader)).await else {
+        return Ok(());
+    };
+    let request = request?;
+
+    let local = request.host.as_deref().is_some_and(|host| {
+        LOCAL_HOSTS
+            .iter()
+            .any(|name| host == *name || host == format!("{name}:{port}"))
+    });
+    let (status, body) = if local && !request.origin {
+        route(&registry, &request.line)
+    } else {
+        (FORBIDDEN, error_json("forbidden"))
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Request> {
+    let mut request = Request {
+        line: String::new(),
+        host: None,
+        origin: false,
+    };
+    reader.read_line(&mut request.line).await?;
+
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            return Ok(request);
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        if name.eq_ignore_ascii_case("host") {
+            request.host = Some(value.trim().to_ascii_lowercase());
+        } else if name.eq_ignore_ascii_case("origin") {
+            request.origin = true;
+        }
+    }
+}
+
+// Returns the status and the body of the response to the `request` line.
+fn route(registry: &HealthRegistry, request: &str) -> (&'static str, Value) {
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path: Vec<&str> = parts
+        .next()
+        .unwrap_or_default()
+        .trim_matches('/')
+        .split('/')
+        .collect();
+
+    match (method, path.as_slice()) {
+        ("GET", ["workers"]) => {
+            let workers: Vec<Value> = registry.workers().iter().map(worker_json).collect();
+            (OK, Value::Array(workers))
+        }
+        ("GET", ["health", probe @ ("live" | "ready")]) => {
+            let report = registry.report();
+            let up = if *probe == "live" {
+                report.live
+            } else {
+                report.ready
+            };
+            let body = json!({ "live": report.live, "ready": report.ready });
+            (if up { OK } else { UNAVAILABLE }, body)
+        }
+        ("POST", ["workers", id, action @ ("terminate" | "pause" | "resume")]) => {
+            let Ok(id) = id.parse::<WorkerId>() else {
+                return (BAD_REQUEST, error_json("bad worker id"));
+            };
+            if !registry
+                .workers()
+                .iter()
+                .any(|info| info.health.worker == id)
+            {
+                return (NOT_FOUND, error_json("worker not registered"));
+            }
+
+            let done = match *action {
+                "terminate" => registry.terminate(id),
+                "pause" => registry.pause(id),
+                _ => registry.resume(id),
+            };
+            match done {
+                Ok(()) => (OK, json!({})),
+                Err(e) => (CONFLICT, error_json(e)),
+            }
+        }
+        ("GET" | "POST", _) => (NOT_FOUND, error_json("not found")),
+        _ => (NOT_ALLOWED, error_json("method not allowed")),
+    }
+}
+
+const OK: &str = "200 OK";
+const BAD_REQUEST: &str = "400 Bad Request";
+const FORBIDDEN: &str = "403 Forbidden";
+const NOT_FOUND: &str = "404 Not Found";
+const NOT_ALLOWED: &str = "405 Method Not Allowed";
+const CONFLICT: &str = "409 Conflict";
+const UNAVAILABLE: &str = "503 Service Unavailable";
+
+fn worker_json(info: &WorkerInfo) -> Value {
+    json!({
+        "id": info.health.worker.value(),
+        "name": info.health.name.as_deref(),
+        "mode": info.mode,
+        "status": format!("{:?}", info.health.status),
+        "paused": info.paused,
+        "mailbox_depth": info.mailbox_depth,
+        "subscribers": info.subscribers,
+        "uptime_ms": info.uptime.as_millis() as u64,
+        "last_heartbeat_ms": info
+            .health
+            .last_heartbeat
+            .map(|last| last.elapsed().as_millis() as u64),
+    })
+}
+
+fn error_json(e: impl Display) -> Value {
+    json!({ "error": e.to_string() })
+}
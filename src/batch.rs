@@ -0,0 +1,117 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Receiving messages in batches.
+//!
+//! The one-way and two-way handles expose a `batched` function that, like
+//! `receiver`, splits the handle, but the messages are received in batches by
+//! a [`BatchReceiver`]:
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{handle, Task};
+//! # pub struct Row {}
+//! # async fn store(rows: Vec<Row>) {
+//! #     println!("stored {} rows", rows.len());
+//! # }
+//! # pub struct Writer {}
+//! # impl Task for Writer {
+//! #     type Handle = handle::Worker<handle::OneWay<Row>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! let (mut batches, hnd) = wk_hnd.batched(100, Duration::from_millis(50));
+//! # async move {
+//! loop {
+//!     tokio::select! {
+//!         Some(rows) = batches.recv() => store(rows).await,
+//!         () = hnd.terminated() => break,
+//!     }
+//! }
+//! # }
+//! #     }
+//! # }
+//!```
+
+use std::{mem, time::Duration};
+
+use tokio::{sync::mpsc::Receiver, time::Instant};
+
+use crate::runtime::sleep_until;
+
+/// Receives the messages sent from the worker in batches: a batch is complete
+/// when it has `max` messages or when `linger` has passed since its first
+/// message was received.
+pub struct BatchReceiver<Message> {
+    receiver: Receiver<Message>,
+    max: usize,
+    linger: Duration,
+    // the batch being filled, kept here so that recv is cancel safe
+    pending: Vec<Message>,
+    // when the pending batch is complete anyway
+    deadline: Option<Instant>,
+}
+
+impl<Message> BatchReceiver<Message> {
+    pub(crate) fn new(
+        receiver: Receiver<Message>,
+        max: usize,
+        linger: Duration,
+    ) -> BatchReceiver<Message> {
+        BatchReceiver {
+            receiver,
+            max: max.max(1),
+            linger,
+            pending: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Receives the next batch. Returns `None` when the worker has gone and
+    /// no more messages can be received; the messages received before are
+    /// returned in a last batch.
+    ///
+    /// This function is cancel safe: the messages of an incomplete batch are
+    /// kept for the next call.
+    pub async fn recv(&mut self) -> Option<Vec<Message>> {
+        loop {
+            if self.pending.len() >= self.max {
+                return Some(self.take());
+            }
+
+            let msg = match self.deadline {
+                None => self.receiver.recv().await,
+                Some(deadline) => tokio::select! {
+                    msg = self.receiver.recv() => msg,
+                    () = sleep_until(deadline) => return Some(self.take()),
+                },
+            };
+
+            match msg {
+                Some(msg) => {
+                    if self.pending.is_empty() {
+                        self.deadline = Some(Instant::now() + self.linger);
+                    }
+                    self.pending.push(msg);
+                    // the messages already waiting don't need a wakeup each
+                    while self.pending.len() < self.max {
+                        match self.receiver.try_recv() {
+                            Ok(msg) => self.pending.push(msg),
+                            Err(_) => break,
+                        }
+                    }
+                }
+                None if self.pending.is_empty() => return None,
+                None => return Some(self.take()),
+            }
+        }
+    }
+
+    fn take(&mut self) -> Vec<Message> {
+        self.deadline = None;
+        mem::take(&mut self.pending)
+    }
+}
@@ -0,0 +1,216 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! A publish/subscribe bus with named topics.
+//!
+//! A [`Bus`] is not bound to a worker: anyone holding a clone of it, a task
+//! included, can publish events to a topic, and the subscribers receive the
+//! events published to the topics matching their pattern.
+//!
+//! Topics are made of segments separated by dots, like `"orders.eu.created"`.
+//! In a pattern `*` matches exactly one segment and `#` matches zero or more
+//! segments, so `"orders.*.created"` and `"orders.#"` both match the topic
+//! above.
+//!
+//!```rust
+//! # use std::future::Future;
+//! # use opifex::{bus::{Bus, Published}, handle, Task};
+//! # #[derive(Clone, Debug)]
+//! # pub struct OrderEvent {
+//! #     id: u64,
+//! # }
+//! # pub struct Auditor {}
+//! # impl Task for Auditor {
+//! #     type Handle = handle::Worker<handle::OnEvent<Published<OrderEvent>>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(Published { topic, event }) = rx.recv().await {
+//! #                 println!("{topic}: {event:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let bus = Bus::<OrderEvent>::new();
+//!
+//! // the subscriber task gets a handle in OnEvent mode, as usual
+//! let auditor = bus.on_topic("orders.#", Auditor {});
+//!
+//! // a task publishing with its clone of the bus
+//! bus.publish("orders.eu.created", OrderEvent { id: 42 });
+//! # }
+//!```
+//!
+//! A subscription goes away when the subscriber worker is terminated, when
+//! its task finishes, or when its receiver is dropped.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, MutexGuard, Weak,
+};
+
+use tokio::sync::broadcast;
+
+use crate::{
+    event::EventReceiver,
+    handle, runtime,
+    worker::{Isolated, Worker},
+    Task, BUFFER_CAPACITY,
+};
+
+/// An event published to a topic of a [`Bus`].
+#[derive(Clone, Debug)]
+pub struct Published<Event> {
+    /// The topic the event was published to.
+    pub topic: String,
+    /// The event.
+    pub event: Event,
+}
+
+/// A bus of events of type `Event`. Cloning the bus gives another reference
+/// to the same bus.
+pub struct Bus<Event> {
+    inner: Arc<Inner<Event>>,
+}
+
+struct Inner<Event> {
+    // the buffer of every subscriber
+    capacity: usize,
+    subscriptions: Mutex<Vec<Subscription<Event>>>,
+    next_id: AtomicU64,
+}
+
+struct Subscription<Event> {
+    id: u64,
+    // the segments of the pattern
+    pattern: Vec<String>,
+    sender: broadcast::Sender<Published<Event>>,
+}
+
+impl<Event> Clone for Bus<Event> {
+    fn clone(&self) -> Self {
+        Bus {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<Event: Clone> Default for Bus<Event> {
+    fn default() -> Self {
+        Bus::new()
+    }
+}
+
+impl<Event: Clone> Bus<Event> {
+    /// Creates a bus whose subscribers can fall behind by up to
+    /// [`BUFFER_CAPACITY`] events.
+    pub fn new() -> Bus<Event> {
+        Bus::with_capacity(BUFFER_CAPACITY)
+    }
+
+    /// Creates a bus whose subscribers can fall behind by up to `capacity`
+    /// events, then they lag like a [`broadcast::Receiver`].
+    pub fn with_capacity(capacity: usize) -> Bus<Event> {
+        Bus {
+            inner: Arc::new(Inner {
+                capacity: capacity.max(1),
+                subscriptions: Mutex::new(Vec::new()),
+                next_id: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Publishes `event` to `topic`. Returns the number of subscribers that
+    /// will receive it.
+    pub fn publish(&self, topic: &str, event: Event) -> usize {
+        let segments: Vec<&str> = topic.split('.').collect();
+
+        let mut subscriptions = self.inner.lock();
+        subscriptions.retain(|sub| sub.sender.receiver_count() > 0);
+        subscriptions
+            .iter()
+            .filter(|sub| matches(&sub.pattern, &segments))
+            .filter(|sub| {
+                sub.sender
+                    .send(Published {
+                        topic: topic.to_string(),
+                        event: event.clone(),
+                    })
+                    .is_ok()
+            })
+            .count()
+    }
+
+    /// Returns a receiver of the events published to the topics matching
+    /// `pattern`.
+    pub fn subscribe(&self, pattern: &str) -> EventReceiver<Published<Event>> {
+        self.subscription(pattern).1
+    }
+
+    /// Let `task` to subscribe to the events published to the topics
+    /// matching `pattern`, see [`Worker::on_message()`]. The subscription is
+    /// removed when the returned worker is terminated.
+    pub fn on_topic<T, C>(&self, pattern: &str, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<Published<Event>>, C>>,
+        <T as Task>::Output: Send + 'static,
+        Event: Send + 'static,
+    {
+        let (id, receiver) = self.subscription(pattern);
+        let worker = Worker::subscriber(task, receiver);
+
+        let token = worker.termination_token.clone();
+        let finished = worker.shared.finished.clone();
+        let bus = Arc::downgrade(&self.inner);
+        runtime::spawn(async move {
+            tokio::select! {
+                () = token.cancelled() => {}
+                () = finished.cancelled() => {}
+            }
+            if let Some(inner) = Weak::upgrade(&bus) {
+                inner.lock().retain(|sub| sub.id != id);
+            }
+        });
+
+        worker
+    }
+
+    fn subscription(&self, pattern: &str) -> (u64, EventReceiver<Published<Event>>) {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = broadcast::channel(self.inner.capacity);
+
+        self.inner.lock().push(Subscription {
+            id,
+            pattern: pattern.split('.').map(str::to_string).collect(),
+            sender,
+        });
+
+        (id, receiver.into())
+    }
+}
+
+impl<Event> Inner<Event> {
+    fn lock(&self) -> MutexGuard<'_, Vec<Subscription<Event>>> {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Returns true if the `topic` segments match the `pattern` ones.
+fn matches(pattern: &[String], topic: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => topic.is_empty(),
+        Some((first, rest)) if first == "#" => {
+            (0..=topic.len()).any(|i| matches(rest, &topic[i..]))
+        }
+        Some((first, rest)) => topic.split_first().is_some_and(|(segment, topic)| {
+            (first == "*" || first == segment) && matches(rest, topic)
+        }),
+    }
+}
@@ -0,0 +1,426 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Circuit breakers around the delivery of messages to a worker.
+//!
+//! A [`CircuitBreaker`] wraps a worker that messages can be posted to, see
+//! [`Post`], and keeps track of the outcome of the last posts. When too many
+//! of them fail the circuit opens, and the posts fail fast with
+//! [`ErrorKind::CircuitOpen`] without reaching the worker. After a while the
+//! circuit is half-open: a few probe messages are let through, and the
+//! circuit closes again if they succeed or opens again if they fail.
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{
+//! #     circuit::{CircuitBreaker, CircuitPolicy, Transition},
+//! #     handle,
+//! #     worker::OneWay,
+//! #     Error, ErrorKind, Task, Worker,
+//! # };
+//! # pub struct Call {}
+//! # pub struct ApiTask {}
+//! # impl Task for ApiTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Call>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move { while rx.recv().await.is_some() {} }
+//! #     }
+//! # }
+//! # pub struct TransitionLogger {}
+//! # impl Task for TransitionLogger {
+//! #     type Handle = handle::Worker<handle::OnEvent<Transition>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(transition) = rx.recv().await {
+//! #                 println!("{transition:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # fn fallback() {}
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! # let call = Call {};
+//! let breaker = CircuitBreaker::new(
+//!     Worker::<OneWay<Call>>::spawn(ApiTask {}),
+//!     CircuitPolicy::new()
+//!         .with_failure_rate(0.5)
+//!         .with_open_for(Duration::from_secs(10)),
+//! );
+//! let logger = breaker.on_transition(TransitionLogger {});
+//!
+//! match breaker.post_message(call).await {
+//!     Err(e) if e.kind() == ErrorKind::CircuitOpen => fallback(),
+//!     result => result?,
+//! }
+//! # Ok(())
+//! # }
+//!```
+//!
+//! By default a post succeeds when the message is delivered to the task.
+//! With [`Outcomes::Reported`] the task itself tells how the processing of
+//! every message went, using a [`Reporter`].
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use tokio::{sync::broadcast, time::Instant};
+
+use crate::{
+    event::EventReceiver,
+    handle,
+    worker::{Isolated, Post, Worker},
+    Error, ErrorKind, Task, BUFFER_CAPACITY,
+};
+
+/// The state of a circuit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The messages are delivered to the worker.
+    Closed,
+    /// The posts fail fast, without reaching the worker.
+    Open,
+    /// A few probe messages are delivered, to find out if the worker has
+    /// recovered.
+    HalfOpen,
+}
+
+/// A change of the state of a circuit, sent to its subscribers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transition {
+    /// The state before.
+    pub from: CircuitState,
+    /// The state after.
+    pub to: CircuitState,
+}
+
+/// What counts as the outcome of a message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Outcomes {
+    /// A message succeeds when it is delivered to the task.
+    #[default]
+    Posted,
+    /// The outcome of a delivered message is reported by the task with a
+    /// [`Reporter`]; a message that can't be delivered still fails.
+    Reported,
+}
+
+/// When a circuit opens and how it recovers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircuitPolicy {
+    window: usize,
+    failure_rate: f64,
+    min_calls: usize,
+    open_for: Duration,
+    probes: u32,
+    outcomes: Outcomes,
+}
+
+impl Default for CircuitPolicy {
+    fn default() -> Self {
+        CircuitPolicy::new()
+    }
+}
+
+impl CircuitPolicy {
+    /// The circuit opens when at least half of the last 20 messages failed,
+    /// but not before 10 messages, and stays open for 5 seconds. Then a
+    /// single probe message is let through.
+    pub fn new() -> CircuitPolicy {
+        CircuitPolicy {
+            window: 20,
+            failure_rate: 0.5,
+            min_calls: 10,
+            open_for: Duration::from_secs(5),
+            probes: 1,
+            outcomes: Outcomes::default(),
+        }
+    }
+
+    /// Sets how many of the last outcomes are used to compute the failure
+    /// rate.
+    pub fn with_window(mut self, window: usize) -> CircuitPolicy {
+        self.window = window.max(1);
+        self.min_calls = self.min_calls.min(self.window);
+        self
+    }
+
+    /// Sets the failure rate, between 0 and 1, that opens the circuit.
+    pub fn with_failure_rate(mut self, failure_rate: f64) -> CircuitPolicy {
+        self.failure_rate = failure_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets how many outcomes are needed, at least, before the circuit can
+    /// open.
+    pub fn with_min_calls(mut self, min_calls: usize) -> CircuitPolicy {
+        self.min_calls = min_calls.clamp(1, self.window);
+        self
+    }
+
+    /// Sets how long the circuit stays open before the probes.
+    pub fn with_open_for(mut self, open_for: Duration) -> CircuitPolicy {
+        self.open_for = open_for;
+        self
+    }
+
+    /// Sets how many probe messages must succeed to close the circuit.
+    pub fn with_probes(mut self, probes: u32) -> CircuitPolicy {
+        self.probes = probes.max(1);
+        self
+    }
+
+    /// Sets what counts as the outcome of a message, by default
+    /// [`Outcomes::Posted`].
+    pub fn with_outcomes(mut self, outcomes: Outcomes) -> CircuitPolicy {
+        self.outcomes = outcomes;
+        self
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// Wraps `worker`, failing fast when the circuit is open. See the module
+/// documentation.
+pub struct CircuitBreaker<W> {
+    worker: W,
+    circuit: Arc<Circuit>,
+}
+
+impl<W> CircuitBreaker<W> {
+    /// Wraps `worker` with a closed circuit that follows `policy`.
+    pub fn new(worker: W, policy: CircuitPolicy) -> CircuitBreaker<W> {
+        CircuitBreaker {
+            worker,
+            circuit: Circuit::new(policy),
+        }
+    }
+
+    /// Send message `msg` to the worker, or fails with
+    /// [`ErrorKind::CircuitOpen`] when the circuit is open. The message is
+    /// dropped in that case.
+    ///
+    /// A message rejected by a rate limit, see [`crate::rate_limit`], doesn't
+    /// count as a failure.
+    pub async fn post_message<Message>(&self, msg: Message) -> Result<(), Error>
+    where
+        W: Post<Message>,
+    {
+        if !self.circuit.admit() {
+            return Err(self.worker.error(ErrorKind::CircuitOpen, "circuit open"));
+        }
+
+        let result = self.worker.post_message(msg).await;
+        match &result {
+            Err(e) if e.kind() == ErrorKind::RateLimited => self.circuit.release(),
+            Err(_) => self.circuit.record(false),
+            Ok(()) if self.circuit.policy.outcomes == Outcomes::Posted => self.circuit.record(true),
+            Ok(()) => {}
+        }
+        result
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        self.circuit.lock().state
+    }
+
+    /// Returns a reporter of the outcome of the messages, to be given to the
+    /// task when the policy uses [`Outcomes::Reported`].
+    pub fn reporter(&self) -> Reporter {
+        Reporter {
+            circuit: self.circuit.clone(),
+        }
+    }
+
+    /// Returns a receiver of the transitions of the circuit.
+    pub fn subscribe(&self) -> EventReceiver<Transition> {
+        self.circuit.transitions.subscribe().into()
+    }
+
+    /// Let `task` to subscribe to the transitions of the circuit, see
+    /// [`Worker::on_message()`].
+    pub fn on_transition<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<Transition>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Worker::subscriber(task, self.subscribe())
+    }
+
+    /// Returns the wrapped worker.
+    pub fn worker(&self) -> &W {
+        &self.worker
+    }
+
+    /// Unwraps the worker.
+    pub fn into_worker(self) -> W {
+        self.worker
+    }
+}
+
+impl<W, Message> Post<Message> for CircuitBreaker<W>
+where
+    W: Post<Message> + Sync,
+    Message: Send,
+{
+    fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        CircuitBreaker::post_message(self, msg)
+    }
+
+    fn error(&self, kind: ErrorKind, cause: &str) -> Error {
+        self.worker.error(kind, cause)
+    }
+}
+
+/// Reports the outcome of the messages processed by a task, see
+/// [`Outcomes::Reported`]. Cloning the reporter gives another reporter for
+/// the same circuit.
+#[derive(Clone)]
+pub struct Reporter {
+    circuit: Arc<Circuit>,
+}
+
+impl Reporter {
+    /// The message was processed successfully.
+    pub fn success(&self) {
+        self.circuit.record(true);
+    }
+
+    /// The processing of the message failed.
+    pub fn failure(&self) {
+        self.circuit.record(false);
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// The circuit shared by a breaker and its reporters.
+struct Circuit {
+    policy: CircuitPolicy,
+    inner: Mutex<Inner>,
+    transitions: broadcast::Sender<Transition>,
+}
+
+struct Inner {
+    state: CircuitState,
+    // when the current state was entered
+    since: Instant,
+    // the last outcomes while closed, true for the successful ones
+    outcomes: VecDeque<bool>,
+    // the probes let through while half-open, and the successful ones
+    probing: u32,
+    succeeded: u32,
+}
+
+impl Circuit {
+    fn new(policy: CircuitPolicy) -> Arc<Circuit> {
+        let (transitions, _) = broadcast::channel(BUFFER_CAPACITY);
+        Arc::new(Circuit {
+            policy,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                since: Instant::now(),
+                outcomes: VecDeque::new(),
+                probing: 0,
+                succeeded: 0,
+            }),
+            transitions,
+        })
+    }
+
+    // Lets a message through, unless the circuit is open.
+    fn admit(&self) -> bool {
+        let mut inner = self.lock();
+        let open_for = self.policy.open_for;
+
+        if inner.state == CircuitState::Open && inner.since.elapsed() >= open_for {
+            self.switch(&mut inner, CircuitState::HalfOpen);
+        }
+        if inner.state == CircuitState::HalfOpen
+            && inner.probing >= self.policy.probes
+            && inner.since.elapsed() >= open_for
+        {
+            // the pending probes never got an outcome: another round
+            inner.probing = inner.succeeded;
+            inner.since = Instant::now();
+        }
+
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen if inner.probing < self.policy.probes => {
+                inner.probing += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Gives back a probe that got no outcome.
+    fn release(&self) {
+        let mut inner = self.lock();
+        if inner.state == CircuitState::HalfOpen {
+            inner.probing = inner.probing.saturating_sub(1).max(inner.succeeded);
+        }
+    }
+
+    fn record(&self, success: bool) {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Closed => {
+                inner.outcomes.push_back(success);
+                if inner.outcomes.len() > self.policy.window {
+                    inner.outcomes.pop_front();
+                }
+
+                let calls = inner.outcomes.len();
+                let failures = inner.outcomes.iter().filter(|ok| !**ok).count();
+                if calls >= self.policy.min_calls
+                    && failures as f64 >= self.policy.failure_rate * calls as f64
+                    && failures > 0
+                {
+                    self.switch(&mut inner, CircuitState::Open);
+                }
+            }
+            CircuitState::HalfOpen if success => {
+                inner.succeeded += 1;
+                if inner.succeeded >= self.policy.probes {
+                    self.switch(&mut inner, CircuitState::Closed);
+                }
+            }
+            CircuitState::HalfOpen => self.switch(&mut inner, CircuitState::Open),
+            // a late outcome of a message delivered before opening
+            CircuitState::Open => {}
+        }
+    }
+
+    // Moves to the state `to`, telling the subscribers; the lock is held so
+    // that they receive the transitions in order.
+    fn switch(&self, inner: &mut Inner, to: CircuitState) {
+        let from = inner.state;
+        inner.state = to;
+        inner.since = Instant::now();
+        inner.outcomes.clear();
+        inner.probing = 0;
+        inner.succeeded = 0;
+
+        // nobody may be subscribed
+        let _ = self.transitions.send(Transition { from, to });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,193 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Coalescing mailboxes, where a newer message supersedes the pending one
+//! with the same key.
+//!
+//! A worker in [`crate::worker::Coalescing`] mode is spawned with a key
+//! function: a posted message replaces the message with the same key that the
+//! task has not yet received, keeping its place in the mailbox. With a
+//! debounce window, a message is received only once no newer message with
+//! the same key has been posted for the whole window:
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{handle, worker::Coalescing, Task, Worker};
+//! # pub type PanelId = u32;
+//! # pub struct Refresh {
+//! #     panel: PanelId,
+//! # }
+//! # pub struct Refresher {}
+//! # impl Task for Refresher {
+//! #     type Handle = handle::Worker<handle::Coalescing<Refresh, PanelId>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(refresh) = rx.recv().await {
+//! #                 println!("refreshing panel {}", refresh.panel);
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let refresher = Worker::<Coalescing<Refresh, PanelId>>::spawn_with_debounce(
+//!     Refresher {},
+//!     |refresh: &Refresh| refresh.panel,
+//!     Duration::from_millis(100),
+//! );
+//! # }
+//!```
+//!
+//! The mailbox holds at most one message per key, so posting never waits.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use tokio::{sync::Notify, time::Instant};
+
+use crate::runtime::sleep_until;
+
+// The mailbox shared by a coalescing worker and its task.
+pub(crate) struct Mailbox<Message, Key> {
+    key: Box<dyn Fn(&Message) -> Key + Send + Sync>,
+    debounce: Option<Duration>,
+    pending: Mutex<Pending<Message, Key>>,
+    // wakes up the receiver when a message is posted or the worker is gone
+    notify: Notify,
+}
+
+struct Pending<Message, Key> {
+    // the keys of the pending messages, in the order they were first posted
+    order: VecDeque<Key>,
+    // the pending messages, with the instant they can be received
+    messages: HashMap<Key, (Message, Instant)>,
+    // the worker is gone
+    closed: bool,
+    // the receiver is gone
+    abandoned: bool,
+}
+
+impl<Message, Key: Hash + Eq + Clone> Mailbox<Message, Key> {
+    pub(crate) fn new(
+        key: impl Fn(&Message) -> Key + Send + Sync + 'static,
+        debounce: Option<Duration>,
+    ) -> Arc<Mailbox<Message, Key>> {
+        Arc::new(Mailbox {
+            key: Box::new(key),
+            debounce,
+            pending: Mutex::new(Pending {
+                order: VecDeque::new(),
+                messages: HashMap::new(),
+                closed: false,
+                abandoned: false,
+            }),
+            notify: Notify::new(),
+        })
+    }
+
+    // Puts `msg` in the mailbox, superseding the pending message with the
+    // same key. Gives back the message when the receiver is gone.
+    pub(crate) fn post(&self, msg: Message) -> Result<(), Message> {
+        let mut pending = self.lock();
+        if pending.abandoned {
+            return Err(msg);
+        }
+
+        let key = (self.key)(&msg);
+        let ready = Instant::now() + self.debounce.unwrap_or_default();
+        if pending.messages.insert(key.clone(), (msg, ready)).is_none() {
+            pending.order.push_back(key);
+        }
+        drop(pending);
+
+        self.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl<Message, Key> Mailbox<Message, Key> {
+    // Returns the number of pending messages.
+    pub(crate) fn len(&self) -> usize {
+        self.lock().messages.len()
+    }
+
+    pub(crate) fn close(&self) {
+        self.lock().closed = true;
+        self.notify.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pending<Message, Key>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// Receives the messages posted to a coalescing worker, one per key.
+pub struct CoalescingReceiver<Message, Key> {
+    mailbox: Arc<Mailbox<Message, Key>>,
+}
+
+impl<Message, Key> CoalescingReceiver<Message, Key> {
+    pub(crate) fn new(mailbox: Arc<Mailbox<Message, Key>>) -> CoalescingReceiver<Message, Key> {
+        CoalescingReceiver { mailbox }
+    }
+}
+
+impl<Message, Key: Hash + Eq + Clone> CoalescingReceiver<Message, Key> {
+    /// Receives the next message. Returns `None` when the worker has gone and
+    /// no more messages can be received; the pending messages are received
+    /// before, without waiting for their debounce window.
+    ///
+    /// This function is cancel safe.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            let next_ready = {
+                let mut guard = self.mailbox.lock();
+                let pending = &mut *guard;
+                let now = Instant::now();
+
+                let ready = pending.order.iter().position(|key| {
+                    pending.closed || pending.messages.get(key).is_some_and(|(_, at)| *at <= now)
+                });
+                if let Some(key) = ready.and_then(|i| pending.order.remove(i)) {
+                    if let Some((msg, _)) = pending.messages.remove(&key) {
+                        return Some(msg);
+                    }
+                }
+                if pending.closed {
+                    return None;
+                }
+
+                pending.messages.values().map(|(_, at)| *at).min()
+            };
+
+            match next_ready {
+                Some(at) => tokio::select! {
+                    () = sleep_until(at) => {}
+                    () = self.mailbox.notify.notified() => {}
+                },
+                None => self.mailbox.notify.notified().await,
+            }
+        }
+    }
+}
+
+impl<Message, Key> Drop for CoalescingReceiver<Message, Key> {
+    fn drop(&mut self) {
+        let mut pending = self.mailbox.lock();
+        pending.abandoned = true;
+        pending.order.clear();
+        pending.messages.clear();
+    }
+}
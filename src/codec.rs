@@ -0,0 +1,119 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Serialization of the messages that leave the process. Every message is
+//! written as a frame: its length, as a big endian `u32`, followed by the
+//! message serialized with the chosen [`Codec`].
+
+#[cfg(feature = "remote")]
+use std::future::Future;
+use std::io;
+
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "remote")]
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::Error;
+
+// frames longer than this are refused, they are probably garbage
+const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// The format used to serialize messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// Compact binary format, see [bincode](https://docs.rs/bincode).
+    #[default]
+    Bincode,
+    /// Human readable format, useful when debugging.
+    Json,
+}
+
+impl Codec {
+    pub(crate) fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        match self {
+            Codec::Bincode => bincode::serialize(value).map_err(|e| Error::from(&e)),
+            Codec::Json => serde_json::to_vec(value).map_err(|e| Error::from(&e)),
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        match self {
+            Codec::Bincode => bincode::deserialize(bytes).map_err(|e| Error::from(&e)),
+            Codec::Json => serde_json::from_slice(bytes).map_err(|e| Error::from(&e)),
+        }
+    }
+
+    // Encodes `value` and writes it as a frame. The value is encoded before
+    // the returned future is awaited, so it doesn't need to be kept alive.
+    #[cfg(feature = "remote")]
+    pub(crate) fn write<'w, T, W>(
+        &self,
+        writer: &'w mut W,
+        value: &T,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'w
+    where
+        T: Serialize,
+        W: AsyncWrite + Unpin + Send,
+    {
+        let bytes = self.encode(value);
+        async move {
+            write_frame(writer, &bytes?)
+                .await
+                .map_err(|e| Error::from(&e))
+        }
+    }
+
+    // Reads a frame and decodes it, returns `None` when the stream is closed.
+    #[cfg(feature = "remote")]
+    pub(crate) async fn read<T, R>(&self, reader: &mut R) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+        R: AsyncRead + Unpin,
+    {
+        match read_frame(reader).await.map_err(|e| Error::from(&e))? {
+            Some(bytes) => self.decode(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+pub(crate) async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    bytes: &[u8],
+) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .ok()
+        .filter(|len| *len as usize <= MAX_FRAME_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "frame too long"))?;
+
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(bytes).await?;
+    writer.flush().await
+}
+
+// Returns `None` when the stream is closed before a new frame.
+#[cfg(feature = "remote")]
+pub(crate) async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+    }
+
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes).await?;
+    Ok(Some(bytes))
+}
@@ -0,0 +1,102 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Out-of-band control commands that a worker, whatever its mode is, can send
+//! to its task besides terminating it.
+//!
+//! The worker sends them with typed functions like [`Worker::pause()`] and the
+//! task receives them, usually in a `tokio::select!`, with
+//! [`handle::Worker::control()`]:
+//!
+//!```rust
+//! # use std::future::Future;
+//! # use opifex::{control::Control, handle, Task};
+//! # struct Reload;
+//! # struct Writer;
+//! # impl Task for Writer {
+//! #     type Handle = handle::Worker<handle::OneWay<String>, Reload>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         let flush = || async {};
+//! #         let apply = |_: Reload| {};
+//! #         let process = |msg: String| println!("{msg}");
+//! #         async move {
+//! #             let mut paused = false;
+//! loop {
+//!     tokio::select! {
+//!         Some(cmd) = hnd.control() => match cmd {
+//!             Control::Pause => paused = true,
+//!             Control::Resume => paused = false,
+//!             Control::Flush => flush().await,
+//!             Control::Custom(reload) => apply(reload),
+//!         },
+//!         Some(msg) = rx.recv(), if !paused => process(msg),
+//!         () = hnd.terminated() => break,
+//!     }
+//! }
+//! #         }
+//! #     }
+//! # }
+//!```
+//!
+//! The type of custom commands is the second type parameter of [`Worker`] and
+//! [`handle::Worker`], that is `()` by default.
+//!
+//! The commands wait in a channel of [`BUFFER_CAPACITY`] commands, like the
+//! messages in a mailbox: when the task never calls `control()` the channel
+//! fills up, and then sending a command waits until the task receives one or
+//! finishes. A task that doesn't care about some commands should receive and
+//! ignore them.
+//!
+//! [`Worker`]: crate::worker::Worker
+//! [`Worker::pause()`]: crate::worker::Worker::pause
+//! [`handle::Worker`]: crate::handle::Worker
+//! [`handle::Worker::control()`]: crate::handle::Worker::control
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{channel, Sender};
+use tokio_util::sync::CancellationToken;
+
+use crate::{handle, worker::Shared, BUFFER_CAPACITY};
+
+/// A control command sent by a worker to its task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Control<Custom = ()> {
+    /// The task should stop processing messages until resumed.
+    Pause,
+    /// The task should resume processing messages.
+    Resume,
+    /// The task should flush any buffered work.
+    Flush,
+    /// An application defined command.
+    Custom(Custom),
+}
+
+// Creates the control channel between a worker and the handle of its task,
+// that will be terminated by `token`.
+pub(crate) fn link<Ctrl>(
+    token: CancellationToken,
+    shared: Arc<Shared>,
+) -> (Sender<Control<Ctrl>>, handle::Link<Ctrl>) {
+    let (control_to_tsk, control_from_wk) = channel::<Control<Ctrl>>(BUFFER_CAPACITY);
+
+    (
+        control_to_tsk,
+        handle::Link {
+            token,
+            control_from_wk,
+            shared,
+        },
+    )
+}
+
+// Creates the link of a handle that has no worker sending control commands.
+pub(crate) fn detached<Ctrl>(token: CancellationToken, shared: Arc<Shared>) -> handle::Link<Ctrl> {
+    link(token, shared).1
+}
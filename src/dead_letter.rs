@@ -0,0 +1,252 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Dead letters: the messages that could not be delivered.
+//!
+//! A message posted to a terminated task, sent by a task when nobody is
+//! subscribed, or that can't be encoded for a remote or a process worker, is
+//! collected by a [`DeadLetterQueue`] if one is set for the worker with
+//! [`Worker::set_dead_letters()`], or globally with [`set_global()`].
+//! Otherwise it is dropped, as usual.
+//!
+//! The queue keeps the last letters, to be drained later, and sends every new
+//! letter to its subscribers:
+//!
+//!```rust
+//! # use std::future::Future;
+//! # use opifex::{dead_letter::DeadLetterQueue, handle, worker::OneWay, Error, Task, Worker};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # // Finishes as soon as it starts.
+//! # pub struct Quit {}
+//! # impl Task for Quit {
+//! #     type Handle = handle::Worker<handle::OneWay<Sum>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (rx, _hnd) = wk_hnd.receiver();
+//! #         async move { drop(rx) }
+//! #     }
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::OneWay<Sum>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(Sum { a, b }) = rx.recv().await {
+//! #                 println!("{}", a + b);
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! # let worker = Worker::<OneWay<Sum>>::spawn(Quit {});
+//! # let other_worker = Worker::<OneWay<Sum>>::spawn(Adder {});
+//! let dead_letters = DeadLetterQueue::new(100);
+//! worker.set_dead_letters(dead_letters.clone());
+//! # worker.finished().await;
+//! # let _ = worker.post_message(Sum { a: 24, b: 28 }).await;
+//!
+//! for letter in dead_letters.drain() {
+//!     if let Some(msg) = letter.take_message::<Sum>() {
+//!         other_worker.post_message(msg).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//!```
+//!
+//! [`Worker::set_dead_letters()`]: crate::Worker::set_dead_letters
+
+use std::{
+    any::{type_name, Any},
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use tokio::sync::broadcast;
+
+use crate::{
+    worker::{Shared, WorkerId},
+    BUFFER_CAPACITY,
+};
+
+// the queue used when a worker has none
+static GLOBAL: RwLock<Option<DeadLetterQueue>> = RwLock::new(None);
+
+/// Sets the queue collecting the dead letters of the workers that don't have
+/// their own.
+pub fn set_global(queue: DeadLetterQueue) {
+    *GLOBAL.write().unwrap_or_else(|e| e.into_inner()) = Some(queue);
+}
+
+/// Removes the global queue: the dead letters of the workers that don't have
+/// their own queue are dropped.
+pub fn clear_global() {
+    *GLOBAL.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Why a message could not be delivered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The receiving task was terminated.
+    Terminated,
+    /// A message sent by a task had no subscribers.
+    NoSubscribers,
+    /// A message for a remote or a process worker could not be encoded.
+    Unencodable,
+}
+
+/// A message that could not be delivered.
+pub struct DeadLetter {
+    /// The worker that sent, or was posted, the message.
+    pub source: WorkerId,
+    /// Why the message could not be delivered.
+    pub reason: Reason,
+    // the name of the message type
+    type_name: &'static str,
+    // taken by the first one that wants to reprocess it
+    message: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl DeadLetter {
+    /// Returns the name of the message type.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Takes the message, if it is of type `Message` and it has not been
+    /// taken yet.
+    pub fn take_message<Message: 'static>(&self) -> Option<Message> {
+        let mut message = self.message.lock().unwrap_or_else(|e| e.into_inner());
+        match message.take()?.downcast::<Message>() {
+            Ok(msg) => Some(*msg),
+            Err(other) => {
+                *message = Some(other);
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Debug for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeadLetter")
+            .field("source", &self.source)
+            .field("reason", &self.reason)
+            .field("type_name", &self.type_name)
+            .finish_non_exhaustive()
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// Collects the dead letters of one or more workers. Cloning the queue gives
+/// another reference to the same queue.
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    // the maximum number of letters kept
+    capacity: usize,
+    letters: Mutex<VecDeque<Arc<DeadLetter>>>,
+    // used by interested tasks to subscribe to the new letters
+    broadcast: broadcast::Sender<Arc<DeadLetter>>,
+}
+
+impl DeadLetterQueue {
+    /// Creates a queue that keeps the last `capacity` letters, the older ones
+    /// are discarded.
+    pub fn new(capacity: usize) -> DeadLetterQueue {
+        let (broadcast, _) = broadcast::channel(BUFFER_CAPACITY);
+
+        DeadLetterQueue {
+            inner: Arc::new(Inner {
+                capacity,
+                letters: Mutex::new(VecDeque::new()),
+                broadcast,
+            }),
+        }
+    }
+
+    /// Removes and returns the letters kept, the oldest first.
+    pub fn drain(&self) -> Vec<Arc<DeadLetter>> {
+        self.letters().drain(..).collect()
+    }
+
+    /// Returns the number of letters kept.
+    pub fn len(&self) -> usize {
+        self.letters().len()
+    }
+
+    /// Returns true when no letters are kept.
+    pub fn is_empty(&self) -> bool {
+        self.letters().is_empty()
+    }
+
+    /// Returns a receiver of the new letters. A letter drained from the queue
+    /// and received by a subscriber is the same letter, its message can be
+    /// taken once.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<DeadLetter>> {
+        self.inner.broadcast.subscribe()
+    }
+
+    fn push(&self, letter: DeadLetter) {
+        let letter = Arc::new(letter);
+
+        let mut letters = self.letters();
+        if self.inner.capacity > 0 {
+            if letters.len() == self.inner.capacity {
+                letters.pop_front();
+            }
+            letters.push_back(letter.clone());
+        }
+        drop(letters);
+
+        let _ = self.inner.broadcast.send(letter);
+    }
+
+    fn letters(&self) -> std::sync::MutexGuard<'_, VecDeque<Arc<DeadLetter>>> {
+        self.inner.letters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Hands `msg` to the dead letter queue of the worker sharing `shared`, or to
+// the global one. Gives back the message when there is no queue.
+pub(crate) fn post<Message: Send + 'static>(
+    shared: &Shared,
+    msg: Message,
+    reason: Reason,
+) -> Result<(), Message> {
+    let queue = shared
+        .dead_letters
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .or_else(|| GLOBAL.read().unwrap_or_else(|e| e.into_inner()).clone());
+
+    match queue {
+        Some(queue) => {
+            queue.push(DeadLetter {
+                source: shared.id,
+                reason,
+                type_name: type_name::<Message>(),
+                message: Mutex::new(Some(Box::new(msg))),
+            });
+            Ok(())
+        }
+        None => Err(msg),
+    }
+}
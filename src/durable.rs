@@ -0,0 +1,401 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Durable mailboxes, that survive a restart of the process. Available with
+//! the `durable` feature.
+//!
+//! A worker in [`crate::worker::Durable`] mode writes every posted message
+//! in a [`Journal`], an append-only file, before sending it to its task. The
+//! task acknowledges the messages it has processed with
+//! [`DurableReceiver::ack()`], and the messages not yet acknowledged are
+//! delivered again when a worker is spawned on the same journal, giving
+//! at-least-once delivery:
+//!
+//!```rust
+//! # use std::future::Future;
+//! # use opifex::{durable::{FsyncPolicy, Journal}, handle, worker::Durable, Error, Task, Worker};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Debug, Serialize, Deserialize)]
+//! # pub struct Order {
+//! #     id: u64,
+//! # }
+//! # async fn process(order: &Order) {
+//! #     println!("{order:?}");
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! # let dir = std::env::temp_dir();
+//! # let order = Order { id: 42 };
+//! let journal = Journal::new(dir.join("orders.journal")).with_fsync(FsyncPolicy::Every(10));
+//! let worker = Worker::<Durable<Order>>::spawn(OrderTask {}, journal)?;
+//! worker.post_message(order).await?;
+//! # worker.terminate();
+//! # let _ = std::fs::remove_file(dir.join("orders.journal"));
+//! # Ok(())
+//! # }
+//! # pub struct OrderTask {}
+//! # impl Task for OrderTask {
+//! #     type Handle = handle::Worker<handle::Durable<Order>>;
+//! #     type Output = Result<(), Error>;
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = Self::Output> + Send + 'static {
+//!
+//! // in the task
+//! let (mut rx, hnd) = wk_hnd.receiver();
+//! # async move {
+//! # loop {
+//! # tokio::select! {
+//! Some(delivery) = rx.recv() => {
+//!     process(&delivery.message).await;
+//!     rx.ack(delivery.seq).await?;
+//! }
+//! # () = hnd.terminated() => break Ok(()),
+//! # }
+//! # }
+//! # }
+//! #     }
+//! # }
+//!```
+//!
+//! A journal must be used by one worker at a time. A record torn by a crash
+//! at the end of the journal is discarded when the journal is opened, while
+//! a complete record that can't be decoded, like one written before a change
+//! of the message type, fails the spawn and the journal is left untouched.
+
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    sync::{
+        mpsc::{Receiver, Sender},
+        Mutex,
+    },
+};
+
+use crate::{
+    codec::{write_frame, Codec},
+    dead_letter::{self, Reason},
+    worker::Shared,
+    Error, ErrorKind,
+};
+
+// when every message has been acknowledged and the journal is longer than
+// this, the journal is emptied.
+const COMPACT_LEN: u64 = 1024 * 1024;
+
+/// When the journal is flushed to the disk with `fsync`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// After every message and acknowledgement: nothing is lost on a crash
+    /// of the machine, but posting is slower.
+    #[default]
+    Always,
+    /// After every `n` messages or acknowledgements.
+    Every(usize),
+    /// Never, leaving it to the operating system: nothing is lost on a crash
+    /// of the process, but something can be lost on a crash of the machine.
+    Never,
+}
+
+/// The file where a durable worker stores its messages.
+#[derive(Clone, Debug)]
+pub struct Journal {
+    path: PathBuf,
+    codec: Codec,
+    fsync: FsyncPolicy,
+}
+
+impl Journal {
+    /// A journal stored at `path`, created if it doesn't exist.
+    pub fn new(path: impl Into<PathBuf>) -> Journal {
+        Journal {
+            path: path.into(),
+            codec: Codec::default(),
+            fsync: FsyncPolicy::default(),
+        }
+    }
+
+    /// Sets the format used to store the messages, by default
+    /// [`Codec::Bincode`]. It can't be changed on an existing journal.
+    pub fn with_codec(mut self, codec: Codec) -> Journal {
+        self.codec = codec;
+        self
+    }
+
+    /// Sets when the journal is flushed to the disk, by default
+    /// [`FsyncPolicy::Always`].
+    pub fn with_fsync(mut self, fsync: FsyncPolicy) -> Journal {
+        self.fsync = fsync;
+        self
+    }
+}
+
+/// A message received by the task of a durable worker.
+#[derive(Debug)]
+pub struct Delivery<Message> {
+    /// The sequence number of the message, used to acknowledge it.
+    pub seq: u64,
+    /// The message.
+    pub message: Message,
+    /// True when the message was posted before a restart and it had not been
+    /// acknowledged, so it could have already been processed.
+    pub redelivered: bool,
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// the records of the journal, each one written as a frame
+#[derive(Serialize, Deserialize)]
+enum Record<Message> {
+    Message(u64, Message),
+    Ack(u64),
+}
+
+// The journal of a worker, shared by the worker and its task.
+pub(crate) struct Log {
+    codec: Codec,
+    fsync: FsyncPolicy,
+    state: Mutex<LogState>,
+    // held by a poster from the append to the send, so that the task
+    // receives the messages in the journal order. The state is not held
+    // while sending, or a full mailbox would block the acks of the task.
+    sending: Mutex<()>,
+}
+
+struct LogState {
+    file: File,
+    // length of the journal
+    len: u64,
+    // records written since the last fsync
+    unsynced: usize,
+    next_seq: u64,
+    unacked: HashSet<u64>,
+}
+
+impl Log {
+    // Opens the journal, compacting it, and returns the messages to deliver
+    // again. A record torn by a crash at the end of the journal is discarded,
+    // a complete one that doesn't decode fails the open before compacting.
+    pub(crate) fn open<Message: DeserializeOwned>(
+        journal: &Journal,
+    ) -> Result<(Log, Vec<Delivery<Message>>), Error> {
+        let bytes = match fs::read(&journal.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(Error::from(&e)),
+        };
+
+        // the frames of the messages not acknowledged, by sequence number
+        let mut frames = BTreeMap::new();
+        let mut next_seq = 0;
+        let mut rest = bytes.as_slice();
+        while let Some((frame, tail)) = split_frame(rest) {
+            let offset = bytes.len() - rest.len();
+            match journal.codec.decode::<Record<Message>>(frame) {
+                Ok(Record::Message(seq, message)) => {
+                    frames.insert(seq, (frame, message));
+                    next_seq = next_seq.max(seq + 1);
+                }
+                Ok(Record::Ack(seq)) => {
+                    frames.remove(&seq);
+                }
+                Err(e) => {
+                    let path = journal.path.display();
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("journal {path}: bad record at byte {offset}: {e}"),
+                    ));
+                }
+            }
+            rest = tail;
+        }
+
+        // the compacted journal is written aside and then moved in place
+        let mut compacted = OsString::from(&journal.path);
+        compacted.push(".compacting");
+        let mut file = fs::File::create(&compacted).map_err(|e| Error::from(&e))?;
+        let mut len = 0;
+        for (frame, _) in frames.values() {
+            file.write_all(&(frame.len() as u32).to_be_bytes())
+                .and_then(|()| file.write_all(frame))
+                .map_err(|e| Error::from(&e))?;
+            len += 4 + frame.len() as u64;
+        }
+        file.sync_all().map_err(|e| Error::from(&e))?;
+        fs::rename(&compacted, &journal.path).map_err(|e| Error::from(&e))?;
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&journal.path)
+            .map_err(|e| Error::from(&e))?;
+
+        let log = Log {
+            codec: journal.codec,
+            fsync: journal.fsync,
+            state: Mutex::new(LogState {
+                file: File::from_std(file),
+                len,
+                unsynced: 0,
+                next_seq,
+                unacked: frames.keys().copied().collect(),
+            }),
+            sending: Mutex::new(()),
+        };
+        let replay = frames
+            .into_iter()
+            .map(|(seq, (_, message))| Delivery {
+                seq,
+                message,
+                redelivered: true,
+            })
+            .collect();
+
+        Ok((log, replay))
+    }
+
+    // Writes `msg` in the journal and then sends it to the task of the
+    // worker sharing `shared`. When the task has terminated the message goes
+    // to the dead letters, if any.
+    pub(crate) async fn post<Message: Serialize + Send + 'static>(
+        &self,
+        shared: &Shared,
+        sender: &Sender<Delivery<Message>>,
+        msg: Message,
+    ) -> Result<(), Error> {
+        let _sending = self.sending.lock().await;
+        if sender.is_closed() {
+            return Err(terminated(shared, msg));
+        }
+
+        let seq = {
+            let mut state = self.state.lock().await;
+            let seq = state.next_seq;
+            let bytes = self
+                .codec
+                .encode(&Record::Message(seq, &msg))
+                .map_err(|e| shared.error(e.kind(), e))?;
+            state
+                .append(&bytes, self.fsync)
+                .await
+                .map_err(|e| shared.error(e.kind(), e))?;
+            state.next_seq += 1;
+            state.unacked.insert(seq);
+            seq
+        };
+
+        sender
+            .send(Delivery {
+                seq,
+                message: msg,
+                redelivered: false,
+            })
+            .await
+            .map_err(|e| terminated(shared, e.0.message))
+    }
+
+    async fn ack(&self, seq: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        if !state.unacked.remove(&seq) {
+            return Ok(());
+        }
+
+        let bytes = self.codec.encode(&Record::<()>::Ack(seq))?;
+        state.append(&bytes, self.fsync).await?;
+
+        if state.unacked.is_empty() && state.len > COMPACT_LEN {
+            state.file.set_len(0).await.map_err(|e| Error::from(&e))?;
+            state.file.sync_all().await.map_err(|e| Error::from(&e))?;
+            state.len = 0;
+            state.unsynced = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl LogState {
+    async fn append(&mut self, bytes: &[u8], fsync: FsyncPolicy) -> Result<(), Error> {
+        write_frame(&mut self.file, bytes)
+            .await
+            .map_err(|e| Error::from(&e))?;
+        self.len += 4 + bytes.len() as u64;
+        self.unsynced += 1;
+
+        let sync = match fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Every(n) => self.unsynced >= n,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            self.file.sync_data().await.map_err(|e| Error::from(&e))?;
+            self.unsynced = 0;
+        }
+
+        Ok(())
+    }
+}
+
+// Hands `msg`, that the task can't receive, to the dead letters.
+fn terminated<Message: Send + 'static>(shared: &Shared, msg: Message) -> Error {
+    let _ = dead_letter::post(shared, msg, Reason::Terminated);
+    shared.error(ErrorKind::Terminated, "channel closed")
+}
+
+// Splits the first frame from `bytes`, returns `None` when there is no
+// complete frame.
+fn split_frame(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = bytes.split_first_chunk::<4>()?;
+    let len = u32::from_be_bytes(*len) as usize;
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// The receiver of the messages sent to the task of a durable worker: the
+/// messages not acknowledged before the last restart come first.
+pub struct DurableReceiver<Message> {
+    replay: VecDeque<Delivery<Message>>,
+    receiver: Receiver<Delivery<Message>>,
+    log: std::sync::Arc<Log>,
+}
+
+impl<Message> DurableReceiver<Message> {
+    pub(crate) fn new(
+        replay: Vec<Delivery<Message>>,
+        receiver: Receiver<Delivery<Message>>,
+        log: std::sync::Arc<Log>,
+    ) -> DurableReceiver<Message> {
+        DurableReceiver {
+            replay: replay.into(),
+            receiver,
+            log,
+        }
+    }
+
+    /// Receives the next message. Returns `None` when the worker has gone and
+    /// no more messages can be received.
+    ///
+    /// This function is cancel safe.
+    pub async fn recv(&mut self) -> Option<Delivery<Message>> {
+        match self.replay.pop_front() {
+            Some(delivery) => Some(delivery),
+            None => self.receiver.recv().await,
+        }
+    }
+
+    /// Acknowledges the message with sequence number `seq`, so that it will
+    /// not be delivered again. Acknowledging a message twice has no effect.
+    pub async fn ack(&self, seq: u64) -> Result<(), Error> {
+        self.log.ack(seq).await
+    }
+}
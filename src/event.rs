@@ -0,0 +1,247 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! The events sent by two-way tasks to their subscribers.
+//!
+//! Usually a subscriber receives only the events sent after it subscribed. A
+//! two-way worker spawned with [`Worker::spawn_with_replay()`] keeps the
+//! recent events, as told by its [`Replay`], and delivers them to every new
+//! subscriber before the live ones:
+//!
+//!```rust
+//! # use opifex::{event::Replay, worker::TwoWay, Worker};
+//! # use std::future::Future;
+//! # use opifex::{handle, Task};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(Sum { a, b }) = rx.recv().await {
+//! #                 let _ = hnd.post_message(Result { sum: a + b }).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(result) = rx.recv().await {
+//! #                 println!("{result:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::result::Result<(), opifex::Error> {
+//! let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn_with_replay(Adder {}, Replay::Last(10));
+//! adder_worker.post_message(Sum { a: 24, b: 28 }).await?;
+//!
+//! // subscribed later, but it receives the result too
+//! let response_worker = adder_worker.on_message(Response {});
+//! # Ok(())
+//! # }
+//!```
+//!
+//! [`Worker::spawn_with_replay()`]: crate::Worker::spawn_with_replay
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::broadcast::{
+        self,
+        error::{RecvError, SendError, TryRecvError},
+    },
+    time::Instant,
+};
+
+/// Which of the past events are delivered to a new subscriber.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Replay {
+    /// The last `n` events.
+    Last(usize),
+    /// The events sent within the given time.
+    Within(Duration),
+}
+
+/// The receiver of the events sent by a task: first the replayed ones, if
+/// any, and then the live ones. It behaves like a
+/// [`tokio::sync::broadcast::Receiver`].
+pub struct EventReceiver<Event> {
+    // the replayed events not yet received
+    pub(crate) backlog: VecDeque<Event>,
+    pub(crate) receiver: broadcast::Receiver<Event>,
+}
+
+impl<Event: Clone> EventReceiver<Event> {
+    /// Receives the next event, see [`broadcast::Receiver::recv()`].
+    ///
+    /// This function is cancel safe.
+    pub async fn recv(&mut self) -> Result<Event, RecvError> {
+        match self.backlog.pop_front() {
+            Some(event) => Ok(event),
+            None => self.receiver.recv().await,
+        }
+    }
+
+    /// Receives the next event if there is one, see
+    /// [`broadcast::Receiver::try_recv()`].
+    pub fn try_recv(&mut self) -> Result<Event, TryRecvError> {
+        match self.backlog.pop_front() {
+            Some(event) => Ok(event),
+            None => self.receiver.try_recv(),
+        }
+    }
+}
+
+impl<Event> From<broadcast::Receiver<Event>> for EventReceiver<Event> {
+    fn from(receiver: broadcast::Receiver<Event>) -> EventReceiver<Event> {
+        EventReceiver {
+            backlog: VecDeque::new(),
+            receiver,
+        }
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// Sends the events of a task to its subscribers, keeping the recent ones when
+// there is a replay.
+pub(crate) struct Broadcaster<Event> {
+    sender: broadcast::Sender<Event>,
+    replay: Option<Arc<Buffer<Event>>>,
+}
+
+struct Buffer<Event> {
+    replay: Replay,
+    // the recent events, with the instant they were sent
+    events: Mutex<VecDeque<(Instant, Event)>>,
+    // given by the constructor, the only place where Event is Clone
+    clone: fn(&Event) -> Event,
+}
+
+impl<Event> Clone for Broadcaster<Event> {
+    fn clone(&self) -> Self {
+        Broadcaster {
+            sender: self.sender.clone(),
+            replay: self.replay.clone(),
+        }
+    }
+}
+
+impl<Event> Broadcaster<Event> {
+    pub(crate) fn new(capacity: usize) -> Broadcaster<Event>
+    where
+        Event: Clone,
+    {
+        let (sender, _) = broadcast::channel(capacity);
+        Broadcaster {
+            sender,
+            replay: None,
+        }
+    }
+
+    pub(crate) fn with_replay(capacity: usize, replay: Replay) -> Broadcaster<Event>
+    where
+        Event: Clone,
+    {
+        let (sender, _) = broadcast::channel(capacity);
+        Broadcaster {
+            sender,
+            replay: Some(Arc::new(Buffer {
+                replay,
+                events: Mutex::new(VecDeque::new()),
+                clone: Event::clone,
+            })),
+        }
+    }
+
+    // Sends `event` to the subscribers, see broadcast::Sender::send. With a
+    // replay the event is kept for the next subscribers: it is not an error
+    // when there are none yet.
+    pub(crate) fn send(&self, event: Event) -> Result<usize, SendError<Event>> {
+        let Some(buffer) = &self.replay else {
+            return self.sender.send(event);
+        };
+
+        // the lock is held while sending, so that a new subscriber receives
+        // every event once: either replayed or live.
+        let mut events = buffer.lock();
+        events.push_back((Instant::now(), (buffer.clone)(&event)));
+        buffer.trim(&mut events);
+        Ok(self.sender.send(event).unwrap_or(0))
+    }
+
+    // Counts the subscribers, while the task can send events.
+    pub(crate) fn subscribers(&self) -> Box<dyn Fn() -> Option<usize> + Send + Sync>
+    where
+        Event: Send + 'static,
+    {
+        let sender = self.sender.downgrade();
+        Box::new(move || sender.upgrade().map(|sender| sender.receiver_count()))
+    }
+
+    pub(crate) fn subscribe(&self) -> EventReceiver<Event> {
+        let Some(buffer) = &self.replay else {
+            return self.sender.subscribe().into();
+        };
+
+        let mut events = buffer.lock();
+        buffer.trim(&mut events);
+        EventReceiver {
+            backlog: events
+                .iter()
+                .map(|(_, event)| (buffer.clone)(event))
+                .collect(),
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+impl<Event> Buffer<Event> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<(Instant, Event)>> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Discards the events that must not be replayed anymore.
+    fn trim(&self, events: &mut VecDeque<(Instant, Event)>) {
+        match self.replay {
+            Replay::Last(n) => {
+                let excess = events.len().saturating_sub(n);
+                events.drain(..excess);
+            }
+            Replay::Within(window) => {
+                let now = Instant::now();
+                while events
+                    .front()
+                    .is_some_and(|(at, _)| now.duration_since(*at) > window)
+                {
+                    events.pop_front();
+                }
+            }
+        }
+    }
+}
@@ -5,12 +5,28 @@ Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
 Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
 */
 
+use std::{fmt, sync::Arc, time::Duration};
+
 use tokio::sync::{
-    broadcast::{self, error::SendError},
-    mpsc::Receiver,
+    broadcast::error::SendError,
+    mpsc::{self, Receiver},
+    watch, Mutex,
 };
 use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
 
+use crate::{
+    batch::BatchReceiver,
+    coalesce::CoalescingReceiver,
+    control::Control,
+    dead_letter::{self, Reason},
+    event::{Broadcaster, EventReceiver},
+    mailbox::Mailbox,
+    priority::PriorityReceiver,
+    streaming::Request,
+    watchdog::MessageGuard,
+    worker::{Shared, WorkerId},
+};
+
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
 mod private {
@@ -21,9 +37,31 @@ pub trait Handle: private::Sealed {}
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
+// Where the messages sent by a task are delivered: usually to all subscribers
+// through a broadcast channel, or to the next stage of a pipeline.
+pub(crate) enum Outlet<OutMessage> {
+    Broadcast(Broadcaster<OutMessage>),
+    Pipe(mpsc::Sender<OutMessage>),
+}
+
+// What binds a handle to its worker, whatever the mode is.
+pub(crate) struct Link<Ctrl> {
+    // used to terminate both the task and the worker
+    pub(crate) token: CancellationToken,
+    // used to receive the control commands sent from the worker
+    pub(crate) control_from_wk: Receiver<Control<Ctrl>>,
+    // shared with the worker
+    pub(crate) shared: Arc<Shared>,
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
 // here I use a mod just to keep clean and ordered the file :)
 mod modes {
-    use tokio::sync::{broadcast, mpsc::Receiver};
+    use tokio::sync::mpsc::Receiver;
+
+    use super::Outlet;
+    use crate::mailbox::{Mailbox, Tokio};
 
     /// Isolated handle mode: in this mode worker and task are isolated, so no
     /// messages can be exchanged.
@@ -31,9 +69,23 @@ mod modes {
 
     /// This mode is used when there is the needs to send messages, of type
     /// Message, from the worker towards the controlled task.
-    pub struct OneWay<Message> {
+    pub struct OneWay<Message, Mb: Mailbox<Message> = Tokio> {
+        // used to receive all messages sent from the worker
+        pub(super) receiver_from_wk: Mb::Receiver,
+    }
+
+    /// Like [`OneWay`] but the messages are received from a priority mailbox.
+    pub struct Prioritized<Message> {
+        // used to receive all messages sent from the worker
+        pub(super) receiver_from_wk: crate::priority::PriorityReceiver<Message>,
+    }
+
+    /// Like [`OneWay`] but the messages are received from a durable mailbox,
+    /// and must be acknowledged once processed.
+    #[cfg(feature = "durable")]
+    pub struct Durable<Message> {
         // used to receive all messages sent from the worker
-        pub(super) receiver_from_wk: Receiver<Message>,
+        pub(super) receiver_from_wk: crate::durable::DurableReceiver<Message>,
     }
 
     /// This mode is used when there is the needs:
@@ -41,25 +93,53 @@ mod modes {
     ///   controlled task,
     /// * to send messages, of type OutMessage, from the task towards the
     ///   controlling worker.
-    pub struct TwoWay<InMessage, OutMessage> {
+    pub struct TwoWay<InMessage, OutMessage, Mb: Mailbox<InMessage> = Tokio> {
         // used to receive all messages sent from the worker
-        pub(super) receiver_from_wk: Receiver<InMessage>,
+        pub(super) receiver_from_wk: Mb::Receiver,
         // used to send messages to the worker.
-        pub(crate) broadcast_from_task: broadcast::Sender<OutMessage>,
+        pub(crate) outlet: Outlet<OutMessage>,
     }
 
     /// This mode is used when there is the needs to send messages, of type
     /// OutMessage, from the task towards the controlling worker.
     pub struct OneWayBack<OutMessage> {
         // used to send messages to the worker.
-        pub(crate) broadcast_from_task: broadcast::Sender<OutMessage>,
+        pub(crate) outlet: Outlet<OutMessage>,
+    }
+
+    /// Like [`OneWay`] but the messages are received from a coalescing
+    /// mailbox, see [`crate::coalesce`].
+    pub struct Coalescing<Message, Key> {
+        // used to receive all messages sent from the worker
+        pub(super) receiver_from_wk: crate::coalesce::CoalescingReceiver<Message, Key>,
+    }
+
+    /// This mode is used when the task answers every request, of type Req,
+    /// with a stream of responses, of type Resp.
+    pub struct Streaming<Req, Resp> {
+        // used to receive all requests sent from the worker
+        pub(super) receiver_from_wk: Receiver<crate::streaming::Request<Req, Resp>>,
+    }
+
+    /// This mode is used by tasks that publish a state, of type S, towards
+    /// the controlling worker and the subscribers.
+    pub struct State<S> {
+        // used to publish the state
+        pub(super) publisher: tokio::sync::watch::Sender<S>,
+    }
+
+    /// Mode used to inject in subscriber tasks the receiver of the states
+    /// published by a task.
+    pub struct OnChange<S> {
+        // used by the state subscriber to receive the states.
+        pub(super) receiver_from_task: tokio::sync::watch::Receiver<S>,
     }
 
     /// Mode used to inject in subscriber tasks the receiver handle of the
     /// channel in which events are sent.
     pub struct OnEvent<Event> {
         // used by the event subscriber to receive the events.
-        pub(crate) receiver_from_task: broadcast::Receiver<Event>,
+        pub(crate) receiver_from_task: crate::event::EventReceiver<Event>,
     }
 }
 
@@ -67,168 +147,481 @@ pub use modes::*;
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
+/// The handle injected by a [`crate::Worker`] in its spawned task. Depending
+/// on the `Mode` it lets the task receive messages from the worker and/or send
+/// messages back to the subscribers.
 ///
-pub struct Worker<Mode> {
-    termination_token: CancellationToken,
+/// Whatever the mode is, the task can receive the [`Control`] commands, with
+/// custom commands of type `Ctrl`, sent by its worker.
+pub struct Worker<Mode, Ctrl = ()> {
+    pub(crate) termination_token: CancellationToken,
+    // used to receive the control commands sent from the worker
+    control_from_wk: Mutex<Receiver<Control<Ctrl>>>,
+    // shared with the worker
+    pub(crate) shared: Arc<Shared>,
     mode: Mode,
 }
 
-impl<Mode> Worker<Mode> {
+impl<Mode, Ctrl> Worker<Mode, Ctrl> {
+    fn new(link: Link<Ctrl>, mode: Mode) -> Worker<Mode, Ctrl> {
+        Self {
+            termination_token: link.token,
+            control_from_wk: Mutex::new(link.control_from_wk),
+            shared: link.shared,
+            mode,
+        }
+    }
+
+    fn split(self) -> (Link<Ctrl>, Mode) {
+        let Worker {
+            termination_token,
+            control_from_wk,
+            shared,
+            mode,
+        } = self;
+
+        (
+            Link {
+                token: termination_token,
+                control_from_wk: control_from_wk.into_inner(),
+                shared,
+            },
+            mode,
+        )
+    }
+
+    /// Returns the identifier of the worker.
+    pub fn id(&self) -> WorkerId {
+        self.shared.id
+    }
+
+    /// Returns the name of the worker, if any, see
+    /// [`crate::Worker::with_name()`].
+    pub fn name(&self) -> Option<Arc<str>> {
+        self.shared.name()
+    }
+
     /// Returns a Future that gets fulfilled when the task or the worker had
     /// been terminated.
     pub fn terminated(&self) -> WaitForCancellationFuture<'_> {
         self.termination_token.cancelled()
     }
 
+    /// Tells the worker that the task is alive and not stuck, see
+    /// [`crate::health`].
+    pub fn heartbeat(&self) {
+        self.shared.heart.beat();
+    }
+
+    /// Marks the start of the processing of a message, that ends when the
+    /// returned guard is dropped. See [`crate::watchdog`].
+    pub fn start_message(&self) -> MessageGuard {
+        self.shared.watch().start()
+    }
+
     /// Terminates the worker and the related task.
     pub fn terminate(self) {
         self.termination_token.cancel();
     }
+
+    /// Receives the next control command sent by the worker. Returns `None`
+    /// when the worker has gone and no more commands can be received.
+    ///
+    /// This function is cancel safe, so it can be used in a `tokio::select!`
+    /// together with [`Self::terminated()`].
+    pub async fn control(&self) -> Option<Control<Ctrl>> {
+        self.control_from_wk.lock().await.recv().await
+    }
+}
+
+impl<Mode, Ctrl> fmt::Debug for Worker<Mode, Ctrl> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.shared.debug(f, &self.termination_token)
+    }
 }
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
-impl private::Sealed for Worker<Isolated> {}
+impl<Ctrl> private::Sealed for Worker<Isolated, Ctrl> {}
 
-impl Handle for Worker<Isolated> {}
+impl<Ctrl> Handle for Worker<Isolated, Ctrl> {}
 
-impl Worker<Isolated> {
-    pub(crate) fn isolated(token: CancellationToken) -> Worker<Isolated> {
-        Self {
-            termination_token: token,
-            mode: Isolated {},
-        }
+impl<Ctrl> Worker<Isolated, Ctrl> {
+    pub(crate) fn isolated(link: Link<Ctrl>) -> Worker<Isolated, Ctrl> {
+        Self::new(link, Isolated {})
     }
 }
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
-impl<Message> private::Sealed for Worker<OneWay<Message>> {}
+impl<Message, Mb: Mailbox<Message>, Ctrl> private::Sealed for Worker<OneWay<Message, Mb>, Ctrl> {}
 
-impl<Message> Handle for Worker<OneWay<Message>> {}
+impl<Message, Mb: Mailbox<Message>, Ctrl> Handle for Worker<OneWay<Message, Mb>, Ctrl> {}
 
-impl<Message> Worker<OneWay<Message>> {
+impl<Message, Mb: Mailbox<Message>, Ctrl> Worker<OneWay<Message, Mb>, Ctrl> {
     pub(crate) fn one_way(
-        token: CancellationToken,
-        from_wk: Receiver<Message>,
-    ) -> Worker<OneWay<Message>> {
-        Self {
-            termination_token: token,
-            mode: OneWay {
+        link: Link<Ctrl>,
+        from_wk: Mb::Receiver,
+    ) -> Worker<OneWay<Message, Mb>, Ctrl> {
+        Self::new(
+            link,
+            OneWay {
                 receiver_from_wk: from_wk,
             },
-        }
+        )
     }
 
     /// This function splits the handle in a tuple with the message receiver
     /// and an isolated handle that is able to terminate the pair task and
     /// worker.
-    pub fn receiver(self) -> (Receiver<Message>, Worker<Isolated>) {
-        let Worker {
-            termination_token,
-            mode,
-        } = self;
+    pub fn receiver(self) -> (Mb::Receiver, Worker<Isolated, Ctrl>) {
+        let (link, mode) = self.split();
         let OneWay { receiver_from_wk } = mode;
 
-        (receiver_from_wk, Worker::isolated(termination_token))
+        (receiver_from_wk, Worker::isolated(link))
+    }
+}
+
+impl<Message, Ctrl> Worker<OneWay<Message>, Ctrl> {
+    /// Like [`Self::receiver()`] but the messages are received in batches of
+    /// up to `max` messages, waiting at most `linger` to complete a batch.
+    /// See [`crate::batch`].
+    pub fn batched(
+        self,
+        max: usize,
+        linger: Duration,
+    ) -> (BatchReceiver<Message>, Worker<Isolated, Ctrl>) {
+        let (receiver, hnd) = self.receiver();
+
+        (BatchReceiver::new(receiver, max, linger), hnd)
     }
 }
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
-impl<InMessage, OutMessage> private::Sealed for Worker<TwoWay<InMessage, OutMessage>> {}
+impl<Message, Ctrl> private::Sealed for Worker<Prioritized<Message>, Ctrl> {}
+
+impl<Message, Ctrl> Handle for Worker<Prioritized<Message>, Ctrl> {}
 
-impl<InMessage, OutMessage> Handle for Worker<TwoWay<InMessage, OutMessage>> {}
+impl<Message, Ctrl> Worker<Prioritized<Message>, Ctrl> {
+    pub(crate) fn prioritized(
+        link: Link<Ctrl>,
+        from_wk: PriorityReceiver<Message>,
+    ) -> Worker<Prioritized<Message>, Ctrl> {
+        Self::new(
+            link,
+            Prioritized {
+                receiver_from_wk: from_wk,
+            },
+        )
+    }
 
-impl<InMessage, OutMessage> Worker<TwoWay<InMessage, OutMessage>> {
+    /// This function splits the handle in a tuple with the priority message
+    /// receiver and an isolated handle that is able to terminate the pair task
+    /// and worker.
+    pub fn receiver(self) -> (PriorityReceiver<Message>, Worker<Isolated, Ctrl>) {
+        let (link, mode) = self.split();
+        let Prioritized { receiver_from_wk } = mode;
+
+        (receiver_from_wk, Worker::isolated(link))
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+#[cfg(feature = "durable")]
+impl<Message, Ctrl> private::Sealed for Worker<Durable<Message>, Ctrl> {}
+
+#[cfg(feature = "durable")]
+impl<Message, Ctrl> Handle for Worker<Durable<Message>, Ctrl> {}
+
+#[cfg(feature = "durable")]
+impl<Message, Ctrl> Worker<Durable<Message>, Ctrl> {
+    pub(crate) fn durable(
+        link: Link<Ctrl>,
+        from_wk: crate::durable::DurableReceiver<Message>,
+    ) -> Worker<Durable<Message>, Ctrl> {
+        Self::new(
+            link,
+            Durable {
+                receiver_from_wk: from_wk,
+            },
+        )
+    }
+
+    /// This function splits the handle in a tuple with the durable message
+    /// receiver and an isolated handle that is able to terminate the pair
+    /// task and worker.
+    pub fn receiver(
+        self,
+    ) -> (
+        crate::durable::DurableReceiver<Message>,
+        Worker<Isolated, Ctrl>,
+    ) {
+        let (link, mode) = self.split();
+        let Durable { receiver_from_wk } = mode;
+
+        (receiver_from_wk, Worker::isolated(link))
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<InMessage, OutMessage, Mb: Mailbox<InMessage>, Ctrl> private::Sealed
+    for Worker<TwoWay<InMessage, OutMessage, Mb>, Ctrl>
+{
+}
+
+impl<InMessage, OutMessage, Mb: Mailbox<InMessage>, Ctrl> Handle
+    for Worker<TwoWay<InMessage, OutMessage, Mb>, Ctrl>
+{
+}
+
+impl<InMessage, OutMessage, Mb: Mailbox<InMessage>, Ctrl>
+    Worker<TwoWay<InMessage, OutMessage, Mb>, Ctrl>
+{
     pub(crate) fn two_way(
-        token: CancellationToken,
-        from_wk: Receiver<InMessage>,
-        to_task: broadcast::Sender<OutMessage>,
-    ) -> Worker<TwoWay<InMessage, OutMessage>> {
-        Self {
-            termination_token: token,
-            mode: TwoWay {
+        link: Link<Ctrl>,
+        from_wk: Mb::Receiver,
+        to_task: Broadcaster<OutMessage>,
+    ) -> Worker<TwoWay<InMessage, OutMessage, Mb>, Ctrl> {
+        Self::with_outlet(link, from_wk, Outlet::Broadcast(to_task))
+    }
+
+    pub(crate) fn with_outlet(
+        link: Link<Ctrl>,
+        from_wk: Mb::Receiver,
+        outlet: Outlet<OutMessage>,
+    ) -> Worker<TwoWay<InMessage, OutMessage, Mb>, Ctrl> {
+        Self::new(
+            link,
+            TwoWay {
                 receiver_from_wk: from_wk,
-                broadcast_from_task: to_task,
+                outlet,
             },
-        }
+        )
     }
 
     /// This function splits the handle in a tuple with the message receiver
     /// and a one-way bask handle that is able to terminate the pair task and
     /// worker and is able to send messages to all interested tasks.
-    pub fn receiver(self) -> (Receiver<InMessage>, Worker<OneWayBack<OutMessage>>) {
-        let Worker {
-            termination_token,
-            mode,
-        } = self;
+    pub fn receiver(self) -> (Mb::Receiver, Worker<OneWayBack<OutMessage>, Ctrl>) {
+        let (link, mode) = self.split();
         let TwoWay {
             receiver_from_wk,
-            broadcast_from_task,
+            outlet,
         } = mode;
 
-        (
-            receiver_from_wk,
-            Worker::one_way_back(termination_token, broadcast_from_task),
-        )
+        (receiver_from_wk, Worker::one_way_back(link, outlet))
+    }
+}
+
+impl<InMessage, OutMessage, Ctrl> Worker<TwoWay<InMessage, OutMessage>, Ctrl> {
+    /// Like [`Self::receiver()`] but the messages are received in batches of
+    /// up to `max` messages, waiting at most `linger` to complete a batch.
+    /// See [`crate::batch`].
+    pub fn batched(
+        self,
+        max: usize,
+        linger: Duration,
+    ) -> (
+        BatchReceiver<InMessage>,
+        Worker<OneWayBack<OutMessage>, Ctrl>,
+    ) {
+        let (receiver, hnd) = self.receiver();
+
+        (BatchReceiver::new(receiver, max, linger), hnd)
     }
 }
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
-impl<OutMessage> private::Sealed for Worker<OneWayBack<OutMessage>> {}
+impl<OutMessage, Ctrl> private::Sealed for Worker<OneWayBack<OutMessage>, Ctrl> {}
 
-impl<OutMessage> Handle for Worker<OneWayBack<OutMessage>> {}
+impl<OutMessage, Ctrl> Handle for Worker<OneWayBack<OutMessage>, Ctrl> {}
 
-impl<OutMessage> Worker<OneWayBack<OutMessage>> {
+impl<OutMessage, Ctrl> Worker<OneWayBack<OutMessage>, Ctrl> {
     pub(crate) fn one_way_back(
-        token: CancellationToken,
-        to_task: broadcast::Sender<OutMessage>,
-    ) -> Worker<OneWayBack<OutMessage>> {
-        Self {
-            termination_token: token,
-            mode: OneWayBack {
-                broadcast_from_task: to_task,
+        link: Link<Ctrl>,
+        outlet: Outlet<OutMessage>,
+    ) -> Worker<OneWayBack<OutMessage>, Ctrl> {
+        Self::new(link, OneWayBack { outlet })
+    }
+
+    /// Send message `msg` to the subscriber tasks and returns the number of
+    /// subscribers that will receive it.
+    ///
+    /// When the task is a stage of a [`crate::pipeline::Pipeline`] the message
+    /// is sent to the next stage instead, waiting for room in its mailbox.
+    ///
+    /// A message that nobody receives goes to the dead letters, if any, and
+    /// `Ok(0)` is returned. See [`crate::dead_letter`]. When the worker keeps
+    /// the recent messages for the new subscribers, see [`crate::event`], the
+    /// message is kept instead.
+    pub async fn post_message(&self, msg: OutMessage) -> Result<usize, SendError<OutMessage>>
+    where
+        OutMessage: Send + 'static,
+    {
+        let (msg, reason) = match &self.mode.outlet {
+            Outlet::Broadcast(sender) => match sender.send(msg) {
+                Ok(receivers) => return Ok(receivers),
+                Err(e) => (e.0, Reason::NoSubscribers),
             },
-        }
+            Outlet::Pipe(sender) => match sender.send(msg).await {
+                Ok(()) => return Ok(1),
+                Err(e) => (e.0, Reason::Terminated),
+            },
+        };
+
+        dead_letter::post(&self.shared, msg, reason)
+            .map(|()| 0)
+            .map_err(SendError)
     }
+}
 
-    /// Send message `msg` to the subscriber tasks.
-    pub async fn post_message(&self, msg: OutMessage) -> Result<usize, SendError<OutMessage>> {
-        self.mode.broadcast_from_task.send(msg)
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Message, Key, Ctrl> private::Sealed for Worker<Coalescing<Message, Key>, Ctrl> {}
+
+impl<Message, Key, Ctrl> Handle for Worker<Coalescing<Message, Key>, Ctrl> {}
+
+impl<Message, Key, Ctrl> Worker<Coalescing<Message, Key>, Ctrl> {
+    pub(crate) fn coalescing(
+        link: Link<Ctrl>,
+        from_wk: CoalescingReceiver<Message, Key>,
+    ) -> Worker<Coalescing<Message, Key>, Ctrl> {
+        Self::new(
+            link,
+            Coalescing {
+                receiver_from_wk: from_wk,
+            },
+        )
+    }
+
+    /// This function splits the handle in a tuple with the coalescing message
+    /// receiver and an isolated handle that is able to terminate the pair
+    /// task and worker.
+    pub fn receiver(self) -> (CoalescingReceiver<Message, Key>, Worker<Isolated, Ctrl>) {
+        let (link, mode) = self.split();
+        let Coalescing { receiver_from_wk } = mode;
+
+        (receiver_from_wk, Worker::isolated(link))
     }
 }
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
-impl<Event> private::Sealed for Worker<OnEvent<Event>> {}
+impl<Req, Resp, Ctrl> private::Sealed for Worker<Streaming<Req, Resp>, Ctrl> {}
+
+impl<Req, Resp, Ctrl> Handle for Worker<Streaming<Req, Resp>, Ctrl> {}
 
-impl<Event> Handle for Worker<OnEvent<Event>> {}
+impl<Req, Resp, Ctrl> Worker<Streaming<Req, Resp>, Ctrl> {
+    pub(crate) fn streaming(
+        link: Link<Ctrl>,
+        from_wk: Receiver<Request<Req, Resp>>,
+    ) -> Worker<Streaming<Req, Resp>, Ctrl> {
+        Self::new(
+            link,
+            Streaming {
+                receiver_from_wk: from_wk,
+            },
+        )
+    }
 
-impl<Event> Worker<OnEvent<Event>> {
+    /// This function splits the handle in a tuple with the request receiver
+    /// and an isolated handle that is able to terminate the pair task and
+    /// worker.
+    pub fn receiver(self) -> (Receiver<Request<Req, Resp>>, Worker<Isolated, Ctrl>) {
+        let (link, mode) = self.split();
+        let Streaming { receiver_from_wk } = mode;
+
+        (receiver_from_wk, Worker::isolated(link))
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<S, Ctrl> private::Sealed for Worker<State<S>, Ctrl> {}
+
+impl<S, Ctrl> Handle for Worker<State<S>, Ctrl> {}
+
+impl<S, Ctrl> Worker<State<S>, Ctrl> {
+    pub(crate) fn state(link: Link<Ctrl>, publisher: watch::Sender<S>) -> Worker<State<S>, Ctrl> {
+        Self::new(link, State { publisher })
+    }
+
+    /// Publishes `state`, replacing the previous one.
+    pub fn publish(&self, state: S) {
+        self.mode.publisher.send_replace(state);
+    }
+
+    /// Modifies the state in place with `modify` and publishes it.
+    pub fn modify(&self, modify: impl FnOnce(&mut S)) {
+        self.mode.publisher.send_modify(modify);
+    }
+
+    /// Returns a reference to the current state. No state can be published
+    /// while the reference is held, so it must be short lived.
+    pub fn borrow(&self) -> watch::Ref<'_, S> {
+        self.mode.publisher.borrow()
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<S, Ctrl> private::Sealed for Worker<OnChange<S>, Ctrl> {}
+
+impl<S, Ctrl> Handle for Worker<OnChange<S>, Ctrl> {}
+
+impl<S, Ctrl> Worker<OnChange<S>, Ctrl> {
+    pub(crate) fn on_change(
+        link: Link<Ctrl>,
+        from_task: watch::Receiver<S>,
+    ) -> Worker<OnChange<S>, Ctrl> {
+        Self::new(
+            link,
+            OnChange {
+                receiver_from_task: from_task,
+            },
+        )
+    }
+
+    /// This function splits the handle in a tuple with the state receiver and
+    /// an isolated handle that is able to terminate the pair task and worker.
+    pub fn receiver(self) -> (watch::Receiver<S>, Worker<Isolated, Ctrl>) {
+        let (link, mode) = self.split();
+        let OnChange { receiver_from_task } = mode;
+
+        (receiver_from_task, Worker::isolated(link))
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Event, Ctrl> private::Sealed for Worker<OnEvent<Event>, Ctrl> {}
+
+impl<Event, Ctrl> Handle for Worker<OnEvent<Event>, Ctrl> {}
+
+impl<Event, Ctrl> Worker<OnEvent<Event>, Ctrl> {
     pub(crate) fn on_event(
-        token: CancellationToken,
-        from_task: broadcast::Receiver<Event>,
-    ) -> Worker<OnEvent<Event>> {
-        Self {
-            termination_token: token,
-            mode: OnEvent {
+        link: Link<Ctrl>,
+        from_task: EventReceiver<Event>,
+    ) -> Worker<OnEvent<Event>, Ctrl> {
+        Self::new(
+            link,
+            OnEvent {
                 receiver_from_task: from_task,
             },
-        }
+        )
     }
 
     /// This function splits the handle in a tuple with the message receiver
     /// and an isolated handle that is able to terminate the pair task and
     /// worker.
-    pub fn receiver(self) -> (broadcast::Receiver<Event>, Worker<Isolated>) {
-        let Worker {
-            termination_token,
-            mode,
-        } = self;
+    pub fn receiver(self) -> (EventReceiver<Event>, Worker<Isolated, Ctrl>) {
+        let (link, mode) = self.split();
         let OnEvent { receiver_from_task } = mode;
 
-        (receiver_from_task, Worker::isolated(termination_token))
+        (receiver_from_task, Worker::isolated(link))
     }
 }
@@ -0,0 +1,335 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Heartbeats and health checks, to find the workers that are alive but
+//! stuck.
+//!
+//! The task beats with [`handle::Worker::heartbeat()`], usually from its main
+//! loop, and the worker tells when the last beat was with
+//! [`Worker::last_heartbeat()`]. When the heartbeats are expected at least
+//! every so often, see [`Worker::set_heartbeat_timeout()`], a task that
+//! doesn't beat in time is [`Status::Stale`]:
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{handle, Task};
+//! # pub struct Job {}
+//! # async fn process(_job: Job) {}
+//! # pub struct JobTask {}
+//! # impl Task for JobTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Job>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! let mut beat = tokio::time::interval(Duration::from_secs(1));
+//! loop {
+//!     tokio::select! {
+//!         _ = beat.tick() => hnd.heartbeat(),
+//!         Some(msg) = rx.recv() => process(msg).await,
+//!         () = hnd.terminated() => break,
+//!     }
+//! }
+//! #         }
+//! #     }
+//! # }
+//!```
+//!
+//! A [`HealthRegistry`] collects the health of many workers in a single
+//! [`HealthReport`], with the readiness and liveness of all of them:
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{handle, health::HealthRegistry, worker::OneWay, Task, Worker};
+//! # pub struct Job {}
+//! # pub struct JobTask {}
+//! # impl Task for JobTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Job>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move { while rx.recv().await.is_some() {} }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! # let worker = Worker::<OneWay<Job>>::spawn(JobTask {});
+//! # worker.set_heartbeat_timeout(Duration::from_secs(1));
+//! let registry = HealthRegistry::new();
+//! registry.register(&worker);
+//!
+//! let report = registry.report();
+//! if !report.live {
+//!     for health in report.workers.iter().filter(|h| !h.status.is_live()) {
+//!         println!("{} {:?} is {:?}", health.worker, health.name, health.status);
+//!     }
+//! }
+//! # }
+//!```
+//!
+//! [`handle::Worker::heartbeat()`]: crate::handle::Worker::heartbeat
+//! [`Worker::last_heartbeat()`]: crate::Worker::last_heartbeat
+//! [`Worker::set_heartbeat_timeout()`]: crate::Worker::set_heartbeat_timeout
+
+use std::{
+    sync::{atomic::Ordering, Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    worker::{Shared, Worker, WorkerId},
+    Error, ErrorKind,
+};
+
+/// The health of a worker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The task hasn't beaten yet, and the heartbeat timeout hasn't passed
+    /// since it was spawned.
+    Starting,
+    /// The task is beating in time.
+    Healthy,
+    /// The task didn't beat within the heartbeat timeout.
+    Stale,
+    /// The task is stuck on a message, see [`crate::watchdog`].
+    Stalled,
+    /// The worker has been terminated.
+    Terminated,
+}
+
+impl Status {
+    /// Returns true if the worker is alive and not stuck, even if it hasn't
+    /// beaten yet.
+    pub fn is_live(&self) -> bool {
+        matches!(self, Status::Starting | Status::Healthy)
+    }
+
+    /// Returns true if the worker is beating in time.
+    pub fn is_ready(&self) -> bool {
+        *self == Status::Healthy
+    }
+}
+
+/// The health report of a worker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Health {
+    /// The worker.
+    pub worker: WorkerId,
+    /// Its name, if any, see [`Worker::with_name()`].
+    pub name: Option<Arc<str>>,
+    /// Its health.
+    pub status: Status,
+    /// When its task did the last beat.
+    pub last_heartbeat: Option<Instant>,
+}
+
+/// What is known about a worker of a [`HealthRegistry`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkerInfo {
+    /// The health of the worker.
+    pub health: Health,
+    /// The name of its mode, like `"TwoWay"`.
+    pub mode: &'static str,
+    /// The last control command sent was [`crate::control::Control::Pause`].
+    pub paused: bool,
+    /// The messages waiting in its mailbox, if known.
+    pub mailbox_depth: Option<usize>,
+    /// The subscribers of the events sent by its task, if any.
+    pub subscribers: Option<usize>,
+    /// How long ago it was spawned.
+    pub uptime: Duration,
+}
+
+/// The health of all the workers of a [`HealthRegistry`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthReport {
+    /// All the workers are live, see [`Status::is_live()`].
+    pub live: bool,
+    /// All the workers are ready, see [`Status::is_ready()`].
+    pub ready: bool,
+    /// The health of every worker, in the order they were registered.
+    pub workers: Vec<Health>,
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// The heartbeats of a task, shared by the worker and the handle.
+pub(crate) struct Heart {
+    last: Mutex<Option<Instant>>,
+    timeout: Mutex<Option<Duration>>,
+}
+
+impl Heart {
+    pub(crate) fn new() -> Heart {
+        Heart {
+            last: Mutex::new(None),
+            timeout: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn beat(&self) {
+        *self.last.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+    }
+
+    pub(crate) fn last(&self) -> Option<Instant> {
+        *self.last.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn set_timeout(&self, timeout: Duration) {
+        *self.timeout.lock().unwrap_or_else(|e| e.into_inner()) = Some(timeout);
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        *self.timeout.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Returns the health of the worker with the `shared` state, terminated by
+// `token`.
+pub(crate) fn check(shared: &Shared, token: &CancellationToken) -> Health {
+    let last_heartbeat = shared.heart.last();
+
+    let status = if token.is_cancelled() {
+        Status::Terminated
+    } else if shared.stalled() {
+        Status::Stalled
+    } else {
+        let since = last_heartbeat.unwrap_or(shared.started);
+        match shared.heart.timeout() {
+            Some(timeout) if since.elapsed() > timeout => Status::Stale,
+            _ if last_heartbeat.is_some() => Status::Healthy,
+            _ => Status::Starting,
+        }
+    };
+
+    Health {
+        worker: shared.id,
+        name: shared.name(),
+        status,
+        last_heartbeat,
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// Collects the health of many workers, and lets them be inspected and
+/// controlled by identifier. Cloning the registry gives another reference to
+/// the same registry.
+#[derive(Clone, Default)]
+pub struct HealthRegistry {
+    entries: Arc<Mutex<Vec<Entry>>>,
+}
+
+struct Entry {
+    shared: Arc<Shared>,
+    token: CancellationToken,
+    // pauses or resumes the task, see Worker::pauser
+    pause: Box<dyn Fn(bool) -> Result<(), Error> + Send + Sync>,
+}
+
+impl HealthRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> HealthRegistry {
+        HealthRegistry::default()
+    }
+
+    /// Adds `worker` to the registry. A terminated worker stays in the
+    /// registry, and the registry is no more live, until it is unregistered.
+    pub fn register<Mode, Ctrl: Send + 'static>(&self, worker: &Worker<Mode, Ctrl>) {
+        self.lock().push(Entry {
+            shared: worker.shared.clone(),
+            token: worker.termination_token.clone(),
+            pause: worker.pauser(),
+        });
+    }
+
+    /// Removes the worker `id` from the registry.
+    pub fn unregister(&self, id: WorkerId) -> Result<(), Error> {
+        let mut entries = self.lock();
+        let count = entries.len();
+        entries.retain(|entry| entry.shared.id != id);
+
+        if entries.len() == count {
+            return Err(not_registered(id));
+        }
+        Ok(())
+    }
+
+    /// Returns the health of the registered workers.
+    pub fn report(&self) -> HealthReport {
+        let workers: Vec<Health> = self
+            .lock()
+            .iter()
+            .map(|entry| check(&entry.shared, &entry.token))
+            .collect();
+
+        HealthReport {
+            live: workers.iter().all(|health| health.status.is_live()),
+            ready: workers.iter().all(|health| health.status.is_ready()),
+            workers,
+        }
+    }
+
+    /// Returns what is known about the registered workers.
+    pub fn workers(&self) -> Vec<WorkerInfo> {
+        self.lock()
+            .iter()
+            .map(|entry| {
+                let probe = entry.shared.probe.get();
+                WorkerInfo {
+                    health: check(&entry.shared, &entry.token),
+                    mode: probe.map_or("Unknown", |probe| probe.mode),
+                    paused: entry.shared.paused.load(Ordering::Relaxed),
+                    mailbox_depth: probe.and_then(|probe| (probe.depth)()),
+                    subscribers: probe.and_then(|probe| (probe.subscribers)()),
+                    uptime: entry.shared.started.elapsed(),
+                }
+            })
+            .collect()
+    }
+
+    /// Terminates the worker `id` and its task, like
+    /// [`Worker::terminate()`].
+    pub fn terminate(&self, id: WorkerId) -> Result<(), Error> {
+        self.with(id, |entry| {
+            entry.token.cancel();
+            Ok(())
+        })
+    }
+
+    /// Asks the task of the worker `id` to pause, like [`Worker::pause()`].
+    /// Fails if its control channel is full.
+    pub fn pause(&self, id: WorkerId) -> Result<(), Error> {
+        self.with(id, |entry| (entry.pause)(true))
+    }
+
+    /// Asks the task of the worker `id` to resume, like
+    /// [`Worker::resume()`]. Fails if its control channel is full.
+    pub fn resume(&self, id: WorkerId) -> Result<(), Error> {
+        self.with(id, |entry| (entry.pause)(false))
+    }
+
+    fn with(&self, id: WorkerId, f: impl FnOnce(&Entry) -> Result<(), Error>) -> Result<(), Error> {
+        let entries = self.lock();
+        let entry = entries
+            .iter()
+            .find(|entry| entry.shared.id == id)
+            .ok_or_else(|| not_registered(id))?;
+        f(entry)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn not_registered(id: WorkerId) -> Error {
+    Error::new(ErrorKind::Other, format!("worker {id} not registered"))
+}
@@ -30,6 +30,21 @@ Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
 //! This struct will be the message we'll send to the following Adder task:
 //!    
 //!```rust
+//! # use opifex::{handle, Task};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # impl From<Sum> for Result {
+//! #     fn from(Sum { a, b }: Sum) -> Self {
+//! #         Result { sum: a + b }
+//! #     }
+//! # }
 //! pub struct Adder {}
 //!
 //! impl Task for Adder {
@@ -70,7 +85,44 @@ Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
 //! a worker with:
 //!
 //!```rust
+//! # use std::future::Future;
+//! # use opifex::{handle, worker::TwoWay, Task, Worker};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # impl From<Sum> for Result {
+//! #     fn from(Sum { a, b }: Sum) -> Self {
+//! #         Result { sum: a + b }
+//! #     }
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (_rx, hnd) = wk_hnd.receiver();
+//! #         async move { hnd.terminated().await }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (_rx, hnd) = wk_hnd.receiver();
+//! #         async move { hnd.terminated().await }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
 //! let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {});
+//! # }
 //!```
 //!
 //! In the same way we can implement a Response task and add it as a subscriber
@@ -78,19 +130,110 @@ Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
 //! on_message:
 //!
 //!```rust
+//! # use std::future::Future;
+//! # use opifex::{handle, worker::TwoWay, Task, Worker};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # impl From<Sum> for Result {
+//! #     fn from(Sum { a, b }: Sum) -> Self {
+//! #         Result { sum: a + b }
+//! #     }
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (_rx, hnd) = wk_hnd.receiver();
+//! #         async move { hnd.terminated().await }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (_rx, hnd) = wk_hnd.receiver();
+//! #         async move { hnd.terminated().await }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! # let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {});
 //! let response_worker = adder_worker.on_message(Response {});
+//! # }
 //!```
 //!
 //! Now we can send Sum messages to the Adder task with:
 //!
 //!```rust
+//! # use std::future::Future;
+//! # use opifex::{handle, worker::TwoWay, Task, Worker};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # impl From<Sum> for Result {
+//! #     fn from(Sum { a, b }: Sum) -> Self {
+//! #         Result { sum: a + b }
+//! #     }
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (_rx, hnd) = wk_hnd.receiver();
+//! #         async move { hnd.terminated().await }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (_rx, hnd) = wk_hnd.receiver();
+//! #         async move { hnd.terminated().await }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! # let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {});
 //! if let Err(e) = adder_worker.post_message(Sum { a: 24, b: 28 }).await {
 //!     eprintln!("Oops! sending a message to adder reports: {e}");
 //! }
+//! # }
 //!```
 //!
 //! The full example can be found in examples folder.
 //!
+//! # Optional features
+//!
+//! * `futures`: `Sink` implementations for workers and `Stream` adapters for
+//!   handles, see [`adapter`].
+//! * `cron`: cron expressions for scheduled workers, see [`schedule`].
+//! * `remote`: two-way workers served over TCP and Unix sockets, see `remote`.
+//! * `process`: workers whose task runs in a child process, see `process`.
+//! * `durable`: mailboxes that survive a restart, see `durable`.
+//! * `flume`, `async-channel`, `crossbeam`: mailboxes backed by the channels
+//!   of these crates, see [`mailbox`].
+//! * `admin`: an HTTP endpoint on localhost to inspect and control the
+//!   workers, see `admin`.
+//! * `smol`: workers running on an `async-executor`, like the one of smol,
+//!   instead of tokio, see [`runtime`].
+//!
 //! [wiki]: https://en.wikipedia.org/wiki/Opifex
 
 #![doc(
@@ -100,7 +243,39 @@ Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
 
 use std::{fmt::Display, future::Future};
 
+#[cfg(feature = "futures")]
+pub mod adapter;
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod batch;
+pub mod bus;
+pub mod circuit;
+pub mod coalesce;
+#[cfg(any(feature = "remote", feature = "durable"))]
+pub mod codec;
+pub mod control;
+pub mod dead_letter;
+#[cfg(feature = "durable")]
+pub mod durable;
+pub mod event;
 pub mod handle;
+pub mod health;
+#[cfg(any(feature = "remote", feature = "admin"))]
+mod listener;
+pub mod mailbox;
+pub mod pipeline;
+pub mod priority;
+#[cfg(all(feature = "process", unix))]
+pub mod process;
+pub mod rate_limit;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod retry;
+pub mod runtime;
+pub mod schedule;
+pub mod streaming;
+pub mod supervisor;
+pub mod watchdog;
 pub mod worker;
 
 pub use worker::Worker;
@@ -108,8 +283,25 @@ pub use worker::Worker;
 // default mpcs channel's buffer capacity
 pub const BUFFER_CAPACITY: usize = 1000;
 
+/// The kind of an [`Error`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The worker or its task has been terminated.
+    Terminated,
+    /// The message was over the rate limit, see [`rate_limit`].
+    RateLimited,
+    /// The circuit around the worker is open, see [`circuit`].
+    CircuitOpen,
+    /// The task of the worker is being restarted, see [`supervisor`].
+    Restarting,
+    /// Any other error.
+    Other,
+}
+
 #[derive(Clone, Debug)]
 pub struct Error {
+    kind: ErrorKind,
     cause: String,
 }
 
@@ -122,11 +314,21 @@ impl Display for Error {
 impl std::error::Error for Error {}
 
 impl Error {
-    pub(crate) fn from<E: std::error::Error>(e: &E) -> Self {
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub(crate) fn new<E: Display>(kind: ErrorKind, e: E) -> Self {
         Error {
+            kind,
             cause: format!("{e}"),
         }
     }
+
+    pub(crate) fn from<E: Display>(e: &E) -> Self {
+        Error::new(ErrorKind::Other, e)
+    }
 }
 
 /// The goal of [`Worker`] is to spawn and communicate to and/or control a `task`.
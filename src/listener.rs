@@ -0,0 +1,69 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+// The accept loop shared by the sockets listening for connections.
+
+use std::{future::Future, io, time::Duration};
+
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
+
+// the pause after a failed accept, like when running out of file
+// descriptors, so that the listener doesn't spin
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+// A socket accepting connections.
+pub(crate) trait Listener: Send + 'static {
+    type Stream: Send + 'static;
+
+    fn accept_stream(&self) -> impl Future<Output = io::Result<Self::Stream>> + Send;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept_stream(&self) -> io::Result<TcpStream> {
+        self.accept().await.map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    async fn accept_stream(&self) -> io::Result<UnixStream> {
+        self.accept().await.map(|(stream, _)| stream)
+    }
+}
+
+// Hands the connections accepted by `listener` to `serve`, until `token` is
+// cancelled. A failed accept doesn't stop the listener.
+pub(crate) fn spawn_accept<L: Listener>(
+    listener: L,
+    token: CancellationToken,
+    mut serve: impl FnMut(L::Stream) + Send + 'static,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                accepted = listener.accept_stream() => match accepted {
+                    Ok(stream) => serve(stream),
+                    Err(_) => tokio::select! {
+                        () = sleep(ACCEPT_ERROR_DELAY) => {}
+                        () = token.cancelled() => break,
+                    },
+                },
+                () = token.cancelled() => break,
+            }
+        }
+    });
+}
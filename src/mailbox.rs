@@ -0,0 +1,246 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Channel backends for the mailboxes of one-way and two-way workers.
+//!
+//! The messages posted to a worker travel in a channel created by its
+//! [`Mailbox`], given as the last type parameter of the mode: by default
+//! [`Tokio`], so the task receives from a [`tokio::sync::mpsc::Receiver`].
+//! With another backend the task gets the receiver of that library, and can
+//! hand it over to the components already using it:
+//!
+//!```rust
+//! # #[cfg(feature = "crossbeam")]
+//! # mod example {
+//! # use std::future::Future;
+//! # use opifex::{handle, mailbox::Crossbeam, worker::OneWay, Task, Worker};
+//! # pub struct Row {}
+//! # fn legacy_writer(rx: crossbeam_channel::Receiver<Row>) {
+//! #     while rx.recv().is_ok() {}
+//! # }
+//! # pub fn writer() {
+//! let worker = Worker::<OneWay<Row, Crossbeam>>::spawn(Writer {});
+//! # }
+//! # pub struct Writer {}
+//! # impl Task for Writer {
+//! #     type Handle = handle::Worker<handle::OneWay<Row, Crossbeam>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//!
+//! // task side: the receiver is a crossbeam_channel::Receiver<Row>
+//! let (rx, hnd) = wk_hnd.receiver();
+//! std::thread::spawn(move || legacy_writer(rx));
+//! # async move { hnd.terminated().await }
+//! #     }
+//! # }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! # #[cfg(feature = "crossbeam")]
+//! # example::writer();
+//! # }
+//!```
+//!
+//! Available backends, besides [`Tokio`], with the feature of the same name:
+//! `Flume`, `AsyncChannel` and `Crossbeam`. Other ones can be added
+//! implementing [`Mailbox`] and [`MailboxSender`].
+//!
+//! Rate limits, batches, pipelines and the futures adapters are available
+//! only with the [`Tokio`] backend.
+
+use std::future::Future;
+#[cfg(feature = "crossbeam")]
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+/// Creates the channels used as mailboxes of the workers, for messages of
+/// type Message.
+pub trait Mailbox<Message> {
+    /// The sending side, kept by the worker.
+    type Sender: MailboxSender<Message>;
+    /// The receiving side, given to the task.
+    type Receiver;
+
+    /// Creates a channel with room for `capacity` messages.
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver);
+
+    /// Returns a function counting the messages waiting in the channel of
+    /// `sender`, without keeping the channel open. It is shown by
+    /// [`crate::health::WorkerInfo`], and by default the count is unknown.
+    fn depth(sender: &Self::Sender) -> Box<dyn Fn() -> Option<usize> + Send + Sync>
+    where
+        Message: Send + 'static,
+    {
+        let _ = sender;
+        Box::new(|| None)
+    }
+}
+
+/// The sending side of a [`Mailbox`].
+pub trait MailboxSender<Message> {
+    /// Sends `msg`, waiting for room in the channel. Gives back the message
+    /// when the receiver is gone.
+    fn send(&self, msg: Message) -> impl Future<Output = Result<(), Message>> + Send
+    where
+        Message: Send + 'static;
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// The default backend: a [`tokio::sync::mpsc`] channel.
+pub struct Tokio;
+
+impl<Message> Mailbox<Message> for Tokio {
+    type Sender = mpsc::Sender<Message>;
+    type Receiver = mpsc::Receiver<Message>;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        mpsc::channel(capacity)
+    }
+
+    fn depth(sender: &Self::Sender) -> Box<dyn Fn() -> Option<usize> + Send + Sync>
+    where
+        Message: Send + 'static,
+    {
+        depth(sender)
+    }
+}
+
+// Counts the messages waiting in the channel of `sender`, while it is open.
+pub(crate) fn depth<Message: Send + 'static>(
+    sender: &mpsc::Sender<Message>,
+) -> Box<dyn Fn() -> Option<usize> + Send + Sync> {
+    let sender = sender.downgrade();
+    Box::new(move || {
+        sender
+            .upgrade()
+            .map(|sender| sender.max_capacity() - sender.capacity())
+    })
+}
+
+impl<Message> MailboxSender<Message> for mpsc::Sender<Message> {
+    async fn send(&self, msg: Message) -> Result<(), Message>
+    where
+        Message: Send + 'static,
+    {
+        mpsc::Sender::send(self, msg).await.map_err(|e| e.0)
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// A bounded [`flume`] channel.
+#[cfg(feature = "flume")]
+pub struct Flume;
+
+#[cfg(feature = "flume")]
+impl<Message> Mailbox<Message> for Flume {
+    type Sender = flume::Sender<Message>;
+    type Receiver = flume::Receiver<Message>;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        flume::bounded(capacity)
+    }
+
+    fn depth(sender: &Self::Sender) -> Box<dyn Fn() -> Option<usize> + Send + Sync>
+    where
+        Message: Send + 'static,
+    {
+        let sender = sender.downgrade();
+        Box::new(move || sender.upgrade().map(|sender| sender.len()))
+    }
+}
+
+#[cfg(feature = "flume")]
+impl<Message> MailboxSender<Message> for flume::Sender<Message> {
+    async fn send(&self, msg: Message) -> Result<(), Message>
+    where
+        Message: Send + 'static,
+    {
+        self.send_async(msg).await.map_err(|e| e.into_inner())
+    }
+}
+
+/// A bounded [`async_channel`] channel.
+#[cfg(feature = "async-channel")]
+pub struct AsyncChannel;
+
+#[cfg(feature = "async-channel")]
+impl<Message> Mailbox<Message> for AsyncChannel {
+    type Sender = async_channel::Sender<Message>;
+    type Receiver = async_channel::Receiver<Message>;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        async_channel::bounded(capacity)
+    }
+
+    fn depth(sender: &Self::Sender) -> Box<dyn Fn() -> Option<usize> + Send + Sync>
+    where
+        Message: Send + 'static,
+    {
+        let sender = sender.downgrade();
+        Box::new(move || sender.upgrade().map(|sender| sender.len()))
+    }
+}
+
+#[cfg(feature = "async-channel")]
+impl<Message> MailboxSender<Message> for async_channel::Sender<Message> {
+    async fn send(&self, msg: Message) -> Result<(), Message>
+    where
+        Message: Send + 'static,
+    {
+        async_channel::Sender::send(self, msg)
+            .await
+            .map_err(|e| e.into_inner())
+    }
+}
+
+// how often a full crossbeam channel is polled for room, from the min to
+// the max delay
+#[cfg(feature = "crossbeam")]
+const MIN_POLL_DELAY: Duration = Duration::from_micros(100);
+#[cfg(feature = "crossbeam")]
+const MAX_POLL_DELAY: Duration = Duration::from_millis(20);
+
+/// A bounded [`crossbeam_channel`] channel. The task receives with a blocking
+/// receiver, so it's meant to be handed over to a thread.
+#[cfg(feature = "crossbeam")]
+pub struct Crossbeam;
+
+#[cfg(feature = "crossbeam")]
+impl<Message> Mailbox<Message> for Crossbeam {
+    type Sender = crossbeam_channel::Sender<Message>;
+    type Receiver = crossbeam_channel::Receiver<Message>;
+
+    fn channel(capacity: usize) -> (Self::Sender, Self::Receiver) {
+        crossbeam_channel::bounded(capacity)
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+impl<Message> MailboxSender<Message> for crossbeam_channel::Sender<Message> {
+    async fn send(&self, msg: Message) -> Result<(), Message>
+    where
+        Message: Send + 'static,
+    {
+        use crossbeam_channel::TrySendError;
+
+        // a blocking send could not be cancelled: room is polled for instead,
+        // more and more slowly while the channel stays full
+        let (mut msg, mut delay) = (msg, MIN_POLL_DELAY);
+        loop {
+            match self.try_send(msg) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(m)) => return Err(m),
+                Err(TrySendError::Full(m)) => msg = m,
+            }
+            crate::runtime::sleep_until(tokio::time::Instant::now() + delay).await;
+            delay = (delay * 2).min(MAX_POLL_DELAY);
+        }
+    }
+}
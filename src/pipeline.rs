@@ -0,0 +1,339 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! A [`Pipeline`] chains two-way tasks so that the messages sent by a stage
+//! become the input of the next one:
+//!
+//!```rust
+//! # use std::future::Future;
+//! # use opifex::{handle, pipeline::Builder, Task};
+//! # struct Parse {}
+//! # impl Task for Parse {
+//! #     type Handle = handle::Worker<handle::TwoWay<String, u32>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(raw) = rx.recv().await {
+//! #                 let _ = hnd.post_message(raw.parse().unwrap_or_default()).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # struct Enrich {}
+//! # impl Task for Enrich {
+//! #     type Handle = handle::Worker<handle::TwoWay<u32, (u32, bool)>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(n) = rx.recv().await {
+//! #                 let _ = hnd.post_message((n, n % 2 == 0)).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # struct Persist {}
+//! # impl Task for Persist {
+//! #     type Handle = handle::Worker<handle::TwoWay<(u32, bool), String>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some((n, even)) = rx.recv().await {
+//! #                 let _ = hnd.post_message(format!("stored {n}, even: {even}")).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # struct Logger {}
+//! # impl Task for Logger {
+//! #     type Handle = handle::Worker<handle::OnEvent<String>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(line) = rx.recv().await {
+//! #                 println!("{line}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), opifex::Error> {
+//! # let raw = "42".to_string();
+//! let pipeline = Builder::new(Parse {}).then(Enrich {}).then(Persist {}).spawn();
+//! let logger = pipeline.on_message(Logger {});
+//!
+//! pipeline.post_message(raw).await?;
+//! # Ok(())
+//! # }
+//!```
+//!
+//! Stages are connected by bounded channels: when a stage is slow, the
+//! `post_message` of the previous stage waits, and so on up to the
+//! [`Pipeline::post_message()`] caller.
+//!
+//! Terminating the pipeline, or any of its stages, terminates every stage
+//! in order: a stage is terminated when the task of the previous one has
+//! finished. Dropping the pipeline terminates it as well, as nothing else
+//! can reach its stages. In the same way the control commands sent to the
+//! pipeline are sent to every stage.
+
+use std::{future::Future, sync::Arc};
+
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    control::{self, Control},
+    event::Broadcaster,
+    handle::{self, Outlet},
+    runtime,
+    worker::{Isolated, Post, Shared, Worker},
+    Error, ErrorKind, Task, BUFFER_CAPACITY,
+};
+
+// a spawned stage
+struct Stage {
+    // used to terminate the stage
+    termination_token: CancellationToken,
+    // used to send control commands toward the stage
+    control_to_tsk: Sender<Control>,
+    // used to wait for the task of the stage to finish
+    shared: Arc<Shared>,
+}
+
+// a stage that is not spawned yet, because it's not known where its output
+// goes: to the next stage or to the pipeline subscribers.
+type PendingStage<Out> = Box<dyn FnOnce(Outlet<Out>) -> Stage>;
+
+// a stage whose output is known, spawned with the pipeline.
+type ConnectedStage = Box<dyn FnOnce() -> Stage>;
+
+/// Builds a [`Pipeline`] whose stages receive `In` messages from the first
+/// stage and send `Out` messages from the last one. No stage is spawned
+/// before [`Builder::spawn()`]: a dropped builder leaves nothing behind.
+pub struct Builder<In, Out> {
+    // used to send messages toward the first stage
+    sender_to_first: Sender<In>,
+    // the stages before the last one, in order
+    stages: Vec<ConnectedStage>,
+    last: PendingStage<Out>,
+}
+
+impl<In: Send + 'static, Out: Send + 'static> Builder<In, Out> {
+    /// Starts a pipeline whose first stage is `task`.
+    pub fn new<T>(task: T) -> Builder<In, Out>
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<In, Out>>> + 'static,
+    {
+        let (sender_to_first, receiver) = channel::<In>(BUFFER_CAPACITY);
+
+        Builder {
+            sender_to_first,
+            stages: Vec::new(),
+            last: stage(task, receiver),
+        }
+    }
+
+    /// Appends `task` to the pipeline: it will receive the messages sent by
+    /// the current last stage.
+    pub fn then<T, Next>(self, task: T) -> Builder<In, Next>
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<Out, Next>>> + 'static,
+        Next: Send + 'static,
+    {
+        let Builder {
+            sender_to_first,
+            mut stages,
+            last,
+        } = self;
+
+        let (sender, receiver) = channel::<Out>(BUFFER_CAPACITY);
+        stages.push(Box::new(move || last(Outlet::Pipe(sender))));
+
+        Builder {
+            sender_to_first,
+            stages,
+            last: stage(task, receiver),
+        }
+    }
+
+    /// Spawns the stages and returns the running pipeline.
+    pub fn spawn(self) -> Pipeline<In, Out>
+    where
+        Out: Clone,
+    {
+        let Builder {
+            sender_to_first,
+            stages,
+            last,
+        } = self;
+
+        // the broadcast channel used by the last stage to communicate with
+        // the pipeline subscribers.
+        let broadcast_from_last = Broadcaster::new(BUFFER_CAPACITY);
+        let mut stages: Vec<Stage> = stages.into_iter().map(|stage| stage()).collect();
+        stages.push(last(Outlet::Broadcast(broadcast_from_last.clone())));
+
+        // This token is used to terminate the pipeline and all its stages.
+        let token = CancellationToken::new();
+
+        // a stage that terminates terminates the whole pipeline: these tasks
+        // end with it, at the latest when the pipeline is dropped...
+        for stage in &stages {
+            let (stage, token) = (stage.termination_token.clone(), token.clone());
+            runtime::spawn(async move {
+                tokio::select! {
+                    () = stage.cancelled() => token.cancel(),
+                    () = token.cancelled() => {}
+                }
+            });
+        }
+
+        // ...that terminates its stages in order, waiting for each task to
+        // finish before terminating the next one.
+        let terminated = token.clone();
+        let ordered: Vec<_> = stages
+            .iter()
+            .map(|stage| (stage.termination_token.clone(), stage.shared.clone()))
+            .collect();
+        runtime::spawn(async move {
+            terminated.cancelled().await;
+            for (stage, shared) in ordered {
+                stage.cancel();
+                shared.finished.cancelled().await;
+            }
+        });
+
+        Pipeline {
+            termination_token: token,
+            stages,
+            sender_to_first,
+            broadcast_from_last,
+        }
+    }
+}
+
+fn stage<T, In, Out>(task: T, receiver: Receiver<In>) -> PendingStage<Out>
+where
+    T: Task<Handle = handle::Worker<handle::TwoWay<In, Out>>> + 'static,
+    In: Send + 'static,
+    Out: Send + 'static,
+{
+    Box::new(move |outlet| {
+        // This token is used to terminate the stage and its controlled task.
+        let token = CancellationToken::new();
+
+        // the channel used to send control commands to the stage.
+        let shared = Shared::new(None);
+        let (control_to_tsk, link) = control::link(token.clone(), shared.clone());
+
+        let wkh = handle::Worker::with_outlet(link, receiver, outlet);
+
+        // The Task is spawned here
+        runtime::spawn(shared.track(task.spawn(wkh)));
+
+        Stage {
+            termination_token: token,
+            control_to_tsk,
+            shared,
+        }
+    })
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// A chain of two-way tasks built with [`Builder`]. It behaves like a two-way
+/// [`Worker`]: messages posted to the pipeline are received by the first stage
+/// and subscribers receive the messages sent by the last one.
+pub struct Pipeline<In, Out> {
+    // used to terminate all the stages
+    termination_token: CancellationToken,
+    // the stages, in order
+    stages: Vec<Stage>,
+    // used to send messages toward the first stage
+    sender_to_first: Sender<In>,
+    // used by interested tasks to subscribe to messages sent by the last stage
+    broadcast_from_last: Broadcaster<Out>,
+}
+
+impl<In, Out: Clone> Pipeline<In, Out> {
+    /// Send message `msg` to the first stage of the pipeline.
+    pub async fn post_message(&self, msg: In) -> Result<(), Error> {
+        self.sender_to_first
+            .send(msg)
+            .await
+            .map_err(|_| self.error(ErrorKind::Terminated, "pipeline terminated"))
+    }
+
+    /// Let `task` to subscribe to the messages sent by the last stage of the
+    /// pipeline. See [`Worker::on_message()`].
+    ///
+    /// [`Worker::on_message()`]: crate::worker::Worker::on_message
+    pub fn on_message<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<Out>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Worker::subscriber(task, self.broadcast_from_last.subscribe())
+    }
+
+    /// Asks every stage to pause, see [`Control::Pause`].
+    pub async fn pause(&self) -> Result<(), Error> {
+        self.send_control(Control::Pause).await
+    }
+
+    /// Asks every stage to resume, see [`Control::Resume`].
+    pub async fn resume(&self) -> Result<(), Error> {
+        self.send_control(Control::Resume).await
+    }
+
+    /// Asks every stage to flush, see [`Control::Flush`].
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.send_control(Control::Flush).await
+    }
+
+    async fn send_control(&self, cmd: Control) -> Result<(), Error> {
+        for stage in &self.stages {
+            stage.control_to_tsk.send(cmd.clone()).await.map_err(|_| {
+                stage
+                    .shared
+                    .error(ErrorKind::Terminated, "pipeline terminated")
+            })?;
+        }
+        Ok(())
+    }
+
+    // Returns an error of the pipeline, saying which stage it is about: the
+    // first one.
+    fn error(&self, kind: ErrorKind, cause: &str) -> Error {
+        self.stages[0].shared.error(kind, cause)
+    }
+
+    /// Terminates all the stages of the pipeline, from the first to the last.
+    pub fn terminate(self) {
+        self.termination_token.cancel();
+    }
+}
+
+impl<In, Out> Drop for Pipeline<In, Out> {
+    fn drop(&mut self) {
+        self.termination_token.cancel();
+    }
+}
+
+impl<In: Send, Out: Clone + Send> Post<In> for Pipeline<In, Out> {
+    fn post_message(&self, msg: In) -> impl Future<Output = Result<(), Error>> + Send {
+        Pipeline::post_message(self, msg)
+    }
+
+    fn error(&self, kind: ErrorKind, cause: &str) -> Error {
+        Pipeline::error(self, kind, cause)
+    }
+}
@@ -0,0 +1,201 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Priority mailboxes used by [`Worker<Prioritized>`]: every priority has its
+//! own lane and the task always receives the messages of the higher lanes
+//! first.
+//!
+//! [`Worker<Prioritized>`]: crate::worker::Worker
+
+use tokio::sync::mpsc::Receiver;
+
+/// The priority of a message sent to a [`Worker<Prioritized>`].
+///
+/// [`Worker<Prioritized>`]: crate::worker::Worker
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+// number of priority lanes
+pub(crate) const LANES: usize = 3;
+
+/// Receives the messages sent to a [`Worker<Prioritized>`], draining the
+/// higher priority lanes first.
+///
+/// When a starvation limit is set, after that number of consecutive messages
+/// taken from a higher lane while lower lanes have waiting messages, one
+/// message of the lower lanes is received.
+///
+/// [`Worker<Prioritized>`]: crate::worker::Worker
+pub struct PriorityReceiver<Message> {
+    lanes: [Receiver<Message>; LANES],
+    // messages already taken from the lanes but not yet received
+    peeked: [Option<Message>; LANES],
+    starvation_limit: Option<usize>,
+    // consecutive messages received while lower lanes were waiting
+    streak: usize,
+}
+
+impl<Message> PriorityReceiver<Message> {
+    pub(crate) fn new(
+        lanes: [Receiver<Message>; LANES],
+        starvation_limit: Option<usize>,
+    ) -> PriorityReceiver<Message> {
+        PriorityReceiver {
+            lanes,
+            peeked: [None, None, None],
+            starvation_limit,
+            streak: 0,
+        }
+    }
+
+    /// Receives the next message, waiting for one if all lanes are empty.
+    /// Returns `None` when the worker has gone and no messages are left.
+    ///
+    /// This function is cancel safe.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.fill();
+
+        if self.peeked.iter().all(Option::is_none) {
+            let [high, normal, low] = &mut self.lanes;
+            let (lane, msg) = tokio::select! {
+                biased;
+                Some(msg) = high.recv() => (0, msg),
+                Some(msg) = normal.recv() => (1, msg),
+                Some(msg) = low.recv() => (2, msg),
+                else => return None,
+            };
+            self.peeked[lane] = Some(msg);
+            self.fill();
+        }
+
+        self.next()
+    }
+
+    /// Receives the next message if one is available without waiting.
+    pub fn try_recv(&mut self) -> Option<Message> {
+        self.fill();
+        self.next()
+    }
+
+    // takes a message from every lane that has none waiting in peeked
+    fn fill(&mut self) {
+        for (lane, peeked) in self.lanes.iter_mut().zip(self.peeked.iter_mut()) {
+            if peeked.is_none() {
+                *peeked = lane.try_recv().ok();
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<Message> {
+        let first = self.peeked.iter().position(Option::is_some)?;
+        let lower = self.peeked[first + 1..]
+            .iter()
+            .position(Option::is_some)
+            .map(|lane| first + 1 + lane);
+
+        let starving = self
+            .starvation_limit
+            .is_some_and(|limit| self.streak >= limit);
+
+        let lane = match lower {
+            Some(lower) if starving => {
+                self.streak = 0;
+                lower
+            }
+            Some(_) => {
+                self.streak += 1;
+                first
+            }
+            None => {
+                self.streak = 0;
+                first
+            }
+        };
+
+        self.peeked[lane].take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::{channel, Sender};
+
+    use super::*;
+
+    fn receiver(starvation_limit: Option<usize>) -> ([Sender<u32>; LANES], PriorityReceiver<u32>) {
+        let (high, high_rx) = channel(10);
+        let (normal, normal_rx) = channel(10);
+        let (low, low_rx) = channel(10);
+        (
+            [high, normal, low],
+            PriorityReceiver::new([high_rx, normal_rx, low_rx], starvation_limit),
+        )
+    }
+
+    async fn send(sender: &Sender<u32>, msgs: impl IntoIterator<Item = u32>) {
+        for msg in msgs {
+            sender.send(msg).await.unwrap();
+        }
+    }
+
+    fn recv_all(rx: &mut PriorityReceiver<u32>) -> Vec<u32> {
+        let mut received = Vec::new();
+        while let Some(msg) = rx.try_recv() {
+            received.push(msg);
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn receives_the_higher_lanes_first() {
+        let ([high, normal, low], mut rx) = receiver(None);
+        send(&low, [31, 32]).await;
+        send(&normal, [21, 22]).await;
+        send(&high, [11, 12]).await;
+
+        assert_eq!(recv_all(&mut rx), [11, 12, 21, 22, 31, 32]);
+    }
+
+    #[tokio::test]
+    async fn starves_the_lower_lanes_without_a_limit() {
+        let ([high, _normal, low], mut rx) = receiver(None);
+        send(&low, [31]).await;
+        send(&high, 11..=15).await;
+
+        assert_eq!(recv_all(&mut rx), [11, 12, 13, 14, 15, 31]);
+    }
+
+    #[tokio::test]
+    async fn the_starvation_limit_lets_the_lower_lanes_through() {
+        let ([high, normal, low], mut rx) = receiver(Some(2));
+        send(&low, [31]).await;
+        send(&normal, [21]).await;
+        send(&high, 11..=15).await;
+
+        // the lanes lower than the one received from, the normal one first
+        assert_eq!(recv_all(&mut rx), [11, 12, 21, 13, 14, 31, 15]);
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_message_of_any_lane() {
+        let (senders, mut rx) = receiver(None);
+
+        let waiting = tokio::spawn(async move { (rx.recv().await, rx) });
+        tokio::task::yield_now().await;
+        send(&senders[2], [31]).await;
+        let (msg, mut rx) = waiting.await.unwrap();
+        assert_eq!(msg, Some(31));
+
+        drop(senders);
+        assert_eq!(rx.recv().await, None);
+    }
+}
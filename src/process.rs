@@ -0,0 +1,530 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Workers whose task runs in a child process. Available with the `process`
+//! feature, on Unix only.
+//!
+//! A [`ProcessWorker`] spawns a child process, usually the current binary in a
+//! worker role, and talks to it over the child's stdin and stdout with the
+//! same frames used by [`crate::remote`]. The child serves its task with
+//! [`serve_two_way()`] or [`serve_one_way()`]:
+//!
+//!```rust,no_run
+//! # use opifex::{codec::Codec, process::{self, ProcessWorker, Termination}, worker::TwoWay};
+//! # use std::future::Future;
+//! # use opifex::{handle, Task};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Clone, Debug, Serialize, Deserialize)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug, Serialize, Deserialize)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(Sum { a, b }) = rx.recv().await {
+//! #                 let _ = hnd.post_message(Result { sum: a + b }).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(result) = rx.recv().await {
+//! #                 println!("{result:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! #[tokio::main]
+//! async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+//!     if process::role().as_deref() == Some("adder") {
+//!         // child side
+//!         process::serve_two_way(Adder {}, Codec::Bincode).await?;
+//!         return Ok(());
+//!     }
+//!
+//!     // parent side
+//!     let adder = ProcessWorker::<TwoWay<Sum, Result>>::spawn_role(
+//!         "adder",
+//!         Codec::Bincode,
+//!         Termination::Signal,
+//!     )?;
+//!     let response_worker = adder.on_message(Response {});
+//!     adder.post_message(Sum { a: 24, b: 28 }).await?;
+//!
+//!     adder.terminate();
+//!     let status = adder.wait().await?;
+//!     println!("the adder exited with {status}");
+//!     Ok(())
+//! }
+//!```
+//!
+//! The child's stdout is reserved for the frames: the child must not print on
+//! it, stderr can be used instead.
+//!
+//! Termination is propagated in both directions: terminating the process
+//! worker terminates the child as told by its [`Termination`], and when the
+//! child exits, or its task is terminated, the process worker is terminated
+//! too. The exit status of the child is returned by [`ProcessWorker::wait()`].
+
+use std::{
+    env,
+    fs::File,
+    future::Future,
+    io,
+    marker::PhantomData,
+    os::fd::AsFd,
+    process::{ExitStatus, Stdio},
+    sync::Mutex,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    net::unix::pipe,
+    process::{Child, Command},
+    signal::unix::{signal, SignalKind},
+    sync::{broadcast, mpsc::Receiver},
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    codec::Codec,
+    dead_letter::DeadLetterQueue,
+    event::EventReceiver,
+    handle,
+    remote::{relay, serve_halves, Relayed},
+    worker::{Isolated, OneWay, Post, Shared, TwoWay, Worker},
+    Error, Task,
+};
+
+/// The environment variable used by [`ProcessWorker::spawn_role()`] to tell
+/// the child process its role.
+pub const ROLE_VAR: &str = "OPIFEX_WORKER_ROLE";
+
+// how long a terminated child is waited for before killing it
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Returns the role this process has been spawned with by
+/// [`ProcessWorker::spawn_role()`], if any.
+pub fn role() -> Option<String> {
+    env::var(ROLE_VAR).ok()
+}
+
+/// How a child process is terminated when its [`ProcessWorker`] is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Termination {
+    /// The child is asked to terminate its task with a message, and it is
+    /// killed if it does not exit within a grace period.
+    #[default]
+    Message,
+    /// Like [`Termination::Message`], but a `SIGTERM` signal is sent too. A
+    /// child that is not serving its task yet is killed by the signal.
+    Signal,
+    /// The child is killed straight away.
+    Kill,
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// A worker whose task runs in a child process. See [`crate::process`].
+pub struct ProcessWorker<Mode> {
+    // a local worker whose task is the bridge to the child
+    worker: Worker<Mode>,
+    // the bridge, that returns the exit status of the child
+    joined: JoinHandle<io::Result<ExitStatus>>,
+}
+
+impl<Mode> ProcessWorker<Mode> {
+    /// Terminates this worker and the child process, as told by the
+    /// [`Termination`] given at spawn time.
+    pub fn terminate(&self) {
+        self.worker.termination_token.cancel();
+    }
+
+    /// Sets the queue collecting the messages that can't be delivered to the
+    /// child process, see [`Worker::set_dead_letters()`].
+    pub fn set_dead_letters(&self, queue: DeadLetterQueue) {
+        self.worker.set_dead_letters(queue);
+    }
+
+    /// Waits for the child process to exit and returns its exit status.
+    pub async fn wait(self) -> Result<ExitStatus, Error> {
+        match self.joined.await {
+            Ok(status) => status.map_err(|e| Error::from(&e)),
+            Err(e) => Err(Error::from(&e)),
+        }
+    }
+}
+
+impl<Message, TaskMessage> ProcessWorker<TwoWay<Message, TaskMessage>>
+where
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Clone + Send + 'static,
+{
+    /// Spawns `command` as a child process serving a two-way task with
+    /// [`serve_two_way()`]. The child's stdin and stdout are taken over.
+    pub fn spawn(
+        command: std::process::Command,
+        codec: Codec,
+        termination: Termination,
+    ) -> io::Result<ProcessWorker<TwoWay<Message, TaskMessage>>> {
+        let bridge = Bridge::<TwoWay<Message, TaskMessage>>::new(command, codec, termination)?;
+        let (worker, joined) = Worker::<TwoWay<Message, TaskMessage>>::spawn_joined(bridge);
+
+        Ok(ProcessWorker { worker, joined })
+    }
+
+    /// Spawns the current binary in the worker role `role`, without
+    /// arguments, see [`role_command()`].
+    pub fn spawn_role(
+        role: &str,
+        codec: Codec,
+        termination: Termination,
+    ) -> io::Result<ProcessWorker<TwoWay<Message, TaskMessage>>> {
+        Self::spawn(role_command(role)?, codec, termination)
+    }
+
+    /// Send message `msg` to the child's task.
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error> {
+        self.worker.post_message(msg).await
+    }
+
+    /// Let `task` to subscribe to event messages sent by the child's task,
+    /// see [`Worker::on_message()`].
+    pub fn on_message<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<TaskMessage>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        self.worker.on_message(task)
+    }
+
+    /// Returns a receiver of the event messages sent by the child's task, see
+    /// [`Worker::subscribe()`].
+    pub fn subscribe(&self) -> EventReceiver<TaskMessage> {
+        self.worker.subscribe()
+    }
+}
+
+impl<Message, TaskMessage> Post<Message> for ProcessWorker<TwoWay<Message, TaskMessage>>
+where
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Clone + Send + 'static,
+{
+    fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        ProcessWorker::<TwoWay<Message, TaskMessage>>::post_message(self, msg)
+    }
+}
+
+impl<Message> ProcessWorker<OneWay<Message>>
+where
+    Message: Serialize + Send + 'static,
+{
+    /// Spawns `command` as a child process serving a one-way task with
+    /// [`serve_one_way()`]. The child's stdin and stdout are taken over.
+    pub fn spawn(
+        command: std::process::Command,
+        codec: Codec,
+        termination: Termination,
+    ) -> io::Result<ProcessWorker<OneWay<Message>>> {
+        let bridge = Bridge::<OneWay<Message>>::new(command, codec, termination)?;
+        let (worker, joined) = Worker::<OneWay<Message>>::spawn_joined(bridge);
+
+        Ok(ProcessWorker { worker, joined })
+    }
+
+    /// Spawns the current binary in the worker role `role`, without
+    /// arguments, see [`role_command()`].
+    pub fn spawn_role(
+        role: &str,
+        codec: Codec,
+        termination: Termination,
+    ) -> io::Result<ProcessWorker<OneWay<Message>>> {
+        Self::spawn(role_command(role)?, codec, termination)
+    }
+
+    /// Send message `msg` to the child's task.
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error> {
+        self.worker.post_message(msg).await
+    }
+}
+
+impl<Message: Serialize + Send + 'static> Post<Message> for ProcessWorker<OneWay<Message>> {
+    fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        ProcessWorker::<OneWay<Message>>::post_message(self, msg)
+    }
+}
+
+/// Returns the command spawning the current binary in the worker role `role`,
+/// see [`role()`]. No arguments are passed to the child: they can be added to
+/// the command, which is then spawned with [`ProcessWorker::spawn()`].
+pub fn role_command(role: &str) -> io::Result<std::process::Command> {
+    let mut command = std::process::Command::new(env::current_exe()?);
+    command.env(ROLE_VAR, role);
+    Ok(command)
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// the task of a process worker: it moves messages and events between the
+// local worker and the child process, and reaps the child.
+struct Bridge<Mode> {
+    // taken by the first and only spawn
+    child: Mutex<Option<Child>>,
+    codec: Codec,
+    termination: Termination,
+    mode: PhantomData<fn() -> Mode>,
+}
+
+impl<Mode> Bridge<Mode> {
+    fn new(
+        command: std::process::Command,
+        codec: Codec,
+        termination: Termination,
+    ) -> io::Result<Bridge<Mode>> {
+        let child = Command::from(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        Ok(Bridge {
+            child: Mutex::new(Some(child)),
+            codec,
+            termination,
+            mode: PhantomData,
+        })
+    }
+
+    fn take_child(&self) -> Option<Child> {
+        self.child.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+impl<Message, TaskMessage> Task for Bridge<TwoWay<Message, TaskMessage>>
+where
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Clone + Send + 'static,
+{
+    type Handle = handle::Worker<handle::TwoWay<Message, TaskMessage>>;
+    type Output = io::Result<ExitStatus>;
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (rx, hnd) = wk_hnd.receiver();
+        let (child, codec, termination) = (self.take_child(), self.codec, self.termination);
+
+        async move {
+            let token = hnd.termination_token.clone();
+            supervise(
+                child,
+                codec,
+                termination,
+                rx,
+                Some(&hnd),
+                &hnd.shared,
+                token,
+            )
+            .await
+        }
+    }
+}
+
+impl<Message> Task for Bridge<OneWay<Message>>
+where
+    Message: Serialize + Send + 'static,
+{
+    type Handle = handle::Worker<handle::OneWay<Message>>;
+    type Output = io::Result<ExitStatus>;
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (rx, hnd) = wk_hnd.receiver();
+        let (child, codec, termination) = (self.take_child(), self.codec, self.termination);
+
+        async move {
+            let back = None::<&handle::Worker<handle::OneWayBack<()>>>;
+            let token = hnd.termination_token.clone();
+            supervise(child, codec, termination, rx, back, &hnd.shared, token).await
+        }
+    }
+}
+
+// Relays messages and events between the local worker and `child` until one
+// of the two is terminated, then terminates the other one and reaps the child.
+async fn supervise<Message, TaskMessage>(
+    child: Option<Child>,
+    codec: Codec,
+    termination: Termination,
+    mut rx: Receiver<Message>,
+    back: Option<&handle::Worker<handle::OneWayBack<TaskMessage>>>,
+    shared: &Shared,
+    token: CancellationToken,
+) -> io::Result<ExitStatus>
+where
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Send + 'static,
+{
+    let Some(mut child) = child else {
+        token.cancel();
+        return Err(io::Error::other("process worker spawned twice"));
+    };
+    let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        token.cancel();
+        child.start_kill()?;
+        return child.wait().await;
+    };
+
+    let mut pending = None;
+    match relay(
+        stdout,
+        stdin,
+        codec,
+        &mut rx,
+        &mut pending,
+        back,
+        shared,
+        &token,
+    )
+    .await
+    {
+        // the child has exited or has terminated its task
+        Relayed::Disconnected | Relayed::ServerTerminated => token.cancel(),
+        Relayed::Terminated => match termination {
+            Termination::Message => {}
+            Termination::Signal => {
+                if let Some(pid) = child.id() {
+                    // SAFETY: kill has no memory safety requirements.
+                    unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+                }
+            }
+            Termination::Kill => child.start_kill()?,
+        },
+    }
+
+    match timeout(GRACE_PERIOD, child.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            child.start_kill()?;
+            child.wait().await
+        }
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// Serves the two-way `task` to the parent [`ProcessWorker`] over stdin and
+/// stdout, and returns the task output once the task has finished.
+///
+/// The task is terminated when the parent asks to, when this process receives
+/// a `SIGTERM` signal or when the parent goes away.
+pub async fn serve_two_way<T, Message, TaskMessage>(
+    task: T,
+    codec: Codec,
+) -> Result<<T as Task>::Output, Error>
+where
+    T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage>>>,
+    <T as Task>::Output: Send + 'static,
+    Message: DeserializeOwned + Send + 'static,
+    TaskMessage: Serialize + Clone + Send + 'static,
+{
+    let (worker, joined) = Worker::<TwoWay<Message, TaskMessage>>::spawn_joined(task);
+    let events = worker.mode.broadcast_from_tsk.subscribe();
+
+    serve(
+        worker.mode.sender_to_tsk.clone(),
+        events,
+        codec,
+        &worker.termination_token,
+    )?;
+
+    joined.await.map_err(|e| Error::from(&e))
+}
+
+/// Serves the one-way `task` to the parent [`ProcessWorker`] over stdin and
+/// stdout, and returns the task output once the task has finished. See
+/// [`serve_two_way()`].
+pub async fn serve_one_way<T, Message>(task: T, codec: Codec) -> Result<<T as Task>::Output, Error>
+where
+    T: Task<Handle = handle::Worker<handle::OneWay<Message>>>,
+    <T as Task>::Output: Send + 'static,
+    Message: DeserializeOwned + Send + 'static,
+{
+    let (worker, joined) = Worker::<OneWay<Message>>::spawn_joined(task);
+    // a one-way task sends no events
+    let (_, events) = broadcast::channel::<()>(1);
+    let events = EventReceiver::from(events);
+
+    serve(
+        worker.mode.sender_to_tsk.clone(),
+        events,
+        codec,
+        &worker.termination_token,
+    )?;
+
+    joined.await.map_err(|e| Error::from(&e))
+}
+
+fn serve<Message, TaskMessage>(
+    sender: tokio::sync::mpsc::Sender<Message>,
+    events: EventReceiver<TaskMessage>,
+    codec: Codec,
+    token: &CancellationToken,
+) -> Result<(), Error>
+where
+    Message: DeserializeOwned + Send + 'static,
+    TaskMessage: Serialize + Clone + Send + 'static,
+{
+    // stdin and stdout are reopened as non blocking pipes: the blocking ones
+    // of tokio would keep the runtime alive until the parent closes stdin.
+    let reader =
+        pipe::Receiver::from_file(reopen(io::stdin().as_fd())?).map_err(|e| Error::from(&e))?;
+    let writer =
+        pipe::Sender::from_file(reopen(io::stdout().as_fd())?).map_err(|e| Error::from(&e))?;
+    let mut terminate = signal(SignalKind::terminate()).map_err(|e| Error::from(&e))?;
+
+    let connection = serve_halves(reader, writer, codec, sender, events, token.clone());
+
+    // the task is terminated also when the parent goes away or on SIGTERM
+    let token = token.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            () = connection.cancelled() => {}
+            _ = terminate.recv() => {}
+        }
+        token.cancel();
+    });
+
+    Ok(())
+}
+
+fn reopen(fd: std::os::fd::BorrowedFd<'_>) -> Result<File, Error> {
+    fd.try_clone_to_owned()
+        .map(File::from)
+        .map_err(|e| Error::from(&e))
+}
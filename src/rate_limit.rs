@@ -0,0 +1,215 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Rate limiting of the messages posted to a worker.
+//!
+//! A worker spawned with a [`RateLimit`] lets through at most `rate` messages
+//! every `period`, with bursts of up to `burst` messages, following a token
+//! bucket. The [`Throttle`] tells what happens to the messages over the
+//! limit:
+//!
+//!```rust
+//! # use std::future::Future;
+//! # use opifex::{handle, rate_limit::{RateLimit, Throttle}, worker::OneWay, Task, Worker};
+//! # pub struct Call {}
+//! # pub struct ApiTask {}
+//! # impl Task for ApiTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Call>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move { while rx.recv().await.is_some() {} }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! // at most 10 calls per second to the external API, smoothly
+//! let limit = RateLimit::per_second(10).with_burst(1).with_throttle(Throttle::Smooth);
+//! let worker = Worker::<OneWay<Call>>::spawn_with_rate_limit(ApiTask {}, limit);
+//! # }
+//!```
+//!
+//! The limiter uses the tokio clock, so it works with paused time in tests.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::mpsc::{channel, Receiver},
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    dead_letter::{self, Reason},
+    runtime,
+    worker::Shared,
+    Error, ErrorKind, BUFFER_CAPACITY,
+};
+
+/// What happens to the messages over the limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Throttle {
+    /// `post_message` waits until the message is within the limit.
+    #[default]
+    Delay,
+    /// `post_message` fails with [`ErrorKind::RateLimited`] and the message is
+    /// dropped.
+    Reject,
+    /// `post_message` doesn't wait for the limit: the messages are queued
+    /// and delivered to the task within the limit. The queue is the mailbox,
+    /// of [`BUFFER_CAPACITY`] messages: when it's full `post_message` waits
+    /// as usual. The messages still queued when the worker is terminated are
+    /// dead letters, see [`crate::dead_letter`].
+    Smooth,
+}
+
+/// The maximum rate of the messages posted to a worker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    rate: u32,
+    period: Duration,
+    burst: u32,
+    throttle: Throttle,
+}
+
+impl RateLimit {
+    /// At most `rate` messages every `period`, in bursts of up to `rate`
+    /// messages.
+    pub fn new(rate: u32, period: Duration) -> RateLimit {
+        RateLimit {
+            rate: rate.max(1),
+            period,
+            burst: rate.max(1),
+            throttle: Throttle::default(),
+        }
+    }
+
+    /// At most `rate` messages every second.
+    pub fn per_second(rate: u32) -> RateLimit {
+        RateLimit::new(rate, Duration::from_secs(1))
+    }
+
+    /// Sets the maximum number of messages let through at once, after a
+    /// quiet time. A burst of 1 spreads the messages evenly.
+    pub fn with_burst(mut self, burst: u32) -> RateLimit {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Sets what happens to the messages over the limit, by default
+    /// [`Throttle::Delay`].
+    pub fn with_throttle(mut self, throttle: Throttle) -> RateLimit {
+        self.throttle = throttle;
+        self
+    }
+
+    pub(crate) fn throttle(&self) -> Throttle {
+        self.throttle
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// A token bucket: every message takes a token, the tokens are given back at
+// the limit rate up to the burst.
+pub(crate) struct Limiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Limiter {
+    pub(crate) fn new(limit: RateLimit) -> Limiter {
+        Limiter {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(limit.burst),
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    // Lets a message through, as told by the throttle. The messages limited
+    // with Throttle::Smooth don't get here, see pace.
+    pub(crate) async fn admit(&self) -> Result<(), Error> {
+        if self.limit.throttle == Throttle::Reject {
+            return self
+                .try_acquire()
+                .map_err(|_| Error::new(ErrorKind::RateLimited, "rate limit exceeded"));
+        }
+        self.acquire().await;
+        Ok(())
+    }
+
+    async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire() {
+            runtime::sleep_until(Instant::now() + wait).await;
+        }
+    }
+
+    // Takes a token, or returns how long to wait for the next one.
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+
+        let now = Instant::now();
+        let per_token = self.limit.period.as_secs_f64() / f64::from(self.limit.rate);
+        let refilled = now.duration_since(bucket.last).as_secs_f64() / per_token;
+        bucket.tokens = (bucket.tokens + refilled).min(f64::from(self.limit.burst));
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * per_token))
+        }
+    }
+}
+
+// Returns a receiver of the messages received from `receiver`, paced by
+// `limiter`, until `token` is cancelled. The messages left behind are dead
+// letters of `shared`.
+pub(crate) fn pace<Message: Send + 'static>(
+    mut receiver: Receiver<Message>,
+    limiter: Limiter,
+    shared: Arc<Shared>,
+    token: CancellationToken,
+) -> Receiver<Message> {
+    let (sender, paced) = channel(BUFFER_CAPACITY);
+
+    runtime::spawn(async move {
+        loop {
+            let msg = tokio::select! {
+                Some(msg) = receiver.recv() => msg,
+                () = token.cancelled() => break,
+                else => break,
+            };
+            let admitted = tokio::select! {
+                () = limiter.acquire() => sender.send(msg).await.map_err(|e| e.0),
+                () = token.cancelled() => Err(msg),
+            };
+            if let Err(msg) = admitted {
+                let _ = dead_letter::post(&shared, msg, Reason::Terminated);
+                break;
+            }
+        }
+
+        receiver.close();
+        while let Ok(msg) = receiver.try_recv() {
+            let _ = dead_letter::post(&shared, msg, Reason::Terminated);
+        }
+    });
+
+    paced
+}
@@ -0,0 +1,526 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Two-way workers reachable over TCP or Unix sockets. Available with the
+//! `remote` feature.
+//!
+//! A two-way [`Worker`] is exposed with [`Worker::serve_tcp()`] or
+//! [`Worker::serve_unix()`]; a [`RemoteWorker`] connects to it and offers the
+//! same API of the served worker:
+//!
+//!```rust,no_run
+//! # use opifex::{codec::Codec, remote::{Endpoint, RemoteWorker}, worker::TwoWay, Worker};
+//! # use std::future::Future;
+//! # use opifex::{handle, Task};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Clone, Debug, Serialize, Deserialize)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug, Serialize, Deserialize)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(Sum { a, b }) = rx.recv().await {
+//! #                 let _ = hnd.post_message(Result { sum: a + b }).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(result) = rx.recv().await {
+//! #                 println!("{result:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+//! // server side
+//! let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {});
+//! let addr = adder_worker.serve_tcp("127.0.0.1:0", Codec::Bincode).await?;
+//!
+//! // client side
+//! let remote = RemoteWorker::<TwoWay<Sum, Result>>::connect(
+//!     Endpoint::Tcp(addr.to_string()),
+//!     Codec::Bincode,
+//! );
+//! let response_worker = remote.on_message(Response {});
+//! remote.post_message(Sum { a: 24, b: 28 }).await?;
+//! # Ok(())
+//! # }
+//!```
+//!
+//! Messages are sent as length-prefixed frames, see [`crate::codec`]. The
+//! client connects in background and reconnects when the connection is lost;
+//! messages posted meanwhile wait in its mailbox.
+//!
+//! Termination is propagated in both directions: terminating a remote worker
+//! terminates the served worker, and all of its clients, and terminating the
+//! served worker terminates its clients.
+
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::{future::Future, io, marker::PhantomData, net::SocketAddr, time::Duration};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        broadcast::error::RecvError,
+        mpsc::{self, Receiver, Sender},
+    },
+    time::{sleep, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    codec::{write_frame, Codec},
+    dead_letter::{self, DeadLetterQueue, Reason},
+    event::{Broadcaster, EventReceiver},
+    handle,
+    listener::spawn_accept,
+    worker::{Isolated, Post, Shared, TwoWay, Worker},
+    Error, Task, BUFFER_CAPACITY,
+};
+
+// reconnection delays grow from the min to the max one
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// frames sent by a client
+#[derive(Serialize, Deserialize)]
+enum ToServer<Message> {
+    Message(Message),
+    Terminate,
+}
+
+// frames sent by a server
+#[derive(Serialize, Deserialize)]
+enum ToClient<TaskMessage> {
+    Event(TaskMessage),
+    Terminated,
+}
+
+// a connection, whatever the socket is
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Connection for S {}
+
+/// Where a served worker can be reached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Endpoint {
+    /// A TCP address, like `"127.0.0.1:4000"`.
+    Tcp(String),
+    /// The path of a Unix socket.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    async fn connect(&self) -> io::Result<Box<dyn Connection>> {
+        match self {
+            Endpoint::Tcp(addr) => Ok(Box::new(TcpStream::connect(addr).await?)),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Ok(Box::new(UnixStream::connect(path).await?)),
+        }
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Message, TaskMessage, Ctrl> Worker<TwoWay<Message, TaskMessage>, Ctrl>
+where
+    Message: DeserializeOwned + Send + 'static,
+    TaskMessage: Serialize + Clone + Send + 'static,
+{
+    /// Accepts [`RemoteWorker`] connections on the TCP address `addr`, until
+    /// this worker is terminated. Returns the address the listener is bound
+    /// to, useful when binding to port 0.
+    pub async fn serve_tcp(
+        &self,
+        addr: impl ToSocketAddrs,
+        codec: Codec,
+    ) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+
+        let (sender, broadcast, token) = self.remote_parts();
+        spawn_accept(listener, token.clone(), move |stream| {
+            serve(
+                stream,
+                codec,
+                sender.clone(),
+                broadcast.subscribe(),
+                token.clone(),
+            );
+        });
+
+        Ok(local_addr)
+    }
+
+    /// Accepts [`RemoteWorker`] connections on the Unix socket at `path`,
+    /// until this worker is terminated.
+    #[cfg(unix)]
+    pub async fn serve_unix(&self, path: impl AsRef<Path>, codec: Codec) -> io::Result<()> {
+        let listener = UnixListener::bind(path)?;
+
+        let (sender, broadcast, token) = self.remote_parts();
+        spawn_accept(listener, token.clone(), move |stream| {
+            serve(
+                stream,
+                codec,
+                sender.clone(),
+                broadcast.subscribe(),
+                token.clone(),
+            );
+        });
+
+        Ok(())
+    }
+
+    fn remote_parts(&self) -> (Sender<Message>, Broadcaster<TaskMessage>, CancellationToken) {
+        (
+            self.mode.sender_to_tsk.clone(),
+            self.mode.broadcast_from_tsk.clone(),
+            self.termination_token.clone(),
+        )
+    }
+}
+
+// Serves a client connection: messages read from `stream` are sent to the
+// worker's task and events sent by the task are written to `stream`.
+fn serve<S, Message, TaskMessage>(
+    stream: S,
+    codec: Codec,
+    sender: Sender<Message>,
+    events: EventReceiver<TaskMessage>,
+    token: CancellationToken,
+) where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+    Message: DeserializeOwned + Send + 'static,
+    TaskMessage: Serialize + Clone + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
+    serve_halves(reader, writer, codec, sender, events, token);
+}
+
+// Like serve, but with the two halves of the connection. Returns the token
+// that gets cancelled when the client goes away.
+pub(crate) fn serve_halves<R, W, Message, TaskMessage>(
+    mut reader: R,
+    mut writer: W,
+    codec: Codec,
+    sender: Sender<Message>,
+    mut events: EventReceiver<TaskMessage>,
+    token: CancellationToken,
+) -> CancellationToken
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+    Message: DeserializeOwned + Send + 'static,
+    TaskMessage: Serialize + Clone + Send + 'static,
+{
+    // closed when the client goes away
+    let connection = token.child_token();
+    let worker = token.clone();
+
+    let closed = connection.clone();
+    tokio::spawn(async move {
+        let _guard = closed.drop_guard();
+        while let Ok(Some(frame)) = codec.read::<ToServer<Message>, _>(&mut reader).await {
+            match frame {
+                ToServer::Message(msg) => {
+                    if sender.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                ToServer::Terminate => {
+                    token.cancel();
+                    break;
+                }
+            }
+        }
+    });
+
+    let closed = connection.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        // an event that can't be encoded is skipped, the
+                        // connection is closed only when the write fails
+                        let Ok(frame) = codec.encode(&ToClient::Event(event)) else {
+                            continue;
+                        };
+                        if write_frame(&mut writer, &frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => closed.cancelled().await,
+                },
+                () = closed.cancelled() => {
+                    if worker.is_cancelled() {
+                        let terminated = ToClient::<TaskMessage>::Terminated;
+                        let _ = codec.write(&mut writer, &terminated).await;
+                    }
+                    break;
+                }
+            }
+        }
+    });
+
+    connection
+}
+
+// How a relay between a local worker and a served one ended.
+pub(crate) enum Relayed {
+    // the connection was lost
+    Disconnected,
+    // the served worker was terminated
+    ServerTerminated,
+    // the local worker was terminated
+    Terminated,
+}
+
+// Relays the messages received from `rx` to the served worker and the events
+// sent by the served worker to `back`, until the connection is lost or one of
+// the two workers is terminated. `pending` is the frame of the message that
+// will be sent first, and the one that was not sent when the connection is
+// lost. The messages that can't be encoded are dead letters of `shared`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn relay<R, W, Message, TaskMessage>(
+    mut reader: R,
+    mut writer: W,
+    codec: Codec,
+    rx: &mut Receiver<Message>,
+    pending: &mut Option<Vec<u8>>,
+    back: Option<&handle::Worker<handle::OneWayBack<TaskMessage>>>,
+    shared: &Shared,
+    token: &CancellationToken,
+) -> Relayed
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send,
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Send + 'static,
+{
+    // frames are read by a dedicated task, because reading a frame can't be
+    // cancelled halfway.
+    let (frames_to_relay, mut frames) = mpsc::channel(BUFFER_CAPACITY);
+    tokio::spawn(async move {
+        while let Ok(Some(frame)) = codec.read::<ToClient<TaskMessage>, _>(&mut reader).await {
+            if frames_to_relay.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        if let Some(frame) = pending.take() {
+            if write_frame(&mut writer, &frame).await.is_err() {
+                *pending = Some(frame);
+                return Relayed::Disconnected;
+            }
+        }
+
+        tokio::select! {
+            // the message is encoded before the write: one that can't be
+            // encoded would never be sent on any connection
+            Some(msg) = rx.recv() => match codec.encode(&ToServer::Message(&msg)) {
+                Ok(frame) => *pending = Some(frame),
+                Err(_) => {
+                    let _ = dead_letter::post(shared, msg, Reason::Unencodable);
+                }
+            },
+            frame = frames.recv() => match frame {
+                Some(ToClient::Event(event)) => {
+                    if let Some(back) = back {
+                        let _ = back.post_message(event).await;
+                    }
+                }
+                Some(ToClient::Terminated) => return Relayed::ServerTerminated,
+                None => return Relayed::Disconnected,
+            },
+            () = token.cancelled() => {
+                let _ = codec.write(&mut writer, &ToServer::<Message>::Terminate).await;
+                return Relayed::Terminated;
+            }
+        }
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// A worker that is the client of a worker served in another process, or
+/// anyway behind a socket. See [`crate::remote`].
+pub struct RemoteWorker<Mode> {
+    // a local worker whose task is the connection to the served worker
+    worker: Worker<Mode>,
+}
+
+impl<Message, TaskMessage> RemoteWorker<TwoWay<Message, TaskMessage>>
+where
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Clone + Send + 'static,
+{
+    /// Creates a remote worker connected to the two-way worker served at
+    /// `endpoint`. The connection is established in background and
+    /// reestablished every time it's lost, until the worker is terminated.
+    pub fn connect(endpoint: Endpoint, codec: Codec) -> RemoteWorker<TwoWay<Message, TaskMessage>> {
+        RemoteWorker {
+            worker: Worker::<TwoWay<Message, TaskMessage>>::spawn(Bridge {
+                endpoint,
+                codec,
+                messages: PhantomData,
+            }),
+        }
+    }
+
+    /// Send message `msg` to the served worker's task.
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error> {
+        self.worker.post_message(msg).await
+    }
+
+    /// Let `task` to subscribe to event messages sent by the served worker's
+    /// task, see [`Worker::on_message()`].
+    pub fn on_message<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<TaskMessage>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        self.worker.on_message(task)
+    }
+
+    /// Returns a receiver of the event messages sent by the served worker's
+    /// task, see [`Worker::subscribe()`].
+    pub fn subscribe(&self) -> EventReceiver<TaskMessage> {
+        self.worker.subscribe()
+    }
+
+    /// Sets the queue collecting the messages that can't be delivered to the
+    /// served worker, see [`Worker::set_dead_letters()`].
+    pub fn set_dead_letters(&self, queue: DeadLetterQueue) {
+        self.worker.set_dead_letters(queue);
+    }
+
+    /// Terminates this worker and the served one.
+    pub fn terminate(self) {
+        self.worker.terminate();
+    }
+}
+
+impl<Message, TaskMessage> Post<Message> for RemoteWorker<TwoWay<Message, TaskMessage>>
+where
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Clone + Send + 'static,
+{
+    fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        RemoteWorker::post_message(self, msg)
+    }
+}
+
+// the task of a remote worker: it moves messages and events between the
+// local worker and the connection to the served one.
+struct Bridge<Message, TaskMessage> {
+    endpoint: Endpoint,
+    codec: Codec,
+    messages: PhantomData<fn(Message) -> TaskMessage>,
+}
+
+impl<Message, TaskMessage> Task for Bridge<Message, TaskMessage>
+where
+    Message: Serialize + Send + 'static,
+    TaskMessage: DeserializeOwned + Clone + Send + 'static,
+{
+    type Handle = handle::Worker<handle::TwoWay<Message, TaskMessage>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = Self::Output> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let (endpoint, codec) = (self.endpoint.clone(), self.codec);
+
+        async move {
+            // the frame of a message that could not be written because the
+            // connection was lost
+            let mut pending = None;
+            let mut delay = MIN_RECONNECT_DELAY;
+
+            loop {
+                let connection = tokio::select! {
+                    connection = endpoint.connect() => connection,
+                    () = hnd.terminated() => break,
+                };
+                let Ok(connection) = connection else {
+                    tokio::select! {
+                        () = sleep(delay) => {}
+                        () = hnd.terminated() => break,
+                    }
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                };
+                let connected = Instant::now();
+
+                let (reader, writer) = tokio::io::split(connection);
+                let token = hnd.termination_token.clone();
+                match relay(
+                    reader,
+                    writer,
+                    codec,
+                    &mut rx,
+                    &mut pending,
+                    Some(&hnd),
+                    &hnd.shared,
+                    &token,
+                )
+                .await
+                {
+                    Relayed::Disconnected => {
+                        // the backoff restarts only after a connection that
+                        // lasted, not to reconnect in a tight loop to a
+                        // server that drops the connections
+                        if connected.elapsed() > MAX_RECONNECT_DELAY {
+                            delay = MIN_RECONNECT_DELAY;
+                        }
+                        tokio::select! {
+                            () = sleep(delay) => {}
+                            () = hnd.terminated() => break,
+                        }
+                        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    }
+                    Relayed::ServerTerminated => {
+                        hnd.terminate();
+                        break;
+                    }
+                    Relayed::Terminated => break,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Retrying the messages that could not be posted.
+//!
+//! A [`Retrying`] wraps a worker that messages can be posted to, see
+//! [`Post`], and posts every message again when it fails with a transient
+//! error, waiting between the attempts as told by its [`RetryPolicy`]:
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{
+//! #     circuit::{CircuitBreaker, CircuitPolicy},
+//! #     handle,
+//! #     retry::{RetryPolicy, Retrying},
+//! #     worker::OneWay,
+//! #     Error, Task, Worker,
+//! # };
+//! # #[derive(Clone)]
+//! # pub struct Call {}
+//! # pub struct ApiTask {}
+//! # impl Task for ApiTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Call>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move { while rx.recv().await.is_some() {} }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! # let api_worker = Worker::<OneWay<Call>>::spawn(ApiTask {});
+//! # let call = Call {};
+//! let policy = RetryPolicy::exponential(Duration::from_millis(10), Duration::from_secs(1))
+//!     .with_jitter(Duration::from_millis(10))
+//!     .with_deadline(Duration::from_secs(5));
+//! let api = Retrying::new(CircuitBreaker::new(api_worker, CircuitPolicy::new()), policy);
+//! api.post_message(call).await?;
+//! # Ok(())
+//! # }
+//!```
+//!
+//! The messages must be `Clone`, because every attempt takes its own copy.
+//! By default [`ErrorKind::RateLimited`], [`ErrorKind::CircuitOpen`] and
+//! [`ErrorKind::Restarting`] are transient, see
+//! [`RetryPolicy::with_retry_on()`].
+//!
+//! The task of a terminated worker is never restarted, so posting to it again
+//! is useless. To not lose the messages while a task is being restarted, post
+//! them to a [`Supervisor`]: while the task restarts the posts fail with
+//! [`ErrorKind::Restarting`], and they are tried again until the new task
+//! receives them:
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{
+//! #     handle,
+//! #     retry::{RetryPolicy, Retrying},
+//! #     supervisor::Supervisor,
+//! #     worker::OneWay,
+//! #     Error, Task, Worker,
+//! # };
+//! # #[derive(Clone, Debug)]
+//! # pub struct Order {
+//! #     id: u64,
+//! # }
+//! # pub struct OrderTask {}
+//! # impl Task for OrderTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Order>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(order) = rx.recv().await {
+//! #                 println!("{order:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! # let order = Order { id: 42 };
+//! let orders = Retrying::new(
+//!     Supervisor::spawn(|| Worker::<OneWay<Order>>::spawn(OrderTask {})),
+//!     RetryPolicy::fixed(Duration::from_millis(20)).with_deadline(Duration::from_secs(1)),
+//! );
+//! orders.post_message(order).await?;
+//! # Ok(())
+//! # }
+//!```
+//!
+//! [`Supervisor`]: crate::supervisor::Supervisor
+
+use std::{future::Future, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::{runtime::sleep_until, schedule::jitter, worker::Post, Error, ErrorKind};
+
+/// How long to wait before the next attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// Always the same delay.
+    Fixed(Duration),
+    /// A delay doubling at every attempt, from `initial` up to `max`.
+    Exponential {
+        /// The delay before the second attempt.
+        initial: Duration,
+        /// The longest delay.
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    // Returns how long to wait after the failed `attempt`, counting from 1.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => initial
+                .checked_mul(2u32.saturating_pow(attempt.max(1) - 1))
+                .map_or(max, |delay| delay.min(max)),
+        }
+    }
+
+    // Returns the longest delay.
+    pub(crate) fn longest(&self) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { max, .. } => max,
+        }
+    }
+}
+
+/// When and how many times a message is posted again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    jitter: Duration,
+    // if not given, 3 without a deadline and unlimited with it
+    max_attempts: Option<u32>,
+    deadline: Option<Duration>,
+    retry_on: Vec<ErrorKind>,
+}
+
+impl RetryPolicy {
+    /// Attempts separated by the same `delay`.
+    ///
+    /// Without [`Self::with_max_attempts()`] there are at most 3 attempts,
+    /// or as many as fit within [`Self::with_deadline()`].
+    pub fn fixed(delay: Duration) -> RetryPolicy {
+        RetryPolicy::new(Backoff::Fixed(delay))
+    }
+
+    /// Attempts separated by a delay doubling from `initial` up to `max`.
+    pub fn exponential(initial: Duration, max: Duration) -> RetryPolicy {
+        RetryPolicy::new(Backoff::Exponential { initial, max })
+    }
+
+    /// Attempts separated as told by `backoff`.
+    pub fn new(backoff: Backoff) -> RetryPolicy {
+        RetryPolicy {
+            backoff,
+            jitter: Duration::ZERO,
+            max_attempts: None,
+            deadline: None,
+            retry_on: vec![
+                ErrorKind::RateLimited,
+                ErrorKind::CircuitOpen,
+                ErrorKind::Restarting,
+            ],
+        }
+    }
+
+    /// Delays every attempt by a random amount of time up to `jitter`, so
+    /// that many callers don't retry all together.
+    pub fn with_jitter(mut self, jitter: Duration) -> RetryPolicy {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the maximum number of attempts, the first one included.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> RetryPolicy {
+        self.max_attempts = Some(max_attempts.max(1));
+        self
+    }
+
+    /// Gives up when the next attempt would start later than `deadline`
+    /// after the first one.
+    pub fn with_deadline(mut self, deadline: Duration) -> RetryPolicy {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the kinds of the errors that are worth another attempt, by
+    /// default [`ErrorKind::RateLimited`], [`ErrorKind::CircuitOpen`] and
+    /// [`ErrorKind::Restarting`].
+    pub fn with_retry_on(mut self, kinds: impl IntoIterator<Item = ErrorKind>) -> RetryPolicy {
+        self.retry_on = kinds.into_iter().collect();
+        self
+    }
+
+    fn max_attempts(&self) -> u32 {
+        match (self.max_attempts, self.deadline) {
+            (Some(max_attempts), _) => max_attempts,
+            (None, Some(_)) => u32::MAX,
+            (None, None) => 3,
+        }
+    }
+
+    // Returns how long to wait after the failed `attempt`, counting from 1.
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.delay(attempt) + jitter(self.jitter)
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// Wraps `worker`, posting the messages again when they fail. See the module
+/// documentation.
+pub struct Retrying<W> {
+    worker: W,
+    policy: RetryPolicy,
+}
+
+impl<W> Retrying<W> {
+    /// Wraps `worker`, retrying as told by `policy`.
+    pub fn new(worker: W, policy: RetryPolicy) -> Retrying<W> {
+        Retrying { worker, policy }
+    }
+
+    /// Send message `msg` to the worker, trying again when it fails with a
+    /// transient error. Returns the error of the last attempt when giving up.
+    pub async fn post_message<Message: Clone>(&self, msg: Message) -> Result<(), Error>
+    where
+        W: Post<Message>,
+    {
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let error = match self.worker.post_message(msg.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if attempt >= self.policy.max_attempts()
+                || !self.policy.retry_on.contains(&error.kind())
+            {
+                return Err(error);
+            }
+
+            let next = Instant::now() + self.policy.delay(attempt);
+            if self
+                .policy
+                .deadline
+                .is_some_and(|deadline| next > start + deadline)
+            {
+                return Err(error);
+            }
+            sleep_until(next).await;
+            attempt += 1;
+        }
+    }
+
+    /// Returns the wrapped worker.
+    pub fn worker(&self) -> &W {
+        &self.worker
+    }
+
+    /// Unwraps the worker.
+    pub fn into_worker(self) -> W {
+        self.worker
+    }
+}
+
+impl<W, Message> Post<Message> for Retrying<W>
+where
+    W: Post<Message> + Sync,
+    Message: Clone + Send,
+{
+    fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        Retrying::post_message(self, msg)
+    }
+
+    fn error(&self, kind: ErrorKind, cause: &str) -> Error {
+        self.worker.error(kind, cause)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    // Fails the first `fails` posts with `kind`, recording when every post
+    // was attempted.
+    struct Flaky {
+        fails: usize,
+        kind: ErrorKind,
+        attempts: Mutex<Vec<Instant>>,
+    }
+
+    impl Flaky {
+        fn new(fails: usize, kind: ErrorKind) -> Flaky {
+            Flaky {
+                fails,
+                kind,
+                attempts: Mutex::new(Vec::new()),
+            }
+        }
+
+        // Returns the delays between the attempts, in milliseconds.
+        fn delays(&self) -> Vec<u128> {
+            let attempts = self.attempts.lock().unwrap();
+            attempts
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).as_millis())
+                .collect()
+        }
+    }
+
+    impl Post<u32> for Flaky {
+        async fn post_message(&self, _msg: u32) -> Result<(), Error> {
+            let mut attempts = self.attempts.lock().unwrap();
+            attempts.push(Instant::now());
+            if attempts.len() <= self.fails {
+                return Err(Error::new(self.kind, "flaky"));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fixed_backoff() {
+        let retrying = Retrying::new(
+            Flaky::new(3, ErrorKind::RateLimited),
+            RetryPolicy::fixed(Duration::from_millis(100)).with_max_attempts(5),
+        );
+
+        retrying.post_message(1).await.unwrap();
+        assert_eq!(retrying.worker().delays(), [100, 100, 100]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn exponential_backoff() {
+        let retrying = Retrying::new(
+            Flaky::new(5, ErrorKind::CircuitOpen),
+            RetryPolicy::exponential(Duration::from_millis(10), Duration::from_millis(40))
+                .with_max_attempts(6),
+        );
+
+        retrying.post_message(1).await.unwrap();
+        assert_eq!(retrying.worker().delays(), [10, 20, 40, 40, 40]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let retrying = Retrying::new(
+            Flaky::new(10, ErrorKind::Restarting),
+            RetryPolicy::fixed(Duration::from_millis(10)).with_max_attempts(4),
+        );
+
+        let e = retrying.post_message(1).await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Restarting);
+        assert_eq!(retrying.worker().delays(), [10, 10, 10]);
+
+        // 3 attempts without a deadline or a maximum
+        let retrying = Retrying::new(
+            Flaky::new(10, ErrorKind::Restarting),
+            RetryPolicy::fixed(Duration::from_millis(10)),
+        );
+        assert!(retrying.post_message(1).await.is_err());
+        assert_eq!(retrying.worker().delays(), [10, 10]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_at_the_deadline() {
+        let retrying = Retrying::new(
+            Flaky::new(100, ErrorKind::RateLimited),
+            RetryPolicy::fixed(Duration::from_millis(100))
+                .with_deadline(Duration::from_millis(250)),
+        );
+
+        let start = Instant::now();
+        assert!(retrying.post_message(1).await.is_err());
+        assert_eq!(retrying.worker().delays(), [100, 100]);
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn doesnt_retry_the_other_errors() {
+        let retrying = Retrying::new(
+            Flaky::new(1, ErrorKind::Terminated),
+            RetryPolicy::fixed(Duration::from_millis(10)),
+        );
+
+        let e = retrying.post_message(1).await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Terminated);
+        assert!(retrying.worker().delays().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn jitter_delays_up_to_the_limit() {
+        let retrying = Retrying::new(
+            Flaky::new(20, ErrorKind::RateLimited),
+            RetryPolicy::fixed(Duration::from_millis(100))
+                .with_jitter(Duration::from_millis(50))
+                .with_max_attempts(21),
+        );
+
+        retrying.post_message(1).await.unwrap();
+        let delays = retrying.worker().delays();
+        assert!(delays.iter().all(|delay| (100..=150).contains(delay)));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+}
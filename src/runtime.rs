@@ -0,0 +1,173 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! The async runtime running the tasks and the timers of the workers.
+//!
+//! By default the tasks are spawned on the current [`Tokio`] runtime. Another
+//! runtime can be set, once and before spawning any worker, with
+//! [`set_global()`]. With the `smol` feature the tasks can run on an
+//! `async-executor`, the executor of smol:
+//!
+//!```rust
+//! # #[cfg(feature = "smol")]
+//! # fn main() -> std::result::Result<(), opifex::Error> {
+//! # use std::sync::Arc;
+//! # use async_executor::Executor;
+//! # use opifex::{runtime::{self, Smol}, worker::TwoWay, Worker};
+//! # use std::future::Future;
+//! # use opifex::{handle, Task};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Sum {
+//! #     a: i32,
+//! #     b: i32,
+//! # }
+//! # #[derive(Clone, Debug)]
+//! # pub struct Result {
+//! #     sum: i32,
+//! # }
+//! # pub struct Adder {}
+//! # impl Task for Adder {
+//! #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(Sum { a, b }) = rx.recv().await {
+//! #                 let _ = hnd.post_message(Result { sum: a + b }).await;
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # pub struct Response {}
+//! # impl Task for Response {
+//! #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(result) = rx.recv().await {
+//! #                 println!("{result:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! let executor = Arc::new(Executor::new());
+//! runtime::set_global(Smol::new(executor.clone()))?;
+//!
+//! async_io::block_on(executor.run(async {
+//!     let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {});
+//!     let response_worker = adder_worker.on_message(Response {});
+//!     adder_worker.post_message(Sum { a: 24, b: 28 }).await
+//! }))?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "smol"))]
+//! # fn main() {}
+//!```
+//!
+//! The channels of opifex come from `tokio::sync`, that works with any
+//! runtime; the mailboxes of the workers can use other channels too, see
+//! [`crate::mailbox`]. The remote, process and durable workers still need a
+//! tokio runtime, for their sockets, child processes and files.
+
+use std::{future::Future, pin::Pin, sync::OnceLock};
+
+use tokio::time::Instant;
+
+use crate::{Error, ErrorKind};
+
+/// A boxed future, as spawned by a [`Runtime`].
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs the tasks and the timers of the workers.
+pub trait Runtime: Send + Sync + 'static {
+    /// Runs `future` in background.
+    fn spawn(&self, future: BoxFuture);
+
+    /// Returns a future completing at `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture;
+}
+
+// the runtime used instead of tokio, if any
+static GLOBAL: OnceLock<Box<dyn Runtime>> = OnceLock::new();
+
+/// Sets the runtime used by all the workers. It can be set only once, and
+/// before spawning any worker: the workers spawned before keep running on
+/// tokio.
+pub fn set_global(runtime: impl Runtime) -> Result<(), Error> {
+    GLOBAL
+        .set(Box::new(runtime))
+        .map_err(|_| Error::new(ErrorKind::Other, "runtime already set"))
+}
+
+// Spawns `future` on the global runtime.
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match GLOBAL.get() {
+        Some(runtime) => runtime.spawn(Box::pin(async move {
+            future.await;
+        })),
+        None => {
+            tokio::spawn(future);
+        }
+    }
+}
+
+// Completes at `deadline`, following the timers of the global runtime.
+pub(crate) async fn sleep_until(deadline: Instant) {
+    match GLOBAL.get() {
+        Some(runtime) => runtime.sleep_until(deadline).await,
+        None => tokio::time::sleep_until(deadline).await,
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+/// The default runtime: the tokio one the workers are spawned from.
+pub struct Tokio;
+
+impl Runtime for Tokio {
+    fn spawn(&self, future: BoxFuture) {
+        tokio::spawn(future);
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// Runs the tasks on an [`async_executor::Executor`], like the one of smol,
+/// with the timers of `async-io`.
+#[cfg(feature = "smol")]
+pub struct Smol {
+    executor: std::sync::Arc<async_executor::Executor<'static>>,
+}
+
+#[cfg(feature = "smol")]
+impl Smol {
+    /// Spawns the tasks on `executor`, that must be run by the application.
+    pub fn new(executor: std::sync::Arc<async_executor::Executor<'static>>) -> Smol {
+        Smol { executor }
+    }
+}
+
+#[cfg(feature = "smol")]
+impl Runtime for Smol {
+    fn spawn(&self, future: BoxFuture) {
+        self.executor.spawn(future).detach();
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture {
+        let timer = async_io::Timer::at(deadline.into_std());
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+}
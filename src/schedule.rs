@@ -0,0 +1,287 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Schedules used by [`Worker<Scheduled>`] to decide when its task has to be
+//! run.
+//!
+//! A schedule can be:
+//! * an interval: runs start every `period`, the first one immediately;
+//! * a fixed delay: a run starts `delay` after the end of the previous one,
+//!   the first one immediately;
+//! * a cron expression (with the `cron` feature), e.g. `"0 */5 * * * *"`.
+//!
+//! [`Worker<Scheduled>`]: crate::worker::Worker
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use tokio::time::Instant;
+
+use crate::{
+    control::{self, Control},
+    handle,
+    runtime::sleep_until,
+    Task,
+};
+
+#[cfg(feature = "cron")]
+use crate::Error;
+
+/// What to do when one or more runs were missed, because the previous run
+/// took too long.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissedTick {
+    /// Run immediately as many times as the missed runs, then continue with
+    /// the original schedule.
+    Burst,
+    /// Run immediately once, then continue from now on.
+    Delay,
+    /// Forget the missed runs and wait for the next one of the original
+    /// schedule.
+    #[default]
+    Skip,
+}
+
+enum Kind {
+    Interval(Duration),
+    FixedDelay(Duration),
+    #[cfg(feature = "cron")]
+    Cron(Box<cron::Schedule>),
+}
+
+/// When and how often a scheduled task is run.
+pub struct Schedule {
+    kind: Kind,
+    jitter: Duration,
+    missed_tick: MissedTick,
+}
+
+impl Schedule {
+    /// Runs the task every `period`.
+    pub fn interval(period: Duration) -> Schedule {
+        Self::new(Kind::Interval(period))
+    }
+
+    /// Runs the task waiting `delay` between the end of a run and the start
+    /// of the following one.
+    pub fn fixed_delay(delay: Duration) -> Schedule {
+        Self::new(Kind::FixedDelay(delay))
+    }
+
+    /// Runs the task following the cron `expression` (in UTC), in the format
+    /// accepted by the [cron](https://docs.rs/cron) crate.
+    #[cfg(feature = "cron")]
+    pub fn cron(expression: &str) -> Result<Schedule, Error> {
+        expression
+            .parse::<cron::Schedule>()
+            .map(|schedule| Self::new(Kind::Cron(Box::new(schedule))))
+            .map_err(|e| Error::from(&e))
+    }
+
+    /// Delays every run by a random amount of time up to `jitter`.
+    pub fn with_jitter(mut self, jitter: Duration) -> Schedule {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the behavior used when runs are missed, by default they are skipped.
+    pub fn with_missed_tick(mut self, missed_tick: MissedTick) -> Schedule {
+        self.missed_tick = missed_tick;
+        self
+    }
+
+    fn new(kind: Kind) -> Schedule {
+        Schedule {
+            kind,
+            jitter: Duration::ZERO,
+            missed_tick: MissedTick::default(),
+        }
+    }
+
+    // Returns when the run following the one scheduled at `last` has to start.
+    fn next(&self, last: Instant) -> Option<Instant> {
+        let now = Instant::now();
+
+        match &self.kind {
+            Kind::Interval(period) => {
+                let next = last + *period;
+                if next >= now {
+                    return Some(next);
+                }
+                Some(match self.missed_tick {
+                    MissedTick::Burst => next,
+                    MissedTick::Delay => now,
+                    MissedTick::Skip => {
+                        let missed = (now - next).as_nanos() / period.as_nanos().max(1);
+                        next + *period * u32::try_from(missed + 1).unwrap_or(u32::MAX)
+                    }
+                })
+            }
+            Kind::FixedDelay(delay) => Some(now + *delay),
+            #[cfg(feature = "cron")]
+            Kind::Cron(schedule) => {
+                use chrono::Utc;
+
+                let wall = Utc::now();
+                let at = |instant: Instant| {
+                    wall - chrono::Duration::from_std(now.saturating_duration_since(instant))
+                        .unwrap_or_default()
+                };
+                let next = schedule.after(&at(last)).next()?;
+                let next = if next > wall {
+                    next
+                } else {
+                    match self.missed_tick {
+                        MissedTick::Burst => next,
+                        MissedTick::Delay => wall,
+                        MissedTick::Skip => schedule.after(&wall).next()?,
+                    }
+                };
+                Some(now + (next - wall).to_std().unwrap_or_default())
+            }
+        }
+    }
+
+    // Returns when the run scheduled at `at`, missed while the worker was
+    // paused, has to start once it's resumed: now, or the next run of the
+    // original schedule when the missed runs are skipped. No more than one
+    // missed run is ever made up for.
+    fn resumed(&self, at: Instant) -> Option<Instant> {
+        let now = Instant::now();
+        if self.missed_tick != MissedTick::Skip {
+            return Some(now);
+        }
+
+        match &self.kind {
+            Kind::Interval(period) => {
+                let missed = (now - at).as_nanos() / period.as_nanos().max(1);
+                Some(at + *period * u32::try_from(missed + 1).unwrap_or(u32::MAX))
+            }
+            Kind::FixedDelay(_) => Some(now),
+            #[cfg(feature = "cron")]
+            Kind::Cron(_) => self.first(),
+        }
+    }
+
+    // Returns when the first run has to start.
+    fn first(&self) -> Option<Instant> {
+        match &self.kind {
+            #[cfg(feature = "cron")]
+            Kind::Cron(schedule) => {
+                let wall = chrono::Utc::now();
+                let next = schedule.after(&wall).next()?;
+                Some(Instant::now() + (next - wall).to_std().unwrap_or_default())
+            }
+            _ => Some(Instant::now()),
+        }
+    }
+}
+
+// Returns a random duration between zero and `max`.
+pub(crate) fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    // the hasher keys are random, the counter and the clock make every call
+    // hash something different.
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(CALLS.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        SystemTime::UNIX_EPOCH
+            .elapsed()
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    max.mul_f64(hasher.finish() as f64 / u64::MAX as f64)
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// What a scheduled worker can see of its task.
+pub(crate) struct State<Output> {
+    pub(crate) next_run: Option<std::time::Instant>,
+    pub(crate) last_result: Option<Output>,
+}
+
+impl<Output> Default for State<Output> {
+    fn default() -> Self {
+        State {
+            next_run: None,
+            last_result: None,
+        }
+    }
+}
+
+// Runs `task` following `schedule` until the worker linked by `link` is
+// terminated, handling the control commands sent by the worker.
+pub(crate) async fn run<T>(
+    task: T,
+    schedule: Schedule,
+    link: handle::Link<()>,
+    state: Arc<Mutex<State<T::Output>>>,
+) where
+    T: Task<Handle = handle::Worker<handle::Isolated>>,
+{
+    let handle::Link {
+        token,
+        mut control_from_wk,
+        shared,
+    } = link;
+
+    let mut paused = false;
+    let mut next = schedule
+        .first()
+        .map(|at| (at, at + jitter(schedule.jitter)));
+
+    while let Some((at, deadline)) = next {
+        state.lock().unwrap_or_else(|e| e.into_inner()).next_run =
+            (!paused).then(|| deadline.into_std());
+
+        tokio::select! {
+            () = sleep_until(deadline), if !paused => {}
+            Some(cmd) = control_from_wk.recv() => match cmd {
+                Control::Flush => {}
+                Control::Pause => { paused = true; continue; }
+                Control::Resume => {
+                    // the runs missed while paused are not made up for
+                    if paused && deadline < Instant::now() {
+                        next = schedule
+                            .resumed(at)
+                            .map(|at| (at, at + jitter(schedule.jitter)));
+                    }
+                    paused = false;
+                    continue;
+                }
+                Control::Custom(()) => continue,
+            },
+            () = token.cancelled() => break,
+        }
+
+        state.lock().unwrap_or_else(|e| e.into_inner()).next_run = None;
+
+        // every run gets its own handle, that can terminate the worker
+        let wkh = handle::Worker::isolated(control::detached(token.clone(), shared.clone()));
+
+        let output = tokio::select! {
+            output = task.spawn(wkh) => output,
+            () = token.cancelled() => break,
+        };
+
+        state.lock().unwrap_or_else(|e| e.into_inner()).last_result = Some(output);
+        next = schedule
+            .next(at)
+            .map(|at| (at, at + jitter(schedule.jitter)));
+    }
+}
@@ -0,0 +1,135 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Requests answered with a stream of responses.
+//!
+//! A worker in [`crate::worker::Streaming`] mode sends every request to its
+//! task together with a [`Responder`], and returns to the caller a dedicated
+//! [`ResponseStream`]. The task sends the responses with the responder and
+//! closes the stream dropping it:
+//!
+//!```rust
+//! # use std::future::Future;
+//! # use opifex::{handle, streaming::Request, worker::Streaming, Error, Task, Worker};
+//! # pub struct Query {
+//! #     pattern: String,
+//! # }
+//! # fn search(pattern: String) -> Vec<String> {
+//! #     vec![format!("found {pattern}")]
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! # let worker = Worker::<Streaming<Query, String>>::spawn(Search {});
+//! # let pattern = "opifex".to_string();
+//! // caller side
+//! let mut lines = worker.ask(Query { pattern }).await?;
+//! while let Some(line) = lines.recv().await {
+//!     println!("{line}");
+//! }
+//! # Ok(())
+//! # }
+//! # pub struct Search {}
+//! # impl Task for Search {
+//! #     type Handle = handle::Worker<handle::Streaming<Query, String>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             loop {
+//! #                 tokio::select! {
+//!
+//! // task side
+//! Some(Request { message, responder }) = rx.recv() => {
+//!     tokio::spawn(async move {
+//!         for line in search(message.pattern) {
+//!             if responder.send(line).await.is_err() {
+//!                 // the caller has dropped the stream
+//!                 break;
+//!             }
+//!         }
+//!     });
+//! }
+//! #                     () = hnd.terminated() => break,
+//! #                 }
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//!```
+//!
+//! Every stream has its own bounded buffer: a task sending faster than the
+//! caller receives waits, without slowing down the other streams.
+
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::Error;
+
+// the buffer of a response stream, if not given
+pub(crate) const STREAM_CAPACITY: usize = 32;
+
+/// A request received by the task of a streaming worker.
+pub struct Request<Req, Resp> {
+    /// The request.
+    pub message: Req,
+    /// Used to send the responses; the stream is closed when it's dropped.
+    pub responder: Responder<Resp>,
+}
+
+/// Sends the responses to a request.
+pub struct Responder<Resp> {
+    sender: Sender<Resp>,
+}
+
+impl<Resp> Responder<Resp> {
+    /// Sends `resp`, waiting for room in the stream buffer. Fails when the
+    /// caller has dropped the stream.
+    pub async fn send(&self, resp: Resp) -> Result<(), Error> {
+        self.sender.send(resp).await.map_err(|e| Error::from(&e))
+    }
+
+    /// Returns when the caller has dropped the stream, so that the task can
+    /// stop working on the request.
+    pub async fn cancelled(&self) {
+        self.sender.closed().await
+    }
+
+    /// Returns true when the caller has dropped the stream.
+    pub fn is_cancelled(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+/// The responses to a request, closed when the task drops the responder.
+/// Dropping the stream cancels the request, see [`Responder::cancelled()`].
+pub struct ResponseStream<Resp> {
+    pub(crate) receiver: Receiver<Resp>,
+}
+
+impl<Resp> ResponseStream<Resp> {
+    /// Receives the next response, returns `None` when the stream is closed.
+    ///
+    /// This function is cancel safe.
+    pub async fn recv(&mut self) -> Option<Resp> {
+        self.receiver.recv().await
+    }
+}
+
+// Creates a request and the stream of its responses.
+pub(crate) fn request<Req, Resp>(
+    message: Req,
+    capacity: usize,
+) -> (Request<Req, Resp>, ResponseStream<Resp>) {
+    let (sender, receiver) = tokio::sync::mpsc::channel(capacity.max(1));
+
+    (
+        Request {
+            message,
+            responder: Responder { sender },
+        },
+        ResponseStream { receiver },
+    )
+}
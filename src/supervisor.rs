@@ -0,0 +1,225 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Supervisors restarting the tasks that finish.
+//!
+//! A [`Supervisor`] spawns a worker with a function, and spawns it again
+//! every time its task finishes: when it returns, panics or terminates the
+//! worker. Messages are posted to the supervisor as to its current worker,
+//! see [`Post`]:
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{handle, retry::Backoff, supervisor::Supervisor, worker::OneWay, Error, Task, Worker};
+//! # #[derive(Clone, Debug)]
+//! # pub struct Order {
+//! #     id: u64,
+//! # }
+//! # pub struct OrderTask {}
+//! # impl Task for OrderTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Order>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Some(order) = rx.recv().await {
+//! #                 println!("{order:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! # let order = Order { id: 42 };
+//! let supervisor = Supervisor::spawn_with_backoff(
+//!     || Worker::<OneWay<Order>>::spawn(OrderTask {}),
+//!     Backoff::Exponential {
+//!         initial: Duration::from_millis(10),
+//!         max: Duration::from_secs(1),
+//!     },
+//! );
+//! supervisor.post_message(order).await?;
+//! # Ok(())
+//! # }
+//!```
+//!
+//! While a restart is pending the posts fail with [`ErrorKind::Restarting`],
+//! that a [`crate::retry::Retrying`] tries again by default. The messages
+//! left in the mailbox of the finished task are lost with it, unless the
+//! mailbox is durable, see `durable`.
+//!
+//! Every restart spawns a new worker, with a new identifier: the subscribers
+//! of the events of a task must subscribe again to the new
+//! [`Supervisor::worker()`].
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    retry::Backoff,
+    runtime,
+    worker::{Post, Worker},
+    Error, ErrorKind,
+};
+
+/// Spawns a worker and restarts its task when it finishes. See the module
+/// documentation.
+pub struct Supervisor<Mode, Ctrl = ()> {
+    inner: Arc<Inner<Mode, Ctrl>>,
+}
+
+struct Inner<Mode, Ctrl> {
+    current: RwLock<Arc<Worker<Mode, Ctrl>>>,
+    restarts: AtomicU64,
+    // stops the restarts
+    token: CancellationToken,
+}
+
+impl<Mode, Ctrl> Supervisor<Mode, Ctrl>
+where
+    Mode: Send + Sync + 'static,
+    Ctrl: Send + 'static,
+{
+    /// Spawns a worker with `spawn`, and spawns it again as soon as its task
+    /// finishes.
+    pub fn spawn<F>(spawn: F) -> Supervisor<Mode, Ctrl>
+    where
+        F: Fn() -> Worker<Mode, Ctrl> + Send + Sync + 'static,
+    {
+        Supervisor::spawn_with_backoff(spawn, Backoff::Fixed(Duration::ZERO))
+    }
+
+    /// Like [`Self::spawn()`] but waits before every restart as told by
+    /// `backoff`. The delay grows with the consecutive restarts, and starts
+    /// again from the first one when a task lived longer than the longest
+    /// delay.
+    pub fn spawn_with_backoff<F>(spawn: F, backoff: Backoff) -> Supervisor<Mode, Ctrl>
+    where
+        F: Fn() -> Worker<Mode, Ctrl> + Send + Sync + 'static,
+    {
+        let inner = Arc::new(Inner {
+            current: RwLock::new(Arc::new(spawn())),
+            restarts: AtomicU64::new(0),
+            token: CancellationToken::new(),
+        });
+
+        runtime::spawn(inner.clone().supervise(spawn, backoff));
+
+        Supervisor { inner }
+    }
+}
+
+impl<Mode, Ctrl> Supervisor<Mode, Ctrl> {
+    /// Returns the current worker.
+    pub fn worker(&self) -> Arc<Worker<Mode, Ctrl>> {
+        self.inner.current()
+    }
+
+    /// Returns how many times the task has been restarted.
+    pub fn restarts(&self) -> u64 {
+        self.inner.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Send message `msg` to the current worker. Fails with
+    /// [`ErrorKind::Restarting`] when its task has finished and the next one
+    /// is not spawned yet.
+    pub async fn post_message<Message>(&self, msg: Message) -> Result<(), Error>
+    where
+        Worker<Mode, Ctrl>: Post<Message>,
+    {
+        let worker = self.worker();
+        worker.post_message(msg).await.map_err(|e| {
+            if e.kind() == ErrorKind::Terminated && !self.inner.token.is_cancelled() {
+                worker
+                    .shared
+                    .error(ErrorKind::Restarting, "task restarting")
+            } else {
+                e
+            }
+        })
+    }
+
+    /// Stops the restarts and terminates the current worker.
+    pub fn terminate(self) {
+        // the restarts stop before the task finishes
+        self.inner.token.cancel();
+        self.worker().termination_token.cancel();
+    }
+}
+
+impl<Mode, Ctrl> Drop for Supervisor<Mode, Ctrl> {
+    // stops the restarts, leaving the current worker alone as dropping a
+    // worker does
+    fn drop(&mut self) {
+        self.inner.token.cancel();
+    }
+}
+
+impl<Mode, Ctrl, Message> Post<Message> for Supervisor<Mode, Ctrl>
+where
+    Worker<Mode, Ctrl>: Post<Message> + Send + Sync,
+    Message: Send,
+{
+    fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send {
+        Supervisor::post_message(self, msg)
+    }
+
+    fn error(&self, kind: ErrorKind, cause: &str) -> Error {
+        self.worker().error(kind, cause)
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Mode, Ctrl> Inner<Mode, Ctrl> {
+    fn current(&self) -> Arc<Worker<Mode, Ctrl>> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // Spawns the worker again with `spawn` when its task finishes, until the
+    // supervisor is dropped or terminated.
+    async fn supervise<F>(self: Arc<Self>, spawn: F, backoff: Backoff)
+    where
+        F: Fn() -> Worker<Mode, Ctrl>,
+    {
+        let mut started = Instant::now();
+        let mut attempt = 0;
+        loop {
+            let worker = self.current();
+            tokio::select! {
+                () = worker.finished() => {}
+                () = self.token.cancelled() => break,
+            }
+
+            attempt = if started.elapsed() > backoff.longest() {
+                1
+            } else {
+                attempt + 1
+            };
+            tokio::select! {
+                () = runtime::sleep_until(Instant::now() + backoff.delay(attempt)) => {}
+                () = self.token.cancelled() => break,
+            }
+
+            started = Instant::now();
+            *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(spawn());
+            self.restarts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Watchdogs detecting the tasks stuck on a message.
+//!
+//! The task marks the processing of every message with the guard returned by
+//! [`handle::Worker::start_message()`], that ends the processing when dropped.
+//! When a [`Watchdog`] is set on the worker, with
+//! [`Worker::set_watchdog()`], a message taking longer than its deadline
+//! is a [`Stall`]: it is sent to the subscribers, and the worker can cancel
+//! the message or terminate the task, see [`OnStall`].
+//!
+//!```rust
+//! # use std::{future::Future, time::Duration};
+//! # use opifex::{
+//! #     handle,
+//! #     watchdog::{OnStall, Stall, Watchdog},
+//! #     worker::OneWay,
+//! #     Task, Worker,
+//! # };
+//! # pub struct Job {}
+//! # async fn process(_job: Job) {}
+//! # pub struct StallLogger {}
+//! # impl Task for StallLogger {
+//! #     type Handle = handle::Worker<handle::OnEvent<Stall>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, _hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             while let Ok(stall) = rx.recv().await {
+//! #                 println!("{stall:?}");
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! # let worker = Worker::<OneWay<Job>>::spawn(JobTask {});
+//! // worker side
+//! worker.set_watchdog(Watchdog::new(Duration::from_secs(5)).with_on_stall(OnStall::Cancel));
+//! let logger = worker.on_stall(StallLogger {});
+//! # }
+//! # pub struct JobTask {}
+//! # impl Task for JobTask {
+//! #     type Handle = handle::Worker<handle::OneWay<Job>>;
+//! #     type Output = ();
+//! #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+//! #         let (mut rx, hnd) = wk_hnd.receiver();
+//! #         async move {
+//! #             loop {
+//! #                 tokio::select! {
+//!
+//! // task side
+//! Some(msg) = rx.recv() => {
+//!     let guard = hnd.start_message();
+//!     tokio::select! {
+//!         () = process(msg) => {}
+//!         () = guard.cancelled() => println!("gave up {}", guard.sequence()),
+//!     }
+//! }
+//! #                     () = hnd.terminated() => break,
+//! #                 }
+//! #             }
+//! #         }
+//! #     }
+//! # }
+//!```
+//!
+//! With the `futures` feature the message streams of the handles, see
+//! `adapter`, mark the messages by themselves: a message is processed from
+//! when it is returned by the stream until the next one is asked for.
+//!
+//! There is no restart policy: a stuck task still owns its mailbox, and the
+//! tasks are never aborted, they end by themselves when terminated. To
+//! restart a task, spawn its worker with a [`Supervisor`], set the watchdog
+//! in the function spawning it, and use [`OnStall::Terminate`]: the task is
+//! spawned again as soon as the stuck one reacts to the termination.
+//!
+//! [`handle::Worker::start_message()`]: crate::handle::Worker::start_message
+//! [`Supervisor`]: crate::supervisor::Supervisor
+//! [`Worker::set_watchdog()`]: crate::Worker::set_watchdog
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{broadcast, Notify},
+    time::Instant,
+};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+
+use crate::{event::EventReceiver, runtime, worker::WorkerId, BUFFER_CAPACITY};
+
+/// What the worker does when its task is stuck on a message, besides sending
+/// the [`Stall`] to the subscribers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnStall {
+    /// Nothing else.
+    #[default]
+    Notify,
+    /// Cancels the message: the future of [`MessageGuard::cancelled()`] is
+    /// fulfilled, so that the task can give up the message.
+    Cancel,
+    /// Terminates the worker and its task, that a
+    /// [`crate::supervisor::Supervisor`] can restart.
+    Terminate,
+}
+
+/// How long a message can take, and what to do when it takes longer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchdog {
+    deadline: Duration,
+    on_stall: OnStall,
+}
+
+impl Watchdog {
+    /// Every message can take up to `deadline`.
+    pub fn new(deadline: Duration) -> Watchdog {
+        Watchdog {
+            deadline,
+            on_stall: OnStall::default(),
+        }
+    }
+
+    /// Sets what to do when a message takes too long, by default
+    /// [`OnStall::Notify`].
+    pub fn with_on_stall(mut self, on_stall: OnStall) -> Watchdog {
+        self.on_stall = on_stall;
+        self
+    }
+}
+
+/// A message that took longer than the deadline of the watchdog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stall {
+    /// The worker whose task is stuck.
+    pub worker: WorkerId,
+    /// The sequence number of the message, see [`MessageGuard::sequence()`].
+    pub sequence: u64,
+    /// How long the message has been processed so far.
+    pub elapsed: Duration,
+    /// What has been done about it.
+    pub action: OnStall,
+}
+
+/// Marks the processing of a message, until dropped or finished. See the
+/// module documentation.
+pub struct MessageGuard {
+    watch: Arc<Watch>,
+    sequence: u64,
+    cancel: CancellationToken,
+}
+
+impl MessageGuard {
+    /// Returns the sequence number of the message: the first message started
+    /// by the task is 1.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Returns a Future that gets fulfilled when the watchdog cancels the
+    /// message, see [`OnStall::Cancel`].
+    pub fn cancelled(&self) -> WaitForCancellationFuture<'_> {
+        self.cancel.cancelled()
+    }
+
+    /// Ends the processing of the message, like dropping the guard.
+    pub fn finish(self) {}
+}
+
+impl Drop for MessageGuard {
+    fn drop(&mut self) {
+        let mut running = self.watch.lock();
+        if running
+            .as_ref()
+            .is_some_and(|running| running.sequence == self.sequence)
+        {
+            *running = None;
+            self.watch.changed.notify_one();
+        }
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+// The watchdog state shared by a worker and the handle of its task.
+pub(crate) struct Watch {
+    worker: WorkerId,
+    policy: Mutex<Option<Watchdog>>,
+    running: Mutex<Option<Running>>,
+    // wakes up the watching task when a message starts or ends, or the
+    // policy changes
+    changed: Notify,
+    sequence: AtomicU64,
+    stalls: broadcast::Sender<Stall>,
+}
+
+// The message being processed.
+struct Running {
+    sequence: u64,
+    started: Instant,
+    cancel: CancellationToken,
+    // the stall was already reported
+    stalled: bool,
+}
+
+impl Watch {
+    pub(crate) fn new(worker: WorkerId) -> Watch {
+        let (stalls, _) = broadcast::channel(BUFFER_CAPACITY);
+        Watch {
+            worker,
+            policy: Mutex::new(None),
+            running: Mutex::new(None),
+            changed: Notify::new(),
+            sequence: AtomicU64::new(0),
+            stalls,
+        }
+    }
+
+    // Sets the `policy`, starting to watch the task terminated by `token` if
+    // it wasn't yet.
+    pub(crate) fn set(self: &Arc<Self>, policy: Watchdog, token: CancellationToken) {
+        let previous = self
+            .policy
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(policy);
+
+        match previous {
+            None => runtime::spawn(self.clone().run(token)),
+            Some(_) => self.changed.notify_one(),
+        }
+    }
+
+    // Returns true if the running message has stalled.
+    pub(crate) fn stalled(&self) -> bool {
+        self.lock().as_ref().is_some_and(|running| running.stalled)
+    }
+
+    pub(crate) fn subscribe(&self) -> EventReceiver<Stall> {
+        self.stalls.subscribe().into()
+    }
+
+    pub(crate) fn start(self: &Arc<Self>) -> MessageGuard {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancel = CancellationToken::new();
+
+        *self.lock() = Some(Running {
+            sequence,
+            started: Instant::now(),
+            cancel: cancel.clone(),
+            stalled: false,
+        });
+        self.changed.notify_one();
+
+        MessageGuard {
+            watch: self.clone(),
+            sequence,
+            cancel,
+        }
+    }
+
+    // Waits for the running messages to exceed the deadline, until `token`
+    // is cancelled.
+    async fn run(self: Arc<Self>, token: CancellationToken) {
+        loop {
+            let deadline = self.deadline();
+            tokio::select! {
+                () = runtime::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    self.check(&token);
+                }
+                () = self.changed.notified() => {}
+                () = token.cancelled() => break,
+            }
+        }
+    }
+
+    // Returns when the running message, if any and not stalled yet, exceeds
+    // the deadline.
+    fn deadline(&self) -> Option<Instant> {
+        let policy = (*self.policy.lock().unwrap_or_else(|e| e.into_inner()))?;
+        self.lock()
+            .as_ref()
+            .filter(|running| !running.stalled)
+            .map(|running| running.started + policy.deadline)
+    }
+
+    fn check(&self, token: &CancellationToken) {
+        let Some(policy) = *self.policy.lock().unwrap_or_else(|e| e.into_inner()) else {
+            return;
+        };
+
+        let mut running = self.lock();
+        let Some(running) = running.as_mut() else {
+            return;
+        };
+        let elapsed = running.started.elapsed();
+        if running.stalled || elapsed < policy.deadline {
+            return;
+        }
+        running.stalled = true;
+
+        match policy.on_stall {
+            OnStall::Notify => {}
+            OnStall::Cancel => running.cancel.cancel(),
+            OnStall::Terminate => token.cancel(),
+        }
+
+        // nobody may be subscribed
+        let _ = self.stalls.send(Stall {
+            worker: self.worker,
+            sequence: running.sequence,
+            elapsed,
+            action: policy.on_stall,
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Running>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -5,14 +5,60 @@ Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
 Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
 */
 
-use tokio::sync::{broadcast, mpsc::channel};
-use tokio_util::sync::CancellationToken;
+use std::{
+    fmt,
+    future::Future,
+    hash::Hash,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    time::Duration,
+};
 
-use crate::{handle, Error, Task, BUFFER_CAPACITY};
+#[cfg(feature = "process")]
+use tokio::task::JoinHandle;
+use tokio::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        watch,
+    },
+    time::Instant,
+};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+
+#[cfg(feature = "durable")]
+use serde::{de::DeserializeOwned, Serialize};
+
+#[cfg(feature = "durable")]
+use crate::durable::{DurableReceiver, Journal, Log};
+use crate::{
+    coalesce::{self, CoalescingReceiver},
+    control::{self, Control},
+    dead_letter::{self, DeadLetterQueue, Reason},
+    event::{Broadcaster, EventReceiver, Replay},
+    handle::{self, Outlet},
+    health::{self, Health, Heart},
+    mailbox::{self, Mailbox, MailboxSender},
+    priority::{Priority, PriorityReceiver, LANES},
+    rate_limit::{self, Limiter, RateLimit, Throttle},
+    runtime,
+    schedule::{self, Schedule},
+    streaming::{self, ResponseStream, STREAM_CAPACITY},
+    watchdog::{Stall, Watch, Watchdog},
+    Error, ErrorKind, Task, BUFFER_CAPACITY,
+};
 
 // here I use a mod just to keep clean and ordered the file :)
 mod modes {
-    use tokio::sync::{broadcast, mpsc::Sender};
+    use std::sync::{Arc, Mutex};
+
+    use tokio::sync::mpsc::Sender;
+    #[cfg(feature = "futures")]
+    use tokio_util::sync::PollSender;
+
+    use crate::mailbox::{Mailbox, Tokio};
 
     /// This is the Worker’s mode that lets build a worker that is not able to
     /// communicate with the controlled task.
@@ -21,19 +67,91 @@ mod modes {
     /// Worker's mode that has a communication channel between the worker and its
     /// task. In this channel the messages, with type Message, can be send from the
     /// worker to the task using the function [`super::Worker<Mode>::post_message()`].
-    pub struct OneWay<Message> {
+    pub struct OneWay<Message, Mb: Mailbox<Message> = Tokio> {
         // used to send messages toward Task
-        pub(super) sender_to_tsk: Sender<Message>,
+        pub(crate) sender_to_tsk: Mb::Sender,
+        // lazily created when the worker is used as a futures Sink
+        #[cfg(feature = "futures")]
+        pub(crate) poll_sender: Option<PollSender<Message>>,
     }
 
     /// This mode is used when a bidirectional channel is needed between worker
     /// and its task. These messages can have diffent types.
-    pub struct TwoWay<Message, TaskMessage> {
+    pub struct TwoWay<Message, TaskMessage, Mb: Mailbox<Message> = Tokio> {
         // used to send messages toward Task
-        pub(super) sender_to_tsk: Sender<Message>,
+        pub(crate) sender_to_tsk: Mb::Sender,
+        // lazily created when the worker is used as a futures Sink
+        #[cfg(feature = "futures")]
+        pub(crate) poll_sender: Option<PollSender<Message>>,
+        // used by interested tasks to subscribe to messages sent by this worker
+        // controlled task.
+        pub(crate) broadcast_from_tsk: crate::event::Broadcaster<TaskMessage>,
+    }
+
+    /// Worker's mode whose task takes no messages and only sends events, of
+    /// type TaskMessage, to the subscribers: useful for tasks that produce
+    /// data on their own, like a sensor.
+    pub struct Emitter<TaskMessage> {
         // used by interested tasks to subscribe to messages sent by this worker
         // controlled task.
-        pub(super) broadcast_from_tsk: broadcast::Sender<TaskMessage>,
+        pub(crate) broadcast_from_tsk: crate::event::Broadcaster<TaskMessage>,
+    }
+
+    /// Worker's mode like [`OneWay`] but with a priority mailbox: messages are
+    /// sent with a [`crate::priority::Priority`] and the task receives the
+    /// higher priority ones first.
+    pub struct Prioritized<Message> {
+        // used to send messages toward Task, one sender for each priority
+        pub(super) senders_to_tsk: [Sender<Message>; crate::priority::LANES],
+    }
+
+    /// Worker's mode like [`OneWay`] but with a durable mailbox: messages are
+    /// written in a [`crate::durable::Journal`] before being sent to the task,
+    /// and the ones not acknowledged by the task are sent again when a worker
+    /// is spawned on the same journal.
+    #[cfg(feature = "durable")]
+    pub struct Durable<Message> {
+        // used to send messages toward Task
+        pub(super) sender_to_tsk: Sender<crate::durable::Delivery<Message>>,
+        // where messages are written before being sent
+        pub(super) log: Arc<crate::durable::Log>,
+    }
+
+    /// Worker's mode like [`OneWay`] but with a coalescing mailbox: a posted
+    /// message supersedes the pending one with the same key, of type Key.
+    /// See [`crate::coalesce`].
+    pub struct Coalescing<Message, Key> {
+        // shared with the receiver of Task
+        pub(super) mailbox: Arc<crate::coalesce::Mailbox<Message, Key>>,
+    }
+
+    impl<Message, Key> Drop for Coalescing<Message, Key> {
+        fn drop(&mut self) {
+            // the task receives the pending messages and then sees the end
+            self.mailbox.close();
+        }
+    }
+
+    /// Worker's mode whose task answers every request, of type Req, with a
+    /// dedicated stream of responses, of type Resp. See [`crate::streaming`].
+    pub struct Streaming<Req, Resp> {
+        // used to send requests toward Task
+        pub(super) sender_to_tsk: Sender<crate::streaming::Request<Req, Resp>>,
+    }
+
+    /// Worker's mode whose task publishes a state, of type S, rather than
+    /// events: the worker and the subscribers always see the latest value.
+    pub struct State<S> {
+        // used to read the state published by Task
+        pub(super) receiver_from_tsk: tokio::sync::watch::Receiver<S>,
+    }
+
+    /// Worker's mode that runs its task following a [`crate::schedule::Schedule`].
+    /// The worker can't communicate with the task, but it can see when the
+    /// next run will start and the output of the last one.
+    pub struct Scheduled<Output> {
+        // shared with the scheduler that runs the task
+        pub(super) state: Arc<Mutex<crate::schedule::State<Output>>>,
     }
 }
 
@@ -41,6 +159,268 @@ pub use modes::*;
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
+/// The identifier of a worker, unique in the process. The handle of a task
+/// has the same identifier of its worker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorkerId(u64);
+
+impl WorkerId {
+    fn next() -> WorkerId {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        WorkerId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    #[cfg(feature = "admin")]
+    pub(crate) fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for WorkerId {
+    type Err = Error;
+
+    /// Parses an identifier like `#3`, or just `3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix('#')
+            .unwrap_or(s)
+            .parse()
+            .map(WorkerId)
+            .map_err(|e| Error::new(ErrorKind::Other, format!("bad worker id {s}: {e}")))
+    }
+}
+
+impl fmt::Display for WorkerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// What a worker shares with the handle of its task.
+pub(crate) struct Shared {
+    pub(crate) id: WorkerId,
+    // given by the user, see Worker::spawn_named and Worker::with_name
+    name: RwLock<Option<Arc<str>>>,
+    // where the messages that can't be delivered go, see crate::dead_letter
+    pub(crate) dead_letters: RwLock<Option<DeadLetterQueue>>,
+    // set at spawn time, when the posted messages are rate limited
+    pub(crate) rate_limiter: OnceLock<Limiter>,
+    // created on first use, see crate::watchdog
+    watch: OnceLock<Arc<Watch>>,
+    // the heartbeats of the task, see crate::health
+    pub(crate) heart: Heart,
+    // when the worker was spawned
+    pub(crate) started: Instant,
+    // the last command sent was Control::Pause
+    pub(crate) paused: AtomicBool,
+    // set at spawn time, see crate::health::WorkerInfo
+    pub(crate) probe: OnceLock<Probe>,
+    // cancelled when the task has finished, see Shared::track
+    pub(crate) finished: CancellationToken,
+}
+
+impl Shared {
+    pub(crate) fn new(name: Option<Arc<str>>) -> Arc<Shared> {
+        Arc::new(Shared {
+            id: WorkerId::next(),
+            name: RwLock::new(name),
+            dead_letters: RwLock::new(None),
+            rate_limiter: OnceLock::new(),
+            watch: OnceLock::new(),
+            heart: Heart::new(),
+            started: Instant::now(),
+            paused: AtomicBool::new(false),
+            probe: OnceLock::new(),
+            finished: CancellationToken::new(),
+        })
+    }
+
+    // Wraps the `future` of the task, so that the worker knows when it has
+    // finished: returned, panicked or dropped.
+    pub(crate) fn track<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        let finished = self.finished.clone().drop_guard();
+        async move {
+            let _finished = finished;
+            future.await
+        }
+    }
+
+    pub(crate) fn name(&self) -> Option<Arc<str>> {
+        self.name.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    // Returns an error of this worker, saying which one it is.
+    pub(crate) fn error<E: fmt::Display>(&self, kind: ErrorKind, e: E) -> Error {
+        Error::new(kind, format!("{self}: {e}"))
+    }
+
+    // The Debug output of the worker, or of the handle, terminated by
+    // `token`.
+    pub(crate) fn debug(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        token: &CancellationToken,
+    ) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("id", &format_args!("{}", self.id))
+            .field("name", &self.name())
+            .field(
+                "mode",
+                &self.probe.get().map_or("Unknown", |probe| probe.mode),
+            )
+            .field("terminated", &token.is_cancelled())
+            .finish()
+    }
+
+    pub(crate) fn watch(&self) -> &Arc<Watch> {
+        self.watch.get_or_init(|| Arc::new(Watch::new(self.id)))
+    }
+
+    // Returns true if the task is stuck on a message.
+    pub(crate) fn stalled(&self) -> bool {
+        self.watch.get().is_some_and(|watch| watch.stalled())
+    }
+}
+
+impl fmt::Display for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "worker {} ({name})", self.id),
+            None => write!(f, "worker {}", self.id),
+        }
+    }
+}
+
+// A counter of something of a worker, if known.
+pub(crate) type Gauge = Box<dyn Fn() -> Option<usize> + Send + Sync>;
+
+// What a worker tells about itself, besides its health.
+pub(crate) struct Probe {
+    pub(crate) mode: &'static str,
+    // the messages waiting in the mailbox
+    pub(crate) depth: Gauge,
+    // the subscribers of the events sent by the task
+    pub(crate) subscribers: Gauge,
+}
+
+// Implemented by the modes, to build the probe of their workers.
+pub(crate) trait Inspect {
+    fn probe(&self) -> Probe;
+}
+
+fn unknown() -> Gauge {
+    Box::new(|| None)
+}
+
+impl Inspect for Isolated {
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "Isolated",
+            depth: unknown(),
+            subscribers: unknown(),
+        }
+    }
+}
+
+impl<Message: Send + 'static, Mb: Mailbox<Message>> Inspect for OneWay<Message, Mb> {
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "OneWay",
+            depth: Mb::depth(&self.sender_to_tsk),
+            subscribers: unknown(),
+        }
+    }
+}
+
+impl<Message, TaskMessage, Mb> Inspect for TwoWay<Message, TaskMessage, Mb>
+where
+    Message: Send + 'static,
+    TaskMessage: Send + 'static,
+    Mb: Mailbox<Message>,
+{
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "TwoWay",
+            depth: Mb::depth(&self.sender_to_tsk),
+            subscribers: self.broadcast_from_tsk.subscribers(),
+        }
+    }
+}
+
+impl<TaskMessage: Send + 'static> Inspect for Emitter<TaskMessage> {
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "Emitter",
+            depth: unknown(),
+            subscribers: self.broadcast_from_tsk.subscribers(),
+        }
+    }
+}
+
+impl<Message: Send + 'static> Inspect for Prioritized<Message> {
+    fn probe(&self) -> Probe {
+        let lanes: Vec<Gauge> = self.senders_to_tsk.iter().map(mailbox::depth).collect();
+        Probe {
+            mode: "Prioritized",
+            depth: Box::new(move || lanes.iter().map(|lane| lane()).sum()),
+            subscribers: unknown(),
+        }
+    }
+}
+
+#[cfg(feature = "durable")]
+impl<Message: Send + 'static> Inspect for Durable<Message> {
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "Durable",
+            depth: mailbox::depth(&self.sender_to_tsk),
+            subscribers: unknown(),
+        }
+    }
+}
+
+impl<Message: Send + 'static, Key: Send + 'static> Inspect for Coalescing<Message, Key> {
+    fn probe(&self) -> Probe {
+        let mailbox = Arc::downgrade(&self.mailbox);
+        Probe {
+            mode: "Coalescing",
+            depth: Box::new(move || mailbox.upgrade().map(|mailbox| mailbox.len())),
+            subscribers: unknown(),
+        }
+    }
+}
+
+impl<Req: Send + 'static, Resp: Send + 'static> Inspect for Streaming<Req, Resp> {
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "Streaming",
+            depth: mailbox::depth(&self.sender_to_tsk),
+            subscribers: unknown(),
+        }
+    }
+}
+
+impl<S> Inspect for State<S> {
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "State",
+            depth: unknown(),
+            subscribers: unknown(),
+        }
+    }
+}
+
+impl<Output> Inspect for Scheduled<Output> {
+    fn probe(&self) -> Probe {
+        Probe {
+            mode: "Scheduled",
+            depth: unknown(),
+            subscribers: unknown(),
+        }
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
 /// As the Web Workers API, [`Worker`] makes it possible to spawn a new
 /// [`Task`] and, depending of used `Mode`, having some functions that lets
 /// its user to interact with the spawned task.
@@ -57,155 +437,1334 @@ pub use modes::*;
 /// [`Worker`]: Worker<Mode>
 /// [`terminate`]: Worker<Mode>::terminate
 /// [`spawn`]: Worker<Mode>::spawn
-
-pub struct Worker<Mode> {
+pub struct Worker<Mode, Ctrl = ()> {
     // used to terminate Task
-    termination_token: CancellationToken,
+    pub(crate) termination_token: CancellationToken,
+    // used to send control commands toward Task
+    control_to_tsk: Sender<Control<Ctrl>>,
+    // shared with the handle of the Task
+    pub(crate) shared: Arc<Shared>,
     // mode is used to differenziate the Worker's behaviour.
-    mode: Mode,
+    pub(crate) mode: Mode,
 }
 
-impl<Mode> Worker<Mode> {
+impl<Mode, Ctrl> Worker<Mode, Ctrl> {
+    // Creates a worker with the given `mode` and `name` together with the
+    // link that binds it to the handle of its task.
+    pub(crate) fn link(
+        mode: Mode,
+        name: Option<Arc<str>>,
+    ) -> (Worker<Mode, Ctrl>, handle::Link<Ctrl>)
+    where
+        Mode: Inspect,
+    {
+        // This token is used to terminate the worker and its controlled task.
+        let token = CancellationToken::new();
+
+        let shared = Shared::new(name);
+        let _ = shared.probe.set(mode.probe());
+
+        // the channel used by Worker to send control commands to its Task.
+        let (control_to_tsk, link) = control::link(token.clone(), shared.clone());
+
+        (
+            Worker {
+                termination_token: token,
+                control_to_tsk,
+                shared,
+                mode,
+            },
+            link,
+        )
+    }
+
+    /// Returns the identifier of this worker.
+    pub fn id(&self) -> WorkerId {
+        self.shared.id
+    }
+
+    /// Names this worker, replacing the previous name. The name is shown,
+    /// together with the identifier, in the errors and in the `Debug` output
+    /// of the worker and of the handle of its task:
+    ///
+    ///```rust
+    /// # use opifex::{worker::TwoWay, Worker};
+    /// # use std::future::Future;
+    /// # use opifex::{handle, Task};
+    /// # #[derive(Clone, Debug)]
+    /// # pub struct Sum {
+    /// #     a: i32,
+    /// #     b: i32,
+    /// # }
+    /// # #[derive(Clone, Debug)]
+    /// # pub struct Result {
+    /// #     sum: i32,
+    /// # }
+    /// # pub struct Adder {}
+    /// # impl Task for Adder {
+    /// #     type Handle = handle::Worker<handle::TwoWay<Sum, Result>>;
+    /// #     type Output = ();
+    /// #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+    /// #         let (mut rx, hnd) = wk_hnd.receiver();
+    /// #         async move {
+    /// #             while let Some(Sum { a, b }) = rx.recv().await {
+    /// #                 let _ = hnd.post_message(Result { sum: a + b }).await;
+    /// #             }
+    /// #         }
+    /// #     }
+    /// # }
+    /// # pub struct Response {}
+    /// # impl Task for Response {
+    /// #     type Handle = handle::Worker<handle::OnEvent<Result>>;
+    /// #     type Output = ();
+    /// #     fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+    /// #         let (mut rx, _hnd) = wk_hnd.receiver();
+    /// #         async move {
+    /// #             while let Ok(result) = rx.recv().await {
+    /// #                 println!("{result:?}");
+    /// #             }
+    /// #         }
+    /// #     }
+    /// # }
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let adder_worker = Worker::<TwoWay<Sum, Result>>::spawn(Adder {}).with_name("adder");
+    /// # assert_eq!(adder_worker.name().as_deref(), Some("adder"));
+    /// # adder_worker.terminate();
+    /// # }
+    ///```
+    ///
+    /// The task sees the name once it is set, not while [`Task::spawn()`] is
+    /// called: the `spawn_named` constructors, like
+    /// [`Worker::spawn_named()`], name the worker before.
+    ///
+    /// [`Worker::spawn_named()`]: Worker<TwoWay>::spawn_named
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        *self.shared.name.write().unwrap_or_else(|e| e.into_inner()) = Some(name.into().into());
+        self
+    }
+
+    /// Returns the name of this worker, if any.
+    pub fn name(&self) -> Option<Arc<str>> {
+        self.shared.name()
+    }
+
+    /// Sets the queue collecting the messages of this worker that can't be
+    /// delivered, instead of the global one. See [`crate::dead_letter`].
+    pub fn set_dead_letters(&self, queue: DeadLetterQueue) {
+        *self
+            .shared
+            .dead_letters
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Some(queue);
+    }
+
+    /// Sets the `watchdog` detecting when the task is stuck on a message,
+    /// replacing the previous one. See [`crate::watchdog`].
+    pub fn set_watchdog(&self, watchdog: Watchdog) {
+        self.shared
+            .watch()
+            .set(watchdog, self.termination_token.clone());
+    }
+
+    /// Returns a receiver of the stalls detected by the watchdog.
+    pub fn stalls(&self) -> EventReceiver<Stall> {
+        self.shared.watch().subscribe()
+    }
+
+    /// Let `task` to subscribe to the stalls detected by the watchdog, see
+    /// [`Worker::on_message()`].
+    ///
+    /// [`Worker::on_message()`]: Worker<TwoWay>::on_message
+    pub fn on_stall<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<Stall>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Worker::subscriber(task, self.stalls())
+    }
+
+    /// Returns when the task did the last heartbeat, see [`crate::health`].
+    pub fn last_heartbeat(&self) -> Option<Instant> {
+        self.shared.heart.last()
+    }
+
+    /// Sets how long the task can go without a heartbeat before being
+    /// [`health::Status::Stale`].
+    pub fn set_heartbeat_timeout(&self, timeout: Duration) {
+        self.shared.heart.set_timeout(timeout);
+    }
+
+    /// Returns the health of this worker.
+    pub fn health(&self) -> Health {
+        health::check(&self.shared, &self.termination_token)
+    }
+
+    /// Returns a Future that gets fulfilled when the task has finished: it
+    /// returned, it panicked, or it was dropped by the runtime.
+    pub fn finished(&self) -> WaitForCancellationFuture<'_> {
+        self.shared.finished.cancelled()
+    }
+
+    /// Returns true if the task has finished, see [`Self::finished()`].
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.is_cancelled()
+    }
+
     /// Terminates this worker and the related task.
     pub fn terminate(self) {
         self.termination_token.cancel();
     }
-}
 
-// // // // // // // // // // // // // // // // // // // // // // // // // // //
+    /// Asks the task to pause, see [`Control::Pause`]. Like the other
+    /// commands it waits when the task doesn't receive them, see
+    /// [`crate::control`].
+    pub async fn pause(&self) -> Result<(), Error> {
+        self.send_control(Control::Pause).await?;
+        self.shared.paused.store(true, Ordering::Relaxed);
+        Ok(())
+    }
 
-impl Worker<Isolated> {
-    /// Creates an isolated worker that can only terminate the spawned task.
-    pub fn spawn<T>(task: T) -> Worker<Isolated>
+    /// Asks the task to resume, see [`Control::Resume`].
+    pub async fn resume(&self) -> Result<(), Error> {
+        self.send_control(Control::Resume).await?;
+        self.shared.paused.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Asks the task to flush, see [`Control::Flush`].
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.send_control(Control::Flush).await
+    }
+
+    /// Sends the custom control command `cmd` to the task.
+    pub async fn custom(&self, cmd: Ctrl) -> Result<(), Error> {
+        self.send_control(Control::Custom(cmd)).await
+    }
+
+    // Returns a function asking the task to pause, or to resume, without
+    // keeping the control channel open.
+    pub(crate) fn pauser(&self) -> Box<dyn Fn(bool) -> Result<(), Error> + Send + Sync>
     where
-        T: Task<Handle = handle::Worker<handle::Isolated>>,
-        <T as Task>::Output: Send + 'static,
+        Ctrl: Send + 'static,
     {
-        // This token is used to terminate the worker and its controlled task.
-        let token = CancellationToken::new();
+        let control_to_tsk = self.control_to_tsk.downgrade();
+        let shared = Arc::downgrade(&self.shared);
+        let id = self.shared.id;
+        Box::new(move |pause| {
+            let cmd = if pause {
+                Control::Pause
+            } else {
+                Control::Resume
+            };
+            let (Some(control_to_tsk), Some(shared)) = (control_to_tsk.upgrade(), shared.upgrade())
+            else {
+                return Err(Error::new(
+                    ErrorKind::Terminated,
+                    format!("worker {id} gone"),
+                ));
+            };
+            control_to_tsk
+                .try_send(cmd)
+                .map_err(|e| shared.error(ErrorKind::Other, e))?;
 
-        // Worker's handle that will be used by the Task to communicate with
-        // this worker and to terminate both.
-        let wkh = handle::Worker::isolated(token.clone());
+            shared.paused.store(pause, Ordering::Relaxed);
+            Ok(())
+        })
+    }
 
-        // The Task is spawned here
-        tokio::spawn(task.spawn(wkh));
+    async fn send_control(&self, cmd: Control<Ctrl>) -> Result<(), Error> {
+        self.control_to_tsk
+            .send(cmd)
+            .await
+            .map_err(|e| self.shared.error(ErrorKind::Terminated, e))
+    }
+
+    // Applies the rate `limit`, if any, to the messages sent to the Task:
+    // either on this side, or pacing the messages received by the Task.
+    fn limit<Message: Send + 'static>(
+        &self,
+        receiver: Receiver<Message>,
+        limit: Option<RateLimit>,
+    ) -> Receiver<Message> {
+        match limit {
+            None => receiver,
+            Some(limit) if limit.throttle() == Throttle::Smooth => rate_limit::pace(
+                receiver,
+                Limiter::new(limit),
+                self.shared.clone(),
+                self.termination_token.clone(),
+            ),
+            Some(limit) => {
+                let _ = self.shared.rate_limiter.set(Limiter::new(limit));
+                receiver
+            }
+        }
+    }
 
-        Worker {
-            termination_token: token,
-            mode: Isolated {},
+    // Sends `msg` toward the Task with `sender`, within the rate limit if
+    // any. When the Task has terminated the message goes to the dead
+    // letters, if any.
+    async fn deliver<Message: Send + 'static>(
+        &self,
+        sender: &impl MailboxSender<Message>,
+        msg: Message,
+    ) -> Result<(), Error> {
+        if let Some(limiter) = self.shared.rate_limiter.get() {
+            limiter
+                .admit()
+                .await
+                .map_err(|e| self.shared.error(e.kind(), e))?;
         }
+
+        sender.send(msg).await.map_err(|msg| {
+            let _ = dead_letter::post(&self.shared, msg, Reason::Terminated);
+            self.shared.error(ErrorKind::Terminated, "channel closed")
+        })
+    }
+}
+
+impl<Mode, Ctrl> fmt::Debug for Worker<Mode, Ctrl> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.shared.debug(f, &self.termination_token)
     }
 }
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
-impl<Message> Worker<OneWay<Message>> {
-    /// Creates a worker that is able to send messages to its controlled `task`.
-    pub fn spawn<T>(task: T) -> Worker<OneWay<Message>>
+/// Implemented by the workers that messages, of type Message, can be posted
+/// to, so that wrappers like [`crate::circuit::CircuitBreaker`] can be used
+/// with any of them.
+pub trait Post<Message> {
+    /// Send message `msg`, like the `post_message` function of the worker.
+    fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Returns an error of kind `kind`, saying which worker it is about when
+    /// it is known. Used by the wrappers failing on behalf of the worker.
+    fn error(&self, kind: ErrorKind, cause: &str) -> Error {
+        Error::new(kind, cause)
+    }
+}
+
+macro_rules! impl_post {
+    (<$($param:ident),*> $mode:ty $(where $($bounds:tt)+)?) => {
+        impl<Message, Ctrl, $($param),*> Post<Message> for Worker<$mode, Ctrl>
+        where
+            Message: Send + 'static,
+            Self: Sync,
+            $($($bounds)+)?
+        {
+            fn post_message(&self, msg: Message) -> impl Future<Output = Result<(), Error>> + Send {
+                Worker::<$mode, Ctrl>::post_message(self, msg)
+            }
+
+            fn error(&self, kind: ErrorKind, cause: &str) -> Error {
+                self.shared.error(kind, cause)
+            }
+        }
+    };
+}
+
+impl_post!(<Mb> OneWay<Message, Mb> where Mb: Mailbox<Message>, Mb::Sender: Sync);
+impl_post!(<TaskMessage, Mb> TwoWay<Message, TaskMessage, Mb>
+    where TaskMessage: Clone, Mb: Mailbox<Message>, Mb::Sender: Sync);
+impl_post!(<> Prioritized<Message>);
+impl_post!(<Key> Coalescing<Message, Key> where Key: Hash + Eq + Clone);
+#[cfg(feature = "durable")]
+impl_post!(<> Durable<Message> where Message: Serialize + DeserializeOwned);
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Ctrl> Worker<Isolated, Ctrl> {
+    /// Creates an isolated worker that can only terminate the spawned task.
+    pub fn spawn<T>(task: T) -> Worker<Isolated, Ctrl>
     where
-        T: Task<Handle = handle::Worker<handle::OneWay<Message>>>,
+        T: Task<Handle = handle::Worker<handle::Isolated, Ctrl>>,
         <T as Task>::Output: Send + 'static,
     {
-        // This token is used to terminate the worker and its controlled task.
-        let token = CancellationToken::new();
+        Self::spawn_with(task, None)
+    }
 
-        // the channel used by Worker to communicate with its Task.
-        let (send_to_task, recv_from_wk) = channel::<Message>(BUFFER_CAPACITY);
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(task: T, name: impl Into<String>) -> Worker<Isolated, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Isolated, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Self::spawn_with(task, Some(name.into().into()))
+    }
+
+    fn spawn_with<T>(task: T, name: Option<Arc<str>>) -> Worker<Isolated, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Isolated, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        let (worker, link) = Worker::link(Isolated {}, name);
 
         // Worker's handle that will be used by the Task to communicate with
         // this worker and to terminate both.
-        let wkh = handle::Worker::one_way(token.clone(), recv_from_wk);
+        let wkh = handle::Worker::isolated(link);
 
         // The Task is spawned here
-        tokio::spawn(task.spawn(wkh));
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
 
-        Worker {
-            termination_token: token,
-            mode: OneWay {
-                sender_to_tsk: send_to_task,
-            },
-        }
+        worker
     }
 
-    /// Send message `msg` to the spawned task.
-    pub async fn post_message(&self, msg: Message) -> Result<(), Error> {
-        self.mode
-            .sender_to_tsk
-            .send(msg)
-            .await
-            .map_err(|e| Error::from(&e))
+    // Spawns a subscriber `task` that receives the events sent in the
+    // broadcast channel of `receiver`.
+    pub(crate) fn subscriber<T, Event>(
+        task: T,
+        receiver: EventReceiver<Event>,
+    ) -> Worker<Isolated, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<Event>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        let (worker, link) = Worker::link(Isolated {}, None);
+
+        // OnEvent worker's handle that will be used by the Task to receive
+        // events sent by the publishing task.
+        let wkh = handle::Worker::on_event(link, receiver);
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
     }
 }
 
 // // // // // // // // // // // // // // // // // // // // // // // // // // //
 
-impl<Message, TaskMessage: Clone> Worker<TwoWay<Message, TaskMessage>> {
-    /// Creates a worker that is able to communicate in a bidirectional way with
-    /// the `task` that is spowned. The back channel is a broadcast one so many
-    /// subscriber tasks will be able to subscribe, with the function [`Self::on_message()`],
-    /// to the events sent by this worker's controlled task.
-    pub fn spawn<T>(task: T) -> Worker<TwoWay<Message, TaskMessage>>
+impl<Message, Mb: Mailbox<Message>, Ctrl> Worker<OneWay<Message, Mb>, Ctrl> {
+    /// Creates a worker that is able to send messages to its controlled `task`.
+    pub fn spawn<T>(task: T) -> Worker<OneWay<Message, Mb>, Ctrl>
     where
-        T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage>>>,
+        T: Task<Handle = handle::Worker<handle::OneWay<Message, Mb>, Ctrl>>,
         <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
     {
-        // This token is used to terminate the worker and its controlled task.
-        let token = CancellationToken::new();
+        Self::spawn_with(task, None, |_, recv_from_wk| recv_from_wk)
+    }
 
-        // the channel used by Worker to communicate with its Task.
-        let (send_to_task, recv_from_wk) = channel::<Message>(BUFFER_CAPACITY);
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(task: T, name: impl Into<String>) -> Worker<OneWay<Message, Mb>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::OneWay<Message, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        Self::spawn_with(task, Some(name.into().into()), |_, recv_from_wk| {
+            recv_from_wk
+        })
+    }
 
-        // the broadcast channel used by the Task to communicate with this Worker.
-        let (broadcast_to_wk, _) = broadcast::channel::<TaskMessage>(BUFFER_CAPACITY);
+    // Like spawn but returns also the join handle of the task, that is always
+    // spawned on tokio.
+    #[cfg(feature = "process")]
+    pub(crate) fn spawn_joined<T>(task: T) -> (Self, JoinHandle<T::Output>)
+    where
+        T: Task<Handle = handle::Worker<handle::OneWay<Message, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        let (worker, wkh) = Self::setup(None, |_, recv_from_wk| recv_from_wk);
+        let joined = tokio::spawn(worker.shared.track(task.spawn(wkh)));
+        (worker, joined)
+    }
 
-        // Worker's handle that will be used by the Task to communicate with
-        // this worker and to terminate both.
-        let wkh = handle::Worker::two_way(token.clone(), recv_from_wk, broadcast_to_wk.to_owned());
+    // Spawns `task`, giving it the receiver returned by `prepare`.
+    fn spawn_with<T>(
+        task: T,
+        name: Option<Arc<str>>,
+        prepare: impl FnOnce(&Self, Mb::Receiver) -> Mb::Receiver,
+    ) -> Self
+    where
+        T: Task<Handle = handle::Worker<handle::OneWay<Message, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        let (worker, wkh) = Self::setup(name, prepare);
 
         // The Task is spawned here
-        tokio::spawn(task.spawn(wkh));
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
+    }
+
+    // Creates the worker and the handle for its task, still to be spawned.
+    fn setup(
+        name: Option<Arc<str>>,
+        prepare: impl FnOnce(&Self, Mb::Receiver) -> Mb::Receiver,
+    ) -> (Self, handle::Worker<handle::OneWay<Message, Mb>, Ctrl>)
+    where
+        Message: Send + 'static,
+    {
+        // the channel used by Worker to communicate with its Task.
+        let (send_to_task, recv_from_wk) = Mb::channel(BUFFER_CAPACITY);
 
-        Worker {
-            termination_token: token,
-            mode: TwoWay {
+        let (worker, link) = Worker::link(
+            OneWay {
                 sender_to_tsk: send_to_task,
-                broadcast_from_tsk: broadcast_to_wk,
+                #[cfg(feature = "futures")]
+                poll_sender: None,
             },
-        }
+            name,
+        );
+        let recv_from_wk = prepare(&worker, recv_from_wk);
+
+        // Worker's handle that will be used by the Task to communicate with
+        // this worker and to terminate both.
+        let wkh = handle::Worker::one_way(link, recv_from_wk);
+
+        (worker, wkh)
     }
 
     /// Send message `msg` to the spawned task.
-    pub async fn post_message(&self, msg: Message) -> Result<(), Error> {
-        self.mode
-            .sender_to_tsk
-            .send(msg)
-            .await
-            .map_err(|e| Error::from(&e))
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error>
+    where
+        Message: Send + 'static,
+    {
+        self.deliver(&self.mode.sender_to_tsk, msg).await
     }
+}
 
-    /// Let `task` to subscribe to event messages that will be sent by this
-    /// two-way worker's task. Every subscription will receive independently
-    /// the sent events. The OnEvent handle is able to `terminate` itself and
-    /// the subscriber task, but not the two-way worker or task.
-    pub fn on_message<T>(&self, task: T) -> Worker<Isolated>
+impl<Message, Ctrl> Worker<OneWay<Message>, Ctrl> {
+    /// Like [`Self::spawn()`] but the messages posted to `task` are rate
+    /// limited as told by `limit`, see [`crate::rate_limit`]. Messages sent
+    /// through the futures `Sink` are limited only with [`Throttle::Smooth`].
+    pub fn spawn_with_rate_limit<T>(task: T, limit: RateLimit) -> Worker<OneWay<Message>, Ctrl>
     where
-        T: Task<Handle = handle::Worker<handle::OnEvent<TaskMessage>>>,
+        T: Task<Handle = handle::Worker<handle::OneWay<Message>, Ctrl>>,
         <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
     {
-        // This token is used to terminate the worker and its controlled task.
-        let token = CancellationToken::new();
-
-        // OnEvent worker's handle that will be used by the Task to receive
-        // events sent by this two-way task.
-        let wkh = handle::Worker::on_event(token.clone(), self.mode.broadcast_from_tsk.subscribe());
+        Self::spawn_with(task, None, |worker, recv_from_wk| {
+            worker.limit(recv_from_wk, Some(limit))
+        })
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Message, TaskMessage: Clone, Mb: Mailbox<Message>, Ctrl>
+    Worker<TwoWay<Message, TaskMessage, Mb>, Ctrl>
+{
+    /// Creates a worker that is able to communicate in a bidirectional way with
+    /// the `task` that is spowned. The back channel is a broadcast one so many
+    /// subscriber tasks will be able to subscribe, with the function [`Self::on_message()`],
+    /// to the events sent by this worker's controlled task.
+    pub fn spawn<T>(task: T) -> Worker<TwoWay<Message, TaskMessage, Mb>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        Self::spawn_with(
+            task,
+            None,
+            Broadcaster::new(BUFFER_CAPACITY),
+            |_, recv_from_wk| recv_from_wk,
+        )
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(
+        task: T,
+        name: impl Into<String>,
+    ) -> Worker<TwoWay<Message, TaskMessage, Mb>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        Self::spawn_with(
+            task,
+            Some(name.into().into()),
+            Broadcaster::new(BUFFER_CAPACITY),
+            |_, recv_from_wk| recv_from_wk,
+        )
+    }
+
+    // Like spawn but returns also the join handle of the task, that is always
+    // spawned on tokio.
+    #[cfg(feature = "process")]
+    pub(crate) fn spawn_joined<T>(task: T) -> (Self, JoinHandle<T::Output>)
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        let (worker, wkh) = Self::setup(
+            None,
+            Broadcaster::new(BUFFER_CAPACITY),
+            |_, recv_from_wk| recv_from_wk,
+        );
+        let joined = tokio::spawn(worker.shared.track(task.spawn(wkh)));
+        (worker, joined)
+    }
+
+    /// Like [`Self::spawn()`] but the recent events sent by `task`, as told by
+    /// `replay`, are delivered to every new subscriber before the live ones.
+    /// See [`crate::event`].
+    pub fn spawn_with_replay<T>(
+        task: T,
+        replay: Replay,
+    ) -> Worker<TwoWay<Message, TaskMessage, Mb>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        Self::spawn_with(
+            task,
+            None,
+            Broadcaster::with_replay(BUFFER_CAPACITY, replay),
+            |_, recv_from_wk| recv_from_wk,
+        )
+    }
+
+    // Spawns `task`, giving it the receiver returned by `prepare`.
+    fn spawn_with<T>(
+        task: T,
+        name: Option<Arc<str>>,
+        broadcast_to_wk: Broadcaster<TaskMessage>,
+        prepare: impl FnOnce(&Self, Mb::Receiver) -> Mb::Receiver,
+    ) -> Self
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage, Mb>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        let (worker, wkh) = Self::setup(name, broadcast_to_wk, prepare);
 
         // The Task is spawned here
-        tokio::spawn(task.spawn(wkh));
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
 
-        Worker {
-            termination_token: token,
-            mode: Isolated {},
-        }
+        worker
+    }
+
+    // Creates the worker and the handle for its task, still to be spawned.
+    fn setup(
+        name: Option<Arc<str>>,
+        broadcast_to_wk: Broadcaster<TaskMessage>,
+        prepare: impl FnOnce(&Self, Mb::Receiver) -> Mb::Receiver,
+    ) -> (
+        Self,
+        handle::Worker<handle::TwoWay<Message, TaskMessage, Mb>, Ctrl>,
+    )
+    where
+        Message: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        // the channel used by Worker to communicate with its Task.
+        let (send_to_task, recv_from_wk) = Mb::channel(BUFFER_CAPACITY);
+
+        let (worker, link) = Worker::link(
+            TwoWay {
+                sender_to_tsk: send_to_task,
+                #[cfg(feature = "futures")]
+                poll_sender: None,
+                broadcast_from_tsk: broadcast_to_wk.clone(),
+            },
+            name,
+        );
+        let recv_from_wk = prepare(&worker, recv_from_wk);
+
+        // Worker's handle that will be used by the Task to communicate with
+        // this worker and to terminate both.
+        let wkh = handle::Worker::two_way(link, recv_from_wk, broadcast_to_wk);
+
+        (worker, wkh)
+    }
+
+    /// Send message `msg` to the spawned task.
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error>
+    where
+        Message: Send + 'static,
+    {
+        self.deliver(&self.mode.sender_to_tsk, msg).await
+    }
+
+    /// Let `task` to subscribe to event messages that will be sent by this
+    /// two-way worker's task. Every subscription will receive independently
+    /// the sent events. The OnEvent handle is able to `terminate` itself and
+    /// the subscriber task, but not the two-way worker or task.
+    ///
+    /// When the worker was spawned with [`Self::spawn_with_replay()`] the
+    /// subscriber receives the recent events first.
+    pub fn on_message<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<TaskMessage>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Worker::subscriber(task, self.mode.broadcast_from_tsk.subscribe())
+    }
+
+    /// Returns a receiver of the event messages that will be sent by this
+    /// two-way worker's task, for the cases where spawning a subscriber task
+    /// with [`Self::on_message()`] is not needed.
+    pub fn subscribe(&self) -> EventReceiver<TaskMessage> {
+        self.mode.broadcast_from_tsk.subscribe()
+    }
+}
+
+impl<Message, TaskMessage: Clone, Ctrl> Worker<TwoWay<Message, TaskMessage>, Ctrl> {
+    /// Like [`Self::spawn()`] but the messages posted to `task` are rate
+    /// limited as told by `limit`, see [`crate::rate_limit`]. Messages sent
+    /// through the futures `Sink` are limited only with [`Throttle::Smooth`].
+    pub fn spawn_with_rate_limit<T>(
+        task: T,
+        limit: RateLimit,
+    ) -> Worker<TwoWay<Message, TaskMessage>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::TwoWay<Message, TaskMessage>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        Self::spawn_with(
+            task,
+            None,
+            Broadcaster::new(BUFFER_CAPACITY),
+            |worker, recv_from_wk| worker.limit(recv_from_wk, Some(limit)),
+        )
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<TaskMessage: Clone, Ctrl> Worker<Emitter<TaskMessage>, Ctrl> {
+    /// Creates a worker whose `task` takes no messages and sends events to
+    /// the subscribers, see [`Self::on_message()`].
+    pub fn spawn<T>(task: T) -> Worker<Emitter<TaskMessage>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::OneWayBack<TaskMessage>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        Self::spawn_with(task, None, Broadcaster::new(BUFFER_CAPACITY))
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(task: T, name: impl Into<String>) -> Worker<Emitter<TaskMessage>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::OneWayBack<TaskMessage>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        Self::spawn_with(
+            task,
+            Some(name.into().into()),
+            Broadcaster::new(BUFFER_CAPACITY),
+        )
+    }
+
+    /// Like [`Self::spawn()`] but the recent events, as told by `replay`, are
+    /// delivered to every new subscriber. See [`crate::event`].
+    pub fn spawn_with_replay<T>(task: T, replay: Replay) -> Worker<Emitter<TaskMessage>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::OneWayBack<TaskMessage>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        Self::spawn_with(
+            task,
+            None,
+            Broadcaster::with_replay(BUFFER_CAPACITY, replay),
+        )
+    }
+
+    fn spawn_with<T>(
+        task: T,
+        name: Option<Arc<str>>,
+        broadcast_to_wk: Broadcaster<TaskMessage>,
+    ) -> Self
+    where
+        T: Task<Handle = handle::Worker<handle::OneWayBack<TaskMessage>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        TaskMessage: Send + 'static,
+    {
+        let (worker, link) = Worker::link(
+            Emitter {
+                broadcast_from_tsk: broadcast_to_wk.clone(),
+            },
+            name,
+        );
+
+        // Worker's handle that will be used by the Task to send events to the
+        // subscribers and to terminate both.
+        let wkh = handle::Worker::one_way_back(link, Outlet::Broadcast(broadcast_to_wk));
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
+    }
+
+    /// Let `task` to subscribe to event messages that will be sent by this
+    /// worker's task, see [`Worker::on_message()`].
+    ///
+    /// [`Worker::on_message()`]: Worker<TwoWay>::on_message
+    pub fn on_message<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnEvent<TaskMessage>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Worker::subscriber(task, self.mode.broadcast_from_tsk.subscribe())
+    }
+
+    /// Returns a receiver of the event messages that will be sent by this
+    /// worker's task.
+    pub fn subscribe(&self) -> EventReceiver<TaskMessage> {
+        self.mode.broadcast_from_tsk.subscribe()
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Message, Key: Hash + Eq + Clone, Ctrl> Worker<Coalescing<Message, Key>, Ctrl> {
+    /// Creates a worker whose posted messages supersede the pending ones with
+    /// the same key, given by `key`.
+    pub fn spawn<T>(
+        task: T,
+        key: impl Fn(&Message) -> Key + Send + Sync + 'static,
+    ) -> Worker<Coalescing<Message, Key>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Coalescing<Message, Key>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        Key: Send + 'static,
+    {
+        Self::spawn_with(task, None, coalesce::Mailbox::new(key, None))
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(
+        task: T,
+        key: impl Fn(&Message) -> Key + Send + Sync + 'static,
+        name: impl Into<String>,
+    ) -> Worker<Coalescing<Message, Key>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Coalescing<Message, Key>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        Key: Send + 'static,
+    {
+        Self::spawn_with(
+            task,
+            Some(name.into().into()),
+            coalesce::Mailbox::new(key, None),
+        )
+    }
+
+    /// Like [`Self::spawn()`] but a message is received by `task` only once
+    /// no newer message with the same key has been posted for `window`.
+    pub fn spawn_with_debounce<T>(
+        task: T,
+        key: impl Fn(&Message) -> Key + Send + Sync + 'static,
+        window: Duration,
+    ) -> Worker<Coalescing<Message, Key>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Coalescing<Message, Key>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        Key: Send + 'static,
+    {
+        Self::spawn_with(task, None, coalesce::Mailbox::new(key, Some(window)))
+    }
+
+    fn spawn_with<T>(
+        task: T,
+        name: Option<Arc<str>>,
+        mailbox: Arc<coalesce::Mailbox<Message, Key>>,
+    ) -> Worker<Coalescing<Message, Key>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Coalescing<Message, Key>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+        Key: Send + 'static,
+    {
+        let (worker, link) = Worker::link(
+            Coalescing {
+                mailbox: mailbox.clone(),
+            },
+            name,
+        );
+
+        // Worker's handle that will be used by the Task to communicate with
+        // this worker and to terminate both.
+        let wkh = handle::Worker::coalescing(link, CoalescingReceiver::new(mailbox));
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
+    }
+
+    /// Send message `msg` to the spawned task, superseding the pending message
+    /// with the same key, if any. It never waits.
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error>
+    where
+        Message: Send + 'static,
+    {
+        self.mode.mailbox.post(msg).map_err(|msg| {
+            let _ = dead_letter::post(&self.shared, msg, Reason::Terminated);
+            self.shared.error(ErrorKind::Terminated, "task terminated")
+        })
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Req, Resp, Ctrl> Worker<Streaming<Req, Resp>, Ctrl> {
+    /// Creates a worker that sends requests to its `task`, each one answered
+    /// with a stream of responses.
+    pub fn spawn<T>(task: T) -> Worker<Streaming<Req, Resp>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Streaming<Req, Resp>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        Self::spawn_with(task, None)
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(task: T, name: impl Into<String>) -> Worker<Streaming<Req, Resp>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Streaming<Req, Resp>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        Self::spawn_with(task, Some(name.into().into()))
+    }
+
+    fn spawn_with<T>(task: T, name: Option<Arc<str>>) -> Worker<Streaming<Req, Resp>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Streaming<Req, Resp>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        // the channel used by Worker to communicate with its Task.
+        let (send_to_task, recv_from_wk) = channel(BUFFER_CAPACITY);
+
+        let (worker, link) = Worker::link(
+            Streaming {
+                sender_to_tsk: send_to_task,
+            },
+            name,
+        );
+
+        // Worker's handle that will be used by the Task to communicate with
+        // this worker and to terminate both.
+        let wkh = handle::Worker::streaming(link, recv_from_wk);
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
+    }
+
+    /// Sends request `req` to the spawned task and returns the stream of its
+    /// responses.
+    pub async fn ask(&self, req: Req) -> Result<ResponseStream<Resp>, Error>
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        self.ask_with_capacity(req, STREAM_CAPACITY).await
+    }
+
+    /// Like [`Self::ask()`] but the stream buffers up to `capacity`
+    /// responses, instead of 32, before the task has to wait.
+    pub async fn ask_with_capacity(
+        &self,
+        req: Req,
+        capacity: usize,
+    ) -> Result<ResponseStream<Resp>, Error>
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        let (request, responses) = streaming::request(req, capacity);
+        self.deliver(&self.mode.sender_to_tsk, request).await?;
+        Ok(responses)
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<S, Ctrl> Worker<State<S>, Ctrl> {
+    /// Creates a worker whose `task` publishes a state, starting from
+    /// `initial`.
+    pub fn spawn<T>(task: T, initial: S) -> Worker<State<S>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::State<S>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Self::spawn_with(task, initial, None)
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(task: T, initial: S, name: impl Into<String>) -> Worker<State<S>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::State<S>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        Self::spawn_with(task, initial, Some(name.into().into()))
+    }
+
+    fn spawn_with<T>(task: T, initial: S, name: Option<Arc<str>>) -> Worker<State<S>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::State<S>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        // the watch channel used by the Task to publish its state.
+        let (publish_to_wk, recv_from_tsk) = watch::channel(initial);
+
+        let (worker, link) = Worker::link(
+            State {
+                receiver_from_tsk: recv_from_tsk,
+            },
+            name,
+        );
+
+        // Worker's handle that will be used by the Task to publish its state
+        // and to terminate both.
+        let wkh = handle::Worker::state(link, publish_to_wk);
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
+    }
+
+    /// Returns a reference to the latest state published by the task. The
+    /// task can't publish while the reference is held, so it must be short
+    /// lived.
+    pub fn borrow(&self) -> watch::Ref<'_, S> {
+        self.mode.receiver_from_tsk.borrow()
+    }
+
+    /// Waits for the task to publish a state not yet seen with
+    /// [`Self::borrow_and_update()`]. Returns an error when the task has gone.
+    pub async fn changed(&mut self) -> Result<(), Error> {
+        self.mode
+            .receiver_from_tsk
+            .changed()
+            .await
+            .map_err(|e| self.shared.error(ErrorKind::Terminated, e))
+    }
+
+    /// Like [`Self::borrow()`] but marks the state as seen, see
+    /// [`Self::changed()`].
+    pub fn borrow_and_update(&mut self) -> watch::Ref<'_, S> {
+        self.mode.receiver_from_tsk.borrow_and_update()
+    }
+
+    /// Let `task` to subscribe to the state published by this worker's task.
+    /// The subscriber sees the current state as changed, so it can start from
+    /// it, and then every time a new one is published. When the task
+    /// publishes faster than the subscriber handles, the intermediate states
+    /// are skipped.
+    pub fn on_change<T, C>(&self, task: T) -> Worker<Isolated, C>
+    where
+        T: Task<Handle = handle::Worker<handle::OnChange<S>, C>>,
+        <T as Task>::Output: Send + 'static,
+    {
+        let (worker, link) = Worker::link(Isolated {}, None);
+
+        // OnChange worker's handle that will be used by the Task to receive
+        // the states published by the publishing task.
+        let wkh = handle::Worker::on_change(link, self.subscribe());
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
+    }
+
+    /// Returns a receiver of the states published by this worker's task, for
+    /// the cases where spawning a subscriber task with [`Self::on_change()`]
+    /// is not needed. Like for [`Self::on_change()`], the current state is
+    /// seen as changed.
+    pub fn subscribe(&self) -> watch::Receiver<S> {
+        let mut receiver = self.mode.receiver_from_tsk.clone();
+        receiver.mark_changed();
+        receiver
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Output: Send + 'static> Worker<Scheduled<Output>> {
+    /// Creates a worker that runs `task` following `schedule`, until the worker
+    /// is terminated. A run never overlaps the previous one: see
+    /// [`schedule::MissedTick`] for what happens when a run takes too long.
+    ///
+    /// The scheduler handles the control commands: [`Control::Pause`] and
+    /// [`Control::Resume`] suspend and restart the runs, [`Control::Flush`]
+    /// starts a run immediately.
+    pub fn spawn<T>(task: T, schedule: Schedule) -> Worker<Scheduled<Output>>
+    where
+        T: Task<Handle = handle::Worker<handle::Isolated>, Output = Output> + Send + 'static,
+    {
+        Self::spawn_with(task, schedule, None)
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(
+        task: T,
+        schedule: Schedule,
+        name: impl Into<String>,
+    ) -> Worker<Scheduled<Output>>
+    where
+        T: Task<Handle = handle::Worker<handle::Isolated>, Output = Output> + Send + 'static,
+    {
+        Self::spawn_with(task, schedule, Some(name.into().into()))
+    }
+
+    fn spawn_with<T>(
+        task: T,
+        schedule: Schedule,
+        name: Option<Arc<str>>,
+    ) -> Worker<Scheduled<Output>>
+    where
+        T: Task<Handle = handle::Worker<handle::Isolated>, Output = Output> + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(schedule::State::default()));
+
+        let (worker, link) = Worker::link(
+            Scheduled {
+                state: state.clone(),
+            },
+            name,
+        );
+
+        // The scheduler that runs the Task is spawned here
+        runtime::spawn(
+            worker
+                .shared
+                .track(schedule::run(task, schedule, link, state)),
+        );
+
+        worker
+    }
+
+    /// Returns when the next run will start, or `None` if the task is running
+    /// or no more runs are scheduled.
+    pub fn next_run(&self) -> Option<std::time::Instant> {
+        self.mode
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .next_run
+    }
+
+    /// Returns the output of the last completed run, if any.
+    pub fn last_result(&self) -> Option<Output>
+    where
+        Output: Clone,
+    {
+        self.mode
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .last_result
+            .clone()
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+impl<Message, Ctrl> Worker<Prioritized<Message>, Ctrl> {
+    /// Creates a worker that is able to send messages, with different
+    /// priorities, to its controlled `task`.
+    pub fn spawn<T>(task: T) -> Worker<Prioritized<Message>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Prioritized<Message>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        Self::spawn_with(task, None, None)
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(task: T, name: impl Into<String>) -> Worker<Prioritized<Message>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Prioritized<Message>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        Self::spawn_with(task, None, Some(name.into().into()))
+    }
+
+    /// Like [`Self::spawn()`] but protecting the lower priority messages from
+    /// starvation: after `limit` consecutive higher priority messages, a lower
+    /// priority one, if any, is received by the task.
+    pub fn spawn_with_starvation_limit<T>(
+        task: T,
+        limit: usize,
+    ) -> Worker<Prioritized<Message>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Prioritized<Message>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        Self::spawn_with(task, Some(limit), None)
+    }
+
+    fn spawn_with<T>(
+        task: T,
+        starvation_limit: Option<usize>,
+        name: Option<Arc<str>>,
+    ) -> Worker<Prioritized<Message>, Ctrl>
+    where
+        T: Task<Handle = handle::Worker<handle::Prioritized<Message>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        // the channels used by Worker to communicate with its Task, one for
+        // each priority.
+        let [high, normal, low] = [(); LANES].map(|()| channel::<Message>(BUFFER_CAPACITY));
+        let senders = [high.0, normal.0, low.0];
+        let receivers = [high.1, normal.1, low.1];
+
+        let (worker, link) = Worker::link(
+            Prioritized {
+                senders_to_tsk: senders,
+            },
+            name,
+        );
+
+        // Worker's handle that will be used by the Task to communicate with
+        // this worker and to terminate both.
+        let wkh =
+            handle::Worker::prioritized(link, PriorityReceiver::new(receivers, starvation_limit));
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        worker
+    }
+
+    /// Send message `msg` to the spawned task with [`Priority::Normal`].
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error>
+    where
+        Message: Send + 'static,
+    {
+        self.post_message_with_priority(msg, Priority::Normal).await
+    }
+
+    /// Send message `msg` to the spawned task with the given `priority`.
+    pub async fn post_message_with_priority(
+        &self,
+        msg: Message,
+        priority: Priority,
+    ) -> Result<(), Error>
+    where
+        Message: Send + 'static,
+    {
+        self.deliver(&self.mode.senders_to_tsk[priority as usize], msg)
+            .await
+    }
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+#[cfg(feature = "durable")]
+impl<Message, Ctrl> Worker<Durable<Message>, Ctrl>
+where
+    Message: Serialize + DeserializeOwned,
+{
+    /// Creates a worker with a durable mailbox stored in `journal`. The
+    /// messages left in the journal without acknowledgement are the first
+    /// ones received by `task`.
+    pub fn spawn<T>(task: T, journal: Journal) -> Result<Worker<Durable<Message>, Ctrl>, Error>
+    where
+        T: Task<Handle = handle::Worker<handle::Durable<Message>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        Self::spawn_with(task, journal, None)
+    }
+
+    /// Like [`Self::spawn()`] but naming the worker `name`, see
+    /// [`Self::with_name()`].
+    pub fn spawn_named<T>(
+        task: T,
+        journal: Journal,
+        name: impl Into<String>,
+    ) -> Result<Worker<Durable<Message>, Ctrl>, Error>
+    where
+        T: Task<Handle = handle::Worker<handle::Durable<Message>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        Self::spawn_with(task, journal, Some(name.into().into()))
+    }
+
+    fn spawn_with<T>(
+        task: T,
+        journal: Journal,
+        name: Option<Arc<str>>,
+    ) -> Result<Worker<Durable<Message>, Ctrl>, Error>
+    where
+        T: Task<Handle = handle::Worker<handle::Durable<Message>, Ctrl>>,
+        <T as Task>::Output: Send + 'static,
+        Message: Send + 'static,
+    {
+        let (log, replay) = Log::open(&journal)?;
+        let log = Arc::new(log);
+
+        // the channel used by Worker to communicate with its Task.
+        let (send_to_task, recv_from_wk) = channel(BUFFER_CAPACITY);
+
+        let (worker, link) = Worker::link(
+            Durable {
+                sender_to_tsk: send_to_task,
+                log: log.clone(),
+            },
+            name,
+        );
+
+        // Worker's handle that will be used by the Task to communicate with
+        // this worker and to terminate both.
+        let wkh = handle::Worker::durable(link, DurableReceiver::new(replay, recv_from_wk, log));
+
+        // The Task is spawned here
+        runtime::spawn(worker.shared.track(task.spawn(wkh)));
+
+        Ok(worker)
+    }
+
+    /// Writes message `msg` in the journal and sends it to the spawned task.
+    /// When this function returns `Ok` the message is in the journal, flushed
+    /// as told by the [`crate::durable::FsyncPolicy`].
+    pub async fn post_message(&self, msg: Message) -> Result<(), Error>
+    where
+        Message: Send + 'static,
+    {
+        self.mode
+            .log
+            .post(&self.shared, &self.mode.sender_to_tsk, msg)
+            .await
     }
 }
@@ -0,0 +1,173 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+#![cfg(feature = "futures")]
+
+use std::pin::Pin;
+
+use futures::{stream, Sink, SinkExt, StreamExt};
+use opifex::{
+    dead_letter::{DeadLetterQueue, Reason},
+    handle,
+    streaming::Request,
+    worker::{Streaming, TwoWay},
+    ErrorKind, Task, Worker,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+// Sends back twice the messages it streams, telling with None when the
+// stream has ended.
+struct Doubler {
+    ended: UnboundedSender<Option<u32>>,
+}
+
+impl Task for Doubler {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut stream, hnd) = wk_hnd.stream();
+        let ended = self.ended.clone();
+
+        async move {
+            while let Some(n) = stream.next().await {
+                let _ = hnd.post_message(n * 2).await;
+            }
+            let _ = ended.send(None);
+        }
+    }
+}
+
+// Forwards the events it streams, then None when the stream has ended.
+struct Collect {
+    received: UnboundedSender<Option<u32>>,
+}
+
+impl Task for Collect {
+    type Handle = handle::Worker<handle::OnEvent<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut stream, _hnd) = wk_hnd.stream();
+        let received = self.received.clone();
+
+        async move {
+            while let Some(n) = stream.next().await {
+                let _ = received.send(Some(n));
+            }
+            let _ = received.send(None);
+        }
+    }
+}
+
+// Answers n with the numbers from 0 to n.
+struct Counter;
+
+impl Task for Counter {
+    type Handle = handle::Worker<handle::Streaming<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(Request { message, responder }) = rx.recv() => {
+                        for n in 0..message {
+                            let _ = responder.send(n).await;
+                        }
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_sink_feeds_the_task() {
+    let (ended, _ended) = unbounded_channel();
+    let mut worker = Worker::<TwoWay<u32, u32>>::spawn(Doubler { ended });
+    let (received, mut rx) = unbounded_channel();
+    let subscriber = worker.on_message(Collect { received });
+
+    worker
+        .send_all(&mut stream::iter(1..=3).map(Ok))
+        .await
+        .unwrap();
+    for expected in [2, 4, 6] {
+        assert_eq!(rx.recv().await, Some(Some(expected)));
+    }
+
+    // post_message still works after the sink is closed
+    worker.close().await.unwrap();
+    worker.post_message(4).await.unwrap();
+    assert_eq!(rx.recv().await, Some(Some(8)));
+
+    subscriber.terminate();
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn the_responses_are_a_stream() {
+    let worker = Worker::<Streaming<u32, u32>>::spawn(Counter);
+
+    let responses: Vec<_> = worker.ask(3).await.unwrap().collect().await;
+    assert_eq!(responses, [0, 1, 2]);
+
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn the_streams_end_on_termination() {
+    let (ended, mut task_ended) = unbounded_channel();
+    let worker = Worker::<TwoWay<u32, u32>>::spawn(Doubler { ended });
+    let (received, mut subscriber_ended) = unbounded_channel();
+    let subscriber = worker.on_message(Collect { received });
+
+    // with messages still in the mailbox
+    worker.post_message(1).await.unwrap();
+    worker.terminate();
+    subscriber.terminate();
+
+    assert_eq!(task_ended.recv().await, Some(None));
+    let mut last = subscriber_ended.recv().await;
+    while let Some(Some(_)) = last {
+        last = subscriber_ended.recv().await;
+    }
+    assert_eq!(last, Some(None));
+}
+
+#[tokio::test]
+async fn a_message_the_sink_cant_send_is_a_dead_letter() {
+    let (ended, _ended) = unbounded_channel();
+    let mut worker = Worker::<TwoWay<u32, u32>>::spawn(Doubler { ended });
+    let queue = DeadLetterQueue::new(10);
+    worker.set_dead_letters(queue.clone());
+
+    worker.send(1).await.unwrap();
+    worker.close().await.unwrap();
+    let e = Pin::new(&mut worker).start_send(5).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+
+    let letters = queue.drain();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].reason, Reason::Terminated);
+    assert_eq!(letters[0].take_message::<u32>(), Some(5));
+
+    worker.terminate();
+}
@@ -0,0 +1,179 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+#![cfg(feature = "admin")]
+
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+use opifex::{handle, health::HealthRegistry, worker::OneWay, Task, Worker};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+// Holds its mailbox without reading it, until terminated.
+struct Idle;
+
+impl Task for Idle {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+        let (rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            let _rx = rx;
+            hnd.heartbeat();
+            hnd.terminated().await;
+        }
+    }
+}
+
+// Sends the `request` line, returning the status code and the body.
+async fn request(addr: SocketAddr, request: &str) -> (u16, Value) {
+    request_with(addr, request, "Host: localhost\r\n").await
+}
+
+// Like request, with the `headers` lines.
+async fn request_with(addr: SocketAddr, request: &str, headers: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("{request} HTTP/1.1\r\n{headers}\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[tokio::test]
+async fn lists_the_workers() {
+    let idle = Worker::<OneWay<u32>>::spawn(Idle).with_name("idle");
+    let anonymous = Worker::<OneWay<u32>>::spawn(Idle);
+    idle.post_message(1).await.unwrap();
+    idle.post_message(2).await.unwrap();
+
+    let registry = HealthRegistry::new();
+    registry.register(&idle);
+    registry.register(&anonymous);
+    let admin = registry.serve_admin(0).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let (status, body) = request(admin.local_addr(), "GET /workers").await;
+    assert_eq!(status, 200);
+    let workers = body.as_array().unwrap();
+    assert_eq!(workers.len(), 2);
+
+    let first = &workers[0];
+    assert_eq!(format!("#{}", first["id"]), idle.id().to_string());
+    assert_eq!(first["name"], "idle");
+    assert_eq!(first["mode"], "OneWay");
+    assert_eq!(first["status"], "Healthy");
+    assert_eq!(first["paused"], false);
+    assert_eq!(first["mailbox_depth"], 2);
+    assert_eq!(first["subscribers"], Value::Null);
+    assert!(first["uptime_ms"].is_u64());
+    assert!(first["last_heartbeat_ms"].is_u64());
+    assert_eq!(workers[1]["name"], Value::Null);
+
+    let (status, body) = request(admin.local_addr(), "GET /health/ready").await;
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({ "live": true, "ready": true }));
+
+    admin.shutdown();
+}
+
+#[tokio::test]
+async fn terminates_a_worker() {
+    let idle = Worker::<OneWay<u32>>::spawn(Idle);
+    let registry = HealthRegistry::new();
+    registry.register(&idle);
+    let admin = registry.serve_admin(0).await.unwrap();
+    let addr = admin.local_addr();
+
+    let id = idle.id().to_string();
+    let id = id.trim_start_matches('#');
+    let (status, body) = request(addr, &format!("POST /workers/{id}/terminate")).await;
+    assert_eq!((status, body), (200, json!({})));
+    tokio::time::timeout(Duration::from_secs(5), idle.finished())
+        .await
+        .unwrap();
+
+    let (status, body) = request(addr, "GET /health/live").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["live"], false);
+
+    let (status, _) = request(addr, "POST /workers/999999/terminate").await;
+    assert_eq!(status, 404);
+    let (status, _) = request(addr, "POST /workers/abc/terminate").await;
+    assert_eq!(status, 400);
+    let (status, _) = request(addr, "DELETE /workers").await;
+    assert_eq!(status, 405);
+
+    admin.shutdown();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn refuses_the_requests_of_web_pages() {
+    let idle = Worker::<OneWay<u32>>::spawn(Idle);
+    let registry = HealthRegistry::new();
+    registry.register(&idle);
+    let admin = registry.serve_admin(0).await.unwrap();
+    let addr = admin.local_addr();
+    let port = addr.port();
+
+    let id = idle.id().to_string();
+    let terminate = format!("POST /workers/{}/terminate", id.trim_start_matches('#'));
+    for headers in [
+        String::new(),
+        "Host: evil.example\r\n".to_string(),
+        format!("Host: evil.example:{port}\r\n"),
+        format!("Host: localhost:{}\r\n", port + 1),
+        format!("Host: 127.0.0.1:{port}\r\nOrigin: http://evil.example\r\n"),
+    ] {
+        let (status, body) = request_with(addr, &terminate, &headers).await;
+        assert_eq!((status, body), (403, json!({ "error": "forbidden" })));
+    }
+    assert!(!idle.is_finished());
+
+    let headers = format!("Host: 127.0.0.1:{port}\r\n");
+    let (status, _) = request_with(addr, "GET /workers", &headers).await;
+    assert_eq!(status, 200);
+
+    admin.shutdown();
+    idle.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn drops_the_slow_clients() {
+    let admin = HealthRegistry::new().serve_admin(0).await.unwrap();
+
+    // the request never ends
+    let mut stream = TcpStream::connect(admin.local_addr()).await.unwrap();
+    stream
+        .write_all(b"GET /workers HTTP/1.1\r\n")
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert_eq!(response, "");
+}
+
+#[tokio::test]
+async fn dropping_the_server_stops_it() {
+    let admin = HealthRegistry::new().serve_admin(0).await.unwrap();
+    let addr = admin.local_addr();
+
+    drop(admin);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(TcpStream::connect(addr).await.is_err());
+}
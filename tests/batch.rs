@@ -0,0 +1,161 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+mod common;
+
+use std::time::Duration;
+
+use opifex::{
+    handle,
+    worker::{OneWay, TwoWay},
+    Task, Worker,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{sleep, Instant},
+};
+
+use common::Collect;
+
+const LINGER: Duration = Duration::from_millis(100);
+
+// the batches with the millis they were received at
+type Batches = UnboundedReceiver<(u128, Option<Vec<u32>>)>;
+
+// Forwards the batches it receives with the millis since `start`, then None
+// when the worker has gone.
+struct Batcher {
+    max: usize,
+    start: Instant,
+    batches: UnboundedSender<(u128, Option<Vec<u32>>)>,
+}
+
+impl Task for Batcher {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.batched(self.max, LINGER);
+        let (start, batches) = (self.start, self.batches.clone());
+
+        async move {
+            loop {
+                let batch = rx.recv().await;
+                let end = batch.is_none();
+                let _ = batches.send((start.elapsed().as_millis(), batch));
+                if end {
+                    break;
+                }
+            }
+            drop(hnd);
+        }
+    }
+}
+
+// Sends back the sum of every batch.
+struct Summer;
+
+impl Task for Summer {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.batched(3, LINGER);
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(batch) = rx.recv() => {
+                        let _ = hnd.post_message(batch.iter().sum()).await;
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn batcher(max: usize) -> (Worker<OneWay<u32>>, Batches) {
+    let (batches, rx) = unbounded_channel();
+    let task = Batcher {
+        max,
+        start: Instant::now(),
+        batches,
+    };
+    (Worker::<OneWay<u32>>::spawn(task), rx)
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_full_batch_is_received_at_once() {
+    let (worker, mut rx) = batcher(2);
+
+    for n in 1..=5 {
+        worker.post_message(n).await.unwrap();
+    }
+    assert_eq!(rx.recv().await, Some((0, Some(vec![1, 2]))));
+    assert_eq!(rx.recv().await, Some((0, Some(vec![3, 4]))));
+
+    // the last one waits for more messages
+    assert_eq!(rx.recv().await, Some((100, Some(vec![5]))));
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_linger_starts_from_the_first_message() {
+    let (worker, mut rx) = batcher(10);
+
+    // nothing is received until there's a message
+    sleep(Duration::from_millis(500)).await;
+    worker.post_message(1).await.unwrap();
+    sleep(Duration::from_millis(60)).await;
+    worker.post_message(2).await.unwrap();
+    assert_eq!(rx.recv().await, Some((600, Some(vec![1, 2]))));
+
+    // the next batch has its own linger
+    worker.post_message(3).await.unwrap();
+    assert_eq!(rx.recv().await, Some((700, Some(vec![3]))));
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_last_batch_is_received_when_the_worker_goes() {
+    let (worker, mut rx) = batcher(10);
+
+    worker.post_message(1).await.unwrap();
+    worker.post_message(2).await.unwrap();
+    tokio::task::yield_now().await;
+    drop(worker);
+
+    // without waiting for the linger
+    assert_eq!(rx.recv().await, Some((0, Some(vec![1, 2]))));
+    assert_eq!(rx.recv().await, Some((0, None)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn two_way_tasks_receive_batches_too() {
+    let worker = Worker::<TwoWay<u32, u32>>::spawn(Summer);
+    let (received, mut rx) = unbounded_channel();
+    let subscriber = worker.on_message(Collect { received });
+    tokio::task::yield_now().await;
+
+    for n in 1..=4 {
+        worker.post_message(n).await.unwrap();
+    }
+    assert_eq!(rx.recv().await, Some(6));
+    assert_eq!(rx.recv().await, Some(4));
+
+    subscriber.terminate();
+    worker.terminate();
+}
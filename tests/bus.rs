@@ -0,0 +1,159 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+
+use opifex::{
+    bus::{Bus, Published},
+    event::EventReceiver,
+    handle, Task,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    time::sleep,
+};
+
+// Forwards the topics and the events it receives.
+struct Collect {
+    received: UnboundedSender<(String, u32)>,
+}
+
+impl Task for Collect {
+    type Handle = handle::Worker<handle::OnEvent<Published<u32>>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let received = self.received.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Ok(Published { topic, event }) = rx.recv() => {
+                        let _ = received.send((topic, event));
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn topics(rx: &mut EventReceiver<Published<u32>>) -> Vec<String> {
+    let mut topics = Vec::new();
+    while let Ok(published) = rx.try_recv() {
+        topics.push(published.topic);
+    }
+    topics
+}
+
+#[tokio::test]
+async fn the_patterns_select_the_topics() {
+    let bus = Bus::<u32>::new();
+    let mut created = bus.subscribe("orders.*.created");
+    let mut orders = bus.subscribe("orders.#");
+    let mut all = bus.subscribe("#");
+    let mut eu = bus.subscribe("orders.eu");
+
+    assert_eq!(bus.publish("orders.eu.created", 1), 3);
+    assert_eq!(bus.publish("orders", 2), 2);
+    assert_eq!(bus.publish("orders.eu", 3), 3);
+    assert_eq!(bus.publish("orders.eu.created.late", 4), 2);
+    assert_eq!(bus.publish("payments.eu.created", 5), 1);
+
+    assert_eq!(topics(&mut created), ["orders.eu.created"]);
+    assert_eq!(
+        topics(&mut orders),
+        [
+            "orders.eu.created",
+            "orders",
+            "orders.eu",
+            "orders.eu.created.late"
+        ]
+    );
+    assert_eq!(topics(&mut all).len(), 5);
+    assert_eq!(topics(&mut eu), ["orders.eu"]);
+}
+
+#[tokio::test]
+async fn the_subscriber_tasks_receive_the_events() {
+    let bus = Bus::<u32>::new();
+    let (received, mut rx) = unbounded_channel();
+    let subscriber = bus.on_topic("sensors.*", Collect { received });
+
+    // published from another task
+    let publisher = bus.clone();
+    tokio::spawn(async move {
+        publisher.publish("sensors.temperature", 21);
+        publisher.publish("alarms.temperature", 50);
+        publisher.publish("sensors.humidity", 40);
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(rx.recv().await, Some(("sensors.temperature".into(), 21)));
+    assert_eq!(rx.recv().await, Some(("sensors.humidity".into(), 40)));
+
+    subscriber.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn terminating_the_subscriber_removes_the_subscription() {
+    let bus = Bus::<u32>::new();
+    let (received, _rx) = unbounded_channel();
+    let subscriber = bus.on_topic("#", Collect { received });
+    assert_eq!(bus.publish("a", 1), 1);
+
+    subscriber.terminate();
+    sleep(Duration::from_millis(10)).await;
+    assert_eq!(bus.publish("a", 2), 0);
+}
+
+// Hands its receiver over and finishes.
+struct HandOver {
+    receivers: UnboundedSender<EventReceiver<Published<u32>>>,
+}
+
+impl Task for HandOver {
+    type Handle = handle::Worker<handle::OnEvent<Published<u32>>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (rx, _hnd) = wk_hnd.receiver();
+        let _ = self.receivers.send(rx);
+        async {}
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_finished_subscriber_removes_the_subscription() {
+    let bus = Bus::<u32>::new();
+    let (receivers, mut rx) = unbounded_channel();
+    let subscriber = bus.on_topic("#", HandOver { receivers });
+    subscriber.finished().await;
+
+    // the receiver is still alive, but nobody is going to read it
+    let _receiver = rx.recv().await.unwrap();
+    sleep(Duration::from_millis(10)).await;
+    assert_eq!(bus.publish("a", 1), 0);
+}
+
+#[tokio::test]
+async fn dropping_the_receiver_removes_the_subscription() {
+    let bus = Bus::<u32>::new();
+    let rx = bus.subscribe("a.b");
+    assert_eq!(bus.publish("a.b", 1), 1);
+
+    drop(rx);
+    assert_eq!(bus.publish("a.b", 2), 0);
+}
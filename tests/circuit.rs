@@ -0,0 +1,250 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+mod common;
+
+use std::time::Duration;
+
+use opifex::{
+    circuit::{CircuitBreaker, CircuitPolicy, CircuitState, Outcomes, Reporter, Transition},
+    event::EventReceiver,
+    handle,
+    worker::OneWay,
+    Error, ErrorKind, Task, Worker,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::sleep,
+};
+
+use CircuitState::{Closed, HalfOpen, Open};
+
+use common::Quit;
+
+const OPEN_FOR: Duration = Duration::from_secs(1);
+
+// How the processing of a message goes: None when the outcome is never
+// reported.
+type Outcome = Option<bool>;
+
+// Takes some time to process a message, then reports its outcome and tells
+// it's done.
+struct Processor {
+    done: UnboundedSender<Outcome>,
+}
+
+impl Task for Processor {
+    type Handle = handle::Worker<handle::OneWay<(Outcome, Reporter)>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let done = self.done.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some((outcome, reporter)) = rx.recv() => {
+                        sleep(Duration::from_millis(100)).await;
+                        match outcome {
+                            Some(true) => reporter.success(),
+                            Some(false) => reporter.failure(),
+                            None => {}
+                        }
+                        let _ = done.send(outcome);
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+type Breaker = CircuitBreaker<Worker<OneWay<(Outcome, Reporter)>>>;
+
+// A circuit opening when half of the last 4 messages failed.
+fn breaker(probes: u32) -> (Breaker, UnboundedReceiver<Outcome>) {
+    let (done, rx) = unbounded_channel();
+    let policy = CircuitPolicy::new()
+        .with_window(4)
+        .with_min_calls(4)
+        .with_open_for(OPEN_FOR)
+        .with_probes(probes)
+        .with_outcomes(Outcomes::Reported);
+    let worker = Worker::<OneWay<(Outcome, Reporter)>>::spawn(Processor { done });
+    (CircuitBreaker::new(worker, policy), rx)
+}
+
+async fn post(breaker: &Breaker, outcome: Outcome) -> Result<(), Error> {
+    breaker.post_message((outcome, breaker.reporter())).await
+}
+
+// Posts the outcomes, waiting for each one to be processed.
+async fn process(breaker: &Breaker, done: &mut UnboundedReceiver<Outcome>, outcomes: &[Outcome]) {
+    for outcome in outcomes {
+        post(breaker, *outcome).await.unwrap();
+        assert_eq!(done.recv().await, Some(*outcome));
+    }
+}
+
+async fn open(breaker: &Breaker, done: &mut UnboundedReceiver<Outcome>) {
+    process(breaker, done, &[Some(false); 4]).await;
+    assert_eq!(breaker.state(), Open);
+}
+
+fn transition(rx: &mut EventReceiver<Transition>) -> (CircuitState, CircuitState) {
+    let Transition { from, to } = rx.try_recv().unwrap();
+    (from, to)
+}
+
+#[tokio::test(start_paused = true)]
+async fn too_many_failures_open_the_circuit() {
+    let (breaker, mut done) = breaker(1);
+    let mut transitions = breaker.subscribe();
+
+    // not before the min calls
+    process(&breaker, &mut done, &[Some(false), Some(true), Some(false)]).await;
+    assert_eq!(breaker.state(), Closed);
+    process(&breaker, &mut done, &[Some(true)]).await;
+    assert_eq!(breaker.state(), Open);
+    assert_eq!(transition(&mut transitions), (Closed, Open));
+
+    // the posts fail fast, without reaching the task
+    let e = post(&breaker, Some(true)).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+    sleep(Duration::from_secs(1) / 2).await;
+    assert!(done.try_recv().is_err());
+
+    breaker.into_worker().terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_old_outcomes_leave_the_window() {
+    let (breaker, mut done) = breaker(1);
+
+    process(&breaker, &mut done, &[Some(false), Some(true), Some(true)]).await;
+    process(&breaker, &mut done, &[Some(true), Some(false)]).await;
+    assert_eq!(breaker.state(), Closed);
+    process(&breaker, &mut done, &[Some(false)]).await;
+    assert_eq!(breaker.state(), Open);
+
+    breaker.into_worker().terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_successful_probe_closes_the_circuit() {
+    let (breaker, mut done) = breaker(1);
+    open(&breaker, &mut done).await;
+    let mut transitions = breaker.subscribe();
+
+    // still open
+    sleep(OPEN_FOR / 2).await;
+    let e = post(&breaker, Some(true)).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+
+    // a single probe is let through, while the others fail
+    sleep(OPEN_FOR / 2).await;
+    post(&breaker, Some(true)).await.unwrap();
+    assert_eq!(breaker.state(), HalfOpen);
+    let e = post(&breaker, Some(true)).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+
+    assert_eq!(done.recv().await, Some(Some(true)));
+    assert_eq!(breaker.state(), Closed);
+    assert_eq!(transition(&mut transitions), (Open, HalfOpen));
+    assert_eq!(transition(&mut transitions), (HalfOpen, Closed));
+
+    // with a clean window
+    process(&breaker, &mut done, &[Some(false); 3]).await;
+    assert_eq!(breaker.state(), Closed);
+
+    breaker.into_worker().terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_failed_probe_opens_the_circuit_again() {
+    let (breaker, mut done) = breaker(1);
+    open(&breaker, &mut done).await;
+    let mut transitions = breaker.subscribe();
+
+    sleep(OPEN_FOR).await;
+    process(&breaker, &mut done, &[Some(false)]).await;
+    assert_eq!(breaker.state(), Open);
+    assert_eq!(transition(&mut transitions), (Open, HalfOpen));
+    assert_eq!(transition(&mut transitions), (HalfOpen, Open));
+
+    // for another while
+    let e = post(&breaker, Some(true)).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+    sleep(OPEN_FOR).await;
+    process(&breaker, &mut done, &[Some(true)]).await;
+    assert_eq!(breaker.state(), Closed);
+
+    breaker.into_worker().terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn all_the_probes_must_succeed() {
+    let (breaker, mut done) = breaker(2);
+    open(&breaker, &mut done).await;
+
+    sleep(OPEN_FOR).await;
+    post(&breaker, Some(true)).await.unwrap();
+    post(&breaker, Some(true)).await.unwrap();
+    let e = post(&breaker, Some(true)).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+
+    assert_eq!(done.recv().await, Some(Some(true)));
+    assert_eq!(breaker.state(), HalfOpen);
+    assert_eq!(done.recv().await, Some(Some(true)));
+    assert_eq!(breaker.state(), Closed);
+
+    breaker.into_worker().terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_probe_without_outcome_is_given_another_round() {
+    let (breaker, mut done) = breaker(1);
+    open(&breaker, &mut done).await;
+
+    sleep(OPEN_FOR).await;
+    process(&breaker, &mut done, &[None]).await;
+    let e = post(&breaker, Some(true)).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+
+    // the next probe is let through after another while
+    sleep(OPEN_FOR).await;
+    process(&breaker, &mut done, &[Some(true)]).await;
+    assert_eq!(breaker.state(), Closed);
+
+    breaker.into_worker().terminate();
+}
+
+#[tokio::test]
+async fn undelivered_messages_are_failures() {
+    let worker = Worker::<OneWay<u32>>::spawn(Quit);
+    worker.finished().await;
+    let breaker = CircuitBreaker::new(
+        worker,
+        CircuitPolicy::new().with_window(2).with_min_calls(2),
+    );
+    let mut transitions = breaker.subscribe();
+
+    let e = breaker.post_message(1).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+    assert_eq!(breaker.state(), Closed);
+    let e = breaker.post_message(2).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+    assert_eq!(breaker.state(), Open);
+    assert_eq!(transition(&mut transitions), (Closed, Open));
+
+    let e = breaker.post_message(3).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+}
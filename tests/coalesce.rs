@@ -0,0 +1,157 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use opifex::{handle, worker::Coalescing, ErrorKind, Task, Worker};
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        Notify,
+    },
+    time::{sleep, Instant},
+};
+
+type Message = (char, u32);
+
+// the messages with the millis they were received at
+type Received = UnboundedReceiver<(u128, Option<Message>)>;
+
+const WINDOW: Duration = Duration::from_millis(100);
+
+// Waits for `start`, then forwards the messages it receives with the millis
+// since it was spawned, and None when the worker has gone.
+struct Collect {
+    start: Arc<Notify>,
+    received: UnboundedSender<(u128, Option<Message>)>,
+}
+
+impl Task for Collect {
+    type Handle = handle::Worker<handle::Coalescing<Message, char>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let (start, received) = (self.start.clone(), self.received.clone());
+        let spawned = Instant::now();
+
+        async move {
+            start.notified().await;
+            loop {
+                let msg = rx.recv().await;
+                let end = msg.is_none();
+                let _ = received.send((spawned.elapsed().as_millis(), msg));
+                if end {
+                    break;
+                }
+            }
+            drop(hnd);
+        }
+    }
+}
+
+// Finishes as soon as it starts.
+struct Quit;
+
+impl Task for Quit {
+    type Handle = handle::Worker<handle::Coalescing<Message, char>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (rx, _hnd) = wk_hnd.receiver();
+        async move { drop(rx) }
+    }
+}
+
+fn collect() -> (Collect, Arc<Notify>, Received) {
+    let (received, rx) = unbounded_channel();
+    let start = Arc::new(Notify::new());
+    let task = Collect {
+        start: start.clone(),
+        received,
+    };
+    (task, start, rx)
+}
+
+#[tokio::test]
+async fn a_newer_message_supersedes_the_pending_one() {
+    let (task, start, mut rx) = collect();
+    let worker = Worker::<Coalescing<Message, char>>::spawn(task, |msg: &Message| msg.0);
+
+    for msg in [('a', 1), ('b', 1), ('a', 2), ('c', 1), ('b', 2)] {
+        worker.post_message(msg).await.unwrap();
+    }
+    start.notify_one();
+
+    // the superseding messages keep the place of the first ones
+    for expected in [('a', 2), ('b', 2), ('c', 1)] {
+        assert_eq!(rx.recv().await.unwrap().1, Some(expected));
+    }
+    assert!(rx.try_recv().is_err());
+
+    // a received message isn't pending anymore
+    worker.post_message(('a', 3)).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap().1, Some(('a', 3)));
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_debounce_waits_for_the_last_message() {
+    let (task, start, mut rx) = collect();
+    let worker = Worker::<Coalescing<Message, char>>::spawn_with_debounce(
+        task,
+        |msg: &Message| msg.0,
+        WINDOW,
+    );
+    start.notify_one();
+
+    worker.post_message(('a', 1)).await.unwrap();
+    worker.post_message(('b', 1)).await.unwrap();
+    sleep(Duration::from_millis(60)).await;
+    worker.post_message(('a', 2)).await.unwrap();
+
+    assert_eq!(rx.recv().await, Some((100, Some(('b', 1)))));
+    assert_eq!(rx.recv().await, Some((160, Some(('a', 2)))));
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_pending_messages_are_received_when_the_worker_goes() {
+    let (task, start, mut rx) = collect();
+    let worker = Worker::<Coalescing<Message, char>>::spawn_with_debounce(
+        task,
+        |msg: &Message| msg.0,
+        WINDOW,
+    );
+    start.notify_one();
+
+    worker.post_message(('a', 1)).await.unwrap();
+    worker.post_message(('b', 1)).await.unwrap();
+    drop(worker);
+
+    // without waiting for the debounce
+    assert_eq!(rx.recv().await, Some((0, Some(('a', 1)))));
+    assert_eq!(rx.recv().await, Some((0, Some(('b', 1)))));
+    assert_eq!(rx.recv().await, Some((0, None)));
+}
+
+#[tokio::test]
+async fn posting_fails_when_the_task_has_gone() {
+    let worker = Worker::<Coalescing<Message, char>>::spawn(Quit, |msg: &Message| msg.0);
+    worker.finished().await;
+
+    let e = worker.post_message(('a', 1)).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+}
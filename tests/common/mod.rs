@@ -0,0 +1,57 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+//! Tasks shared by the integration tests.
+
+// every test uses only some of them
+#![allow(dead_code)]
+
+use opifex::{handle, Task};
+use tokio::sync::mpsc::UnboundedSender;
+
+// Finishes as soon as it starts.
+pub struct Quit;
+
+impl Task for Quit {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (rx, _hnd) = wk_hnd.receiver();
+        async move { drop(rx) }
+    }
+}
+
+// Forwards the events it receives.
+pub struct Collect<Event> {
+    pub received: UnboundedSender<Event>,
+}
+
+impl<Event: Clone + Send + 'static> Task for Collect<Event> {
+    type Handle = handle::Worker<handle::OnEvent<Event>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let received = self.received.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Ok(event) = rx.recv() => { let _ = received.send(event); }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+
+use opifex::{control::Control, handle, worker::OneWay, ErrorKind, Task, Worker, BUFFER_CAPACITY};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    time::timeout,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Reload(u32);
+
+// Forwards the control commands it receives.
+struct Controlled {
+    commands: UnboundedSender<Control<Reload>>,
+}
+
+impl Task for Controlled {
+    type Handle = handle::Worker<handle::OneWay<u32>, Reload>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let commands = self.commands.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(cmd) = hnd.control() => { let _ = commands.send(cmd); }
+                    Some(_) = rx.recv() => {}
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+// Never receives the control commands, and finishes on the first message.
+struct Deaf;
+
+impl Task for Deaf {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        async move {
+            rx.recv().await;
+            drop(hnd);
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_commands_reach_the_task() {
+    let (commands, mut rx) = unbounded_channel();
+    let worker = Worker::<OneWay<u32>, Reload>::spawn(Controlled { commands });
+
+    worker.pause().await.unwrap();
+    worker.resume().await.unwrap();
+    worker.flush().await.unwrap();
+    worker.custom(Reload(7)).await.unwrap();
+
+    for expected in [
+        Control::Pause,
+        Control::Resume,
+        Control::Flush,
+        Control::Custom(Reload(7)),
+    ] {
+        assert_eq!(rx.recv().await, Some(expected));
+    }
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_commands_wait_when_not_received() {
+    let worker = Worker::<OneWay<u32>>::spawn(Deaf);
+
+    for _ in 0..BUFFER_CAPACITY {
+        worker.flush().await.unwrap();
+    }
+    let blocked = timeout(Duration::from_secs(1), worker.pause()).await;
+    assert!(blocked.is_err());
+
+    // until the task finishes
+    worker.post_message(0).await.unwrap();
+    worker.finished().await;
+    let e = worker.pause().await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+}
@@ -0,0 +1,96 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+mod common;
+
+use opifex::{
+    dead_letter::{self, DeadLetterQueue, Reason},
+    worker::OneWay,
+    ErrorKind, Worker,
+};
+
+use common::Quit;
+
+async fn finished() -> Worker<OneWay<u32>> {
+    let worker = Worker::<OneWay<u32>>::spawn(Quit);
+    worker.finished().await;
+    worker
+}
+
+#[tokio::test]
+async fn collects_the_messages_posted_to_a_finished_task() {
+    let worker = finished().await;
+    let queue = DeadLetterQueue::new(10);
+    worker.set_dead_letters(queue.clone());
+
+    for n in 1..=2 {
+        let e = worker.post_message(n).await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Terminated);
+    }
+    assert_eq!(queue.len(), 2);
+
+    let letters = queue.drain();
+    assert!(queue.is_empty());
+    for (letter, n) in letters.iter().zip(1..) {
+        assert_eq!(letter.source, worker.id());
+        assert_eq!(letter.reason, Reason::Terminated);
+        assert_eq!(letter.type_name(), "u32");
+
+        // the message is taken once, as the right type
+        assert_eq!(letter.take_message::<String>(), None);
+        assert_eq!(letter.take_message::<u32>(), Some(n));
+        assert_eq!(letter.take_message::<u32>(), None);
+    }
+}
+
+#[tokio::test]
+async fn keeps_the_last_letters() {
+    let worker = finished().await;
+    let queue = DeadLetterQueue::new(2);
+    worker.set_dead_letters(queue.clone());
+    let mut subscriber = queue.subscribe();
+
+    for n in 1..=3 {
+        let _ = worker.post_message(n).await;
+    }
+
+    // the subscribers receive every letter...
+    let mut received = Vec::new();
+    for _ in 1..=3 {
+        received.push(subscriber.recv().await.unwrap());
+    }
+    // ...the queue keeps the last ones, that are the same letters
+    let kept = queue.drain();
+    assert_eq!(received[2].take_message::<u32>(), Some(3));
+    assert_eq!(kept[1].take_message::<u32>(), None);
+    assert_eq!(kept[0].take_message::<u32>(), Some(2));
+    assert_eq!(received[0].take_message::<u32>(), Some(1));
+}
+
+// The only test using the global queue, as it is shared by the whole test
+// binary.
+#[tokio::test]
+async fn the_global_queue_collects_for_the_workers_without_one() {
+    let global = DeadLetterQueue::new(10);
+    dead_letter::set_global(global.clone());
+
+    let without = finished().await;
+    let with = finished().await;
+    let own = DeadLetterQueue::new(10);
+    with.set_dead_letters(own.clone());
+
+    let _ = without.post_message(1).await;
+    let _ = with.post_message(2).await;
+    assert_eq!(global.drain()[0].source, without.id());
+    assert_eq!(own.drain()[0].source, with.id());
+
+    // once cleared the letters are dropped
+    dead_letter::clear_global();
+    let e = without.post_message(3).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+    assert!(global.is_empty());
+}
@@ -0,0 +1,223 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+#![cfg(feature = "durable")]
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use opifex::{
+    codec::Codec,
+    dead_letter::DeadLetterQueue,
+    durable::{FsyncPolicy, Journal},
+    handle,
+    worker::Durable,
+    ErrorKind, Task, Worker, BUFFER_CAPACITY,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+// Receives the messages, acknowledging the ones for which `ack` is true, and
+// forwards them with their redelivered flag.
+struct Consumer {
+    received: UnboundedSender<(u64, bool)>,
+    ack: fn(u64) -> bool,
+}
+
+impl Task for Consumer {
+    type Handle = handle::Worker<handle::Durable<u64>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let received = self.received.clone();
+        let ack = self.ack;
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(delivery) = rx.recv() => {
+                        if ack(delivery.message) {
+                            rx.ack(delivery.seq).await.unwrap();
+                        }
+                        let _ = received.send((delivery.message, delivery.redelivered));
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn journal(name: &str) -> Journal {
+    Journal::new(journal_path(name)).with_fsync(FsyncPolicy::Never)
+}
+
+fn journal_path(name: &str) -> PathBuf {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("opifex-{name}-{}.journal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// Frames a `record` of a JSON journal.
+fn frame(record: &str) -> Vec<u8> {
+    let mut frame = (record.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(record.as_bytes());
+    frame
+}
+
+fn consumer(ack: fn(u64) -> bool) -> (Consumer, UnboundedReceiver<(u64, bool)>) {
+    let (received, rx) = unbounded_channel();
+    (Consumer { received, ack }, rx)
+}
+
+#[tokio::test]
+async fn posts_over_the_mailbox_capacity_while_acking() {
+    let (task, mut received) = consumer(|_| true);
+    let worker = Arc::new(Worker::<Durable<u64>>::spawn(task, journal("capacity")).unwrap());
+
+    // many posters, so that one holds the journal while the mailbox is full
+    let posters = 4;
+    let count = BUFFER_CAPACITY as u64;
+    tokio::time::timeout(Duration::from_secs(30), async {
+        let mut tasks = Vec::new();
+        for p in 0..posters {
+            let worker = worker.clone();
+            tasks.push(tokio::spawn(async move {
+                for i in 0..count {
+                    worker.post_message(p * count + i).await.unwrap();
+                }
+            }));
+        }
+        // the messages of every poster arrive in order
+        let mut next = vec![0; posters as usize];
+        for _ in 0..posters * count {
+            let (msg, redelivered) = received.recv().await.unwrap();
+            assert!(!redelivered);
+            let poster = (msg / count) as usize;
+            assert_eq!(msg % count, next[poster]);
+            next[poster] += 1;
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+    })
+    .await
+    .expect("posting blocked");
+}
+
+#[tokio::test]
+async fn replays_the_unacked_messages_after_a_reopen() {
+    let journal = journal("replay");
+
+    let (task, mut received) = consumer(|msg| msg % 2 == 0);
+    let worker = Worker::<Durable<u64>>::spawn(task, journal.clone()).unwrap();
+    for i in 0..6 {
+        worker.post_message(i).await.unwrap();
+    }
+    for i in 0..6 {
+        assert_eq!(received.recv().await, Some((i, false)));
+    }
+    worker.terminate();
+
+    let (task, mut received) = consumer(|_| true);
+    let worker = Worker::<Durable<u64>>::spawn(task, journal.clone()).unwrap();
+    worker.post_message(6).await.unwrap();
+    for expected in [(1, true), (3, true), (5, true), (6, false)] {
+        assert_eq!(received.recv().await, Some(expected));
+    }
+    worker.terminate();
+
+    // everything has been acknowledged
+    let (task, mut received) = consumer(|_| true);
+    let worker = Worker::<Durable<u64>>::spawn(task, journal).unwrap();
+    worker.post_message(7).await.unwrap();
+    assert_eq!(received.recv().await, Some((7, false)));
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn a_bad_record_fails_the_spawn_and_keeps_the_journal() {
+    let path = journal_path("bad-record");
+    let bytes = [
+        frame(r#"{"Message":[0,10]}"#),
+        frame(r#"{"Message":[1,"eleven"]}"#),
+        frame(r#"{"Message":[2,12]}"#),
+        frame(r#"{"Ack":0}"#),
+    ]
+    .concat();
+    std::fs::write(&path, &bytes).unwrap();
+
+    let journal = Journal::new(&path)
+        .with_codec(Codec::Json)
+        .with_fsync(FsyncPolicy::Never);
+    let (task, _received) = consumer(|_| true);
+    let e = Worker::<Durable<u64>>::spawn(task, journal).err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::Other);
+    assert!(e.to_string().contains("bad record at byte 22"));
+
+    // the records after the bad one are still there
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+}
+
+#[tokio::test]
+async fn a_torn_record_at_the_end_is_discarded() {
+    let path = journal_path("torn-record");
+    let torn = frame(r#"{"Message":[2,12]}"#);
+    let bytes = [
+        frame(r#"{"Message":[0,10]}"#),
+        frame(r#"{"Message":[1,11]}"#),
+        frame(r#"{"Ack":0}"#),
+        torn[..10].to_vec(),
+    ]
+    .concat();
+    std::fs::write(&path, bytes).unwrap();
+
+    let journal = Journal::new(&path)
+        .with_codec(Codec::Json)
+        .with_fsync(FsyncPolicy::Never);
+    let (task, mut received) = consumer(|_| true);
+    let worker = Worker::<Durable<u64>>::spawn(task, journal).unwrap();
+    worker.post_message(13).await.unwrap();
+    assert_eq!(received.recv().await, Some((11, true)));
+    assert_eq!(received.recv().await, Some((13, false)));
+    worker.terminate();
+}
+
+// Ends at once, dropping its mailbox.
+struct Quitter;
+
+impl Task for Quitter {
+    type Handle = handle::Worker<handle::Durable<u64>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        drop(wk_hnd);
+        async {}
+    }
+}
+
+#[tokio::test]
+async fn dead_letters_the_messages_posted_after_termination() {
+    let worker = Worker::<Durable<u64>>::spawn(Quitter, journal("terminated")).unwrap();
+    let dead_letters = DeadLetterQueue::new(10);
+    worker.set_dead_letters(dead_letters.clone());
+
+    let e = worker.post_message(1).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+    assert!(e.to_string().contains(&worker.id().to_string()), "{e}");
+
+    let letters = dead_letters.drain();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].source, worker.id());
+    assert_eq!(letters[0].take_message::<u64>(), Some(1));
+}
@@ -0,0 +1,89 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+mod common;
+
+use std::time::Duration;
+
+use opifex::{event::Replay, handle, worker::Emitter, Task, Worker};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc::unbounded_channel},
+    time::{sleep, Instant},
+};
+
+use common::Collect;
+
+const PERIOD: Duration = Duration::from_millis(10);
+
+// Emits a reading every PERIOD, numbered from 1, until terminated.
+struct Sensor;
+
+impl Task for Sensor {
+    type Handle = handle::Worker<handle::OneWayBack<u32>>;
+    type Output = ();
+
+    fn spawn(&self, hnd: Self::Handle) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let start = Instant::now();
+
+        async move {
+            let mut reading = 0;
+            loop {
+                reading += 1;
+                tokio::select! {
+                    () = tokio::time::sleep_until(start + PERIOD * reading) => {
+                        let _ = hnd.post_message(reading).await;
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_subscribers_receive_the_emitted_events() {
+    let sensor = Worker::<Emitter<u32>>::spawn(Sensor);
+    let (received, mut rx) = unbounded_channel();
+    let subscriber = sensor.on_message(Collect { received });
+    let mut readings = sensor.subscribe();
+
+    for expected in 1..=3 {
+        assert_eq!(rx.recv().await, Some(expected));
+        assert_eq!(readings.recv().await.unwrap(), expected);
+    }
+
+    subscriber.terminate();
+    sensor.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_late_subscriber_gets_the_replay() {
+    let sensor = Worker::<Emitter<u32>>::spawn_with_replay(Sensor, Replay::Last(2));
+    sleep(PERIOD * 3 + PERIOD / 2).await;
+
+    let mut readings = sensor.subscribe();
+    for expected in 2..=4 {
+        assert_eq!(readings.recv().await.unwrap(), expected);
+    }
+
+    sensor.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_subscriptions_end_with_the_task() {
+    let sensor = Worker::<Emitter<u32>>::spawn(Sensor);
+    let mut readings = sensor.subscribe();
+    assert_eq!(readings.recv().await.unwrap(), 1);
+
+    sensor.terminate();
+    let end = loop {
+        if let Err(e) = readings.recv().await {
+            break e;
+        }
+    };
+    assert_eq!(end, RecvError::Closed);
+}
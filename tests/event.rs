@@ -0,0 +1,99 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+mod common;
+
+use opifex::{
+    dead_letter::{DeadLetterQueue, Reason},
+    event::Replay,
+    handle,
+    worker::{Isolated, TwoWay},
+    Task, Worker,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use common::Collect;
+
+// Sends back the messages it receives, telling how many subscribers got
+// them.
+struct Echo {
+    sent: UnboundedSender<usize>,
+}
+
+impl Task for Echo {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let sent = self.sent.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(msg) = rx.recv() => {
+                        let _ = sent.send(hnd.post_message(msg).await.unwrap());
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn collect(worker: &Worker<TwoWay<u32, u32>>) -> (Worker<Isolated>, UnboundedReceiver<u32>) {
+    let (received, rx) = unbounded_channel();
+    (worker.on_message(Collect { received }), rx)
+}
+
+#[tokio::test]
+async fn replays_the_events_sent_before_subscribing() {
+    let (sent, mut sent_rx) = unbounded_channel();
+    let worker = Worker::<TwoWay<u32, u32>>::spawn_with_replay(Echo { sent }, Replay::Last(2));
+    let dead_letters = DeadLetterQueue::new(10);
+    worker.set_dead_letters(dead_letters.clone());
+
+    for n in 1..=3 {
+        worker.post_message(n).await.unwrap();
+        assert_eq!(sent_rx.recv().await, Some(0));
+    }
+    // kept for the subscribers to come, not lost
+    assert!(dead_letters.is_empty());
+
+    let (subscriber, mut received) = collect(&worker);
+    assert_eq!(received.recv().await, Some(2));
+    assert_eq!(received.recv().await, Some(3));
+
+    worker.post_message(4).await.unwrap();
+    assert_eq!(sent_rx.recv().await, Some(1));
+    assert_eq!(received.recv().await, Some(4));
+
+    subscriber.terminate();
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn events_nobody_receives_are_dead_letters() {
+    let (sent, mut sent_rx) = unbounded_channel();
+    let worker = Worker::<TwoWay<u32, u32>>::spawn(Echo { sent });
+    let dead_letters = DeadLetterQueue::new(10);
+    worker.set_dead_letters(dead_letters.clone());
+
+    worker.post_message(1).await.unwrap();
+    assert_eq!(sent_rx.recv().await, Some(0));
+
+    let letters = dead_letters.drain();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].source, worker.id());
+    assert_eq!(letters[0].reason, Reason::NoSubscribers);
+    assert_eq!(letters[0].take_message::<u32>(), Some(1));
+
+    worker.terminate();
+}
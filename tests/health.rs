@@ -0,0 +1,166 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+
+use opifex::{
+    handle,
+    health::{HealthRegistry, Status},
+    watchdog::Watchdog,
+    worker::OneWay,
+    ErrorKind, Task, Worker,
+};
+use tokio::time::{interval_at, sleep, Instant};
+
+const PERIOD: Duration = Duration::from_millis(100);
+const TIMEOUT: Duration = Duration::from_millis(250);
+
+// Beats every PERIOD, starting after the first one, and takes the millis it
+// receives to process a message, without beating meanwhile.
+struct Beater;
+
+impl Task for Beater {
+    type Handle = handle::Worker<handle::OneWay<u64>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            let mut beat = interval_at(Instant::now() + PERIOD, PERIOD);
+            loop {
+                tokio::select! {
+                    _ = beat.tick() => hnd.heartbeat(),
+                    Some(millis) = rx.recv() => {
+                        let _guard = hnd.start_message();
+                        sleep(Duration::from_millis(millis)).await;
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn beater() -> Worker<OneWay<u64>> {
+    let worker = Worker::<OneWay<u64>>::spawn(Beater);
+    worker.set_heartbeat_timeout(TIMEOUT);
+    worker
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_beating_task_is_healthy() {
+    let start = Instant::now();
+    let worker = beater();
+
+    let health = worker.health();
+    assert_eq!(health.status, Status::Starting);
+    assert_eq!(health.last_heartbeat, None);
+    assert!(health.status.is_live() && !health.status.is_ready());
+
+    sleep(PERIOD + PERIOD / 2).await;
+    let health = worker.health();
+    assert_eq!(health.status, Status::Healthy);
+    assert_eq!(health.last_heartbeat, Some(start + PERIOD));
+    assert_eq!(worker.last_heartbeat(), Some(start + PERIOD));
+    assert!(health.status.is_ready());
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_task_not_beating_in_time_is_stale() {
+    let start = Instant::now();
+    let worker = beater();
+    sleep(PERIOD + PERIOD / 2).await;
+
+    // busy for a second since 150 millis, the last beat at 100
+    worker.post_message(1000).await.unwrap();
+    sleep(TIMEOUT - PERIOD / 2 - Duration::from_millis(1)).await;
+    assert_eq!(worker.health().status, Status::Healthy);
+    sleep(Duration::from_millis(2)).await;
+    let health = worker.health();
+    assert_eq!(health.status, Status::Stale);
+    assert_eq!(health.last_heartbeat, Some(start + PERIOD));
+    assert!(!health.status.is_live());
+
+    // beating again after the message
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(worker.health().status, Status::Healthy);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_task_never_beating_is_stale() {
+    let worker = beater();
+    worker.post_message(1000).await.unwrap();
+
+    sleep(TIMEOUT - Duration::from_millis(1)).await;
+    assert_eq!(worker.health().status, Status::Starting);
+    sleep(Duration::from_millis(2)).await;
+    let health = worker.health();
+    assert_eq!(health.status, Status::Stale);
+    assert_eq!(health.last_heartbeat, None);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_stuck_message_is_stalled() {
+    let worker = beater();
+    worker.set_watchdog(Watchdog::new(Duration::from_millis(150)));
+    sleep(PERIOD + PERIOD / 2).await;
+
+    // the beats stop, but the watchdog finds out first
+    worker.post_message(1000).await.unwrap();
+    sleep(Duration::from_millis(200)).await;
+    let health = worker.health();
+    assert_eq!(health.status, Status::Stalled);
+    assert!(!health.status.is_live());
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_registry_sums_up_the_workers() {
+    let registry = HealthRegistry::new();
+    let first = beater();
+    sleep(PERIOD + PERIOD / 2).await;
+    let second = beater();
+    registry.register(&first);
+    registry.register(&second);
+
+    let report = registry.report();
+    assert!(report.live && !report.ready);
+    let statuses: Vec<_> = report.workers.iter().map(|h| h.status).collect();
+    assert_eq!(statuses, [Status::Healthy, Status::Starting]);
+    assert_eq!(report.workers[0].worker, first.id());
+
+    sleep(PERIOD + PERIOD / 2).await;
+    let report = registry.report();
+    assert!(report.live && report.ready);
+
+    // a terminated worker stays until unregistered
+    registry.terminate(second.id()).unwrap();
+    let report = registry.report();
+    assert!(!report.live && !report.ready);
+    assert_eq!(report.workers[1].status, Status::Terminated);
+
+    registry.unregister(second.id()).unwrap();
+    let report = registry.report();
+    assert!(report.live && report.ready);
+    assert_eq!(report.workers.len(), 1);
+
+    let e = registry.unregister(second.id()).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Other);
+
+    first.terminate();
+}
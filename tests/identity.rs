@@ -0,0 +1,112 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+mod common;
+
+use std::sync::Arc;
+
+use opifex::{
+    circuit::{CircuitBreaker, CircuitPolicy},
+    handle,
+    rate_limit::{RateLimit, Throttle},
+    worker::OneWay,
+    ErrorKind, Task, Worker,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use common::Quit;
+
+// Tells the name it sees when spawned, then waits to be terminated without
+// reading its mailbox.
+struct Named {
+    seen: UnboundedSender<Option<Arc<str>>>,
+}
+
+impl Task for Named {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let _ = self.seen.send(wk_hnd.name());
+        let (rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            hnd.terminated().await;
+            drop(rx);
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_task_sees_the_name_when_spawned() {
+    let (seen, mut rx) = unbounded_channel();
+
+    let named = Worker::<OneWay<u32>>::spawn_named(Named { seen: seen.clone() }, "adder");
+    assert_eq!(rx.recv().await.unwrap().as_deref(), Some("adder"));
+    assert_eq!(named.name().as_deref(), Some("adder"));
+
+    // the name is given to that worker only
+    let unnamed = Worker::<OneWay<u32>>::spawn(Named { seen });
+    assert_eq!(rx.recv().await.unwrap(), None);
+    assert_eq!(unnamed.name(), None);
+
+    named.terminate();
+    unnamed.terminate();
+}
+
+#[tokio::test]
+async fn rate_limit_errors_tell_the_worker() {
+    let (seen, _rx) = unbounded_channel();
+    let limit = RateLimit::per_second(1)
+        .with_burst(1)
+        .with_throttle(Throttle::Reject);
+    let worker =
+        Worker::<OneWay<u32>>::spawn_with_rate_limit(Named { seen }, limit).with_name("limited");
+
+    worker.post_message(1).await.unwrap();
+    let e = worker.post_message(2).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::RateLimited);
+    assert!(e
+        .to_string()
+        .contains(&format!("{} (limited)", worker.id())));
+
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn circuit_open_errors_tell_the_worker() {
+    let worker = Worker::<OneWay<u32>>::spawn_named(Quit, "quitter");
+    worker.finished().await;
+    let id = worker.id();
+
+    let breaker = CircuitBreaker::new(
+        worker,
+        CircuitPolicy::new().with_window(1).with_min_calls(1),
+    );
+    let e = breaker.post_message(1).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+
+    let e = breaker.post_message(2).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::CircuitOpen);
+    assert!(e.to_string().contains(&format!("{id} (quitter)")));
+}
+
+#[cfg(feature = "futures")]
+#[tokio::test]
+async fn sink_errors_tell_the_worker() {
+    use futures::SinkExt;
+
+    let mut worker = Worker::<OneWay<u32>>::spawn(Quit);
+    worker.finished().await;
+
+    let e = worker.send(1).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+    assert!(e.to_string().contains(&worker.id().to_string()));
+}
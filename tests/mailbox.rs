@@ -0,0 +1,124 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::marker::PhantomData;
+
+use opifex::{
+    handle,
+    health::HealthRegistry,
+    mailbox::{Mailbox, Tokio},
+    worker::OneWay,
+    Task, Worker,
+};
+
+// Never receives its messages.
+struct Idle<Mb>(PhantomData<Mb>);
+
+impl<Mb> Task for Idle<Mb>
+where
+    Mb: Mailbox<u32>,
+    Mb::Receiver: Send + 'static,
+{
+    type Handle = handle::Worker<handle::OneWay<u32, Mb>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (rx, hnd) = wk_hnd.receiver();
+        async move {
+            hnd.terminated().await;
+            drop(rx);
+        }
+    }
+}
+
+// Tells the mailbox depth of a worker with 3 waiting messages.
+async fn depth<Mb>() -> Option<usize>
+where
+    Mb: Mailbox<u32> + Send + Sync + 'static,
+    Mb::Sender: Sync,
+    Mb::Receiver: Send + 'static,
+{
+    let worker = Worker::<OneWay<u32, Mb>>::spawn(Idle::<Mb>(PhantomData));
+    let registry = HealthRegistry::new();
+    registry.register(&worker);
+
+    for n in 0..3 {
+        worker.post_message(n).await.unwrap();
+    }
+    let depth = registry.workers()[0].mailbox_depth;
+
+    worker.terminate();
+    depth
+}
+
+#[tokio::test]
+async fn counts_the_waiting_messages() {
+    assert_eq!(depth::<Tokio>().await, Some(3));
+}
+
+#[cfg(feature = "flume")]
+#[tokio::test]
+async fn counts_the_waiting_messages_of_flume() {
+    assert_eq!(depth::<opifex::mailbox::Flume>().await, Some(3));
+}
+
+#[cfg(feature = "async-channel")]
+#[tokio::test]
+async fn counts_the_waiting_messages_of_async_channel() {
+    assert_eq!(depth::<opifex::mailbox::AsyncChannel>().await, Some(3));
+}
+
+// Hands its crossbeam receiver over and waits to be terminated.
+#[cfg(feature = "crossbeam")]
+struct HandOver {
+    receivers: std::sync::mpsc::Sender<crossbeam_channel::Receiver<u32>>,
+}
+
+#[cfg(feature = "crossbeam")]
+impl Task for HandOver {
+    type Handle = handle::Worker<handle::OneWay<u32, opifex::mailbox::Crossbeam>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (rx, hnd) = wk_hnd.receiver();
+        let _ = self.receivers.send(rx);
+        async move { hnd.terminated().await }
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+#[tokio::test]
+async fn a_cancelled_post_to_crossbeam_sends_nothing() {
+    use std::time::Duration;
+
+    use opifex::{mailbox::Crossbeam, BUFFER_CAPACITY};
+
+    let (receivers, handed_over) = std::sync::mpsc::channel();
+    let worker = Worker::<OneWay<u32, Crossbeam>>::spawn(HandOver { receivers });
+    let rx = handed_over.recv().unwrap();
+
+    for n in 0..BUFFER_CAPACITY as u32 {
+        worker.post_message(n).await.unwrap();
+    }
+    let full = tokio::time::timeout(Duration::from_millis(50), worker.post_message(999)).await;
+    assert!(full.is_err());
+
+    // there is room again, but the cancelled post is gone
+    for n in 0..BUFFER_CAPACITY as u32 {
+        assert_eq!(rx.recv(), Ok(n));
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(rx.try_recv().is_err());
+
+    worker.terminate();
+}
@@ -0,0 +1,205 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+mod common;
+
+use std::time::Duration;
+
+use opifex::{
+    handle,
+    pipeline::{Builder, Pipeline},
+    ErrorKind, Task, BUFFER_CAPACITY,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{sleep, timeout},
+};
+
+use common::Collect;
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    Terminated(usize),
+    Finished(usize),
+}
+
+// Applies `f` to the messages it receives and terminates itself on 0. It
+// takes some time to finish, to catch the next stage being terminated too early.
+struct Stage {
+    index: usize,
+    f: fn(u32) -> u32,
+    steps: UnboundedSender<Step>,
+}
+
+impl Task for Stage {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let (index, f, steps) = (self.index, self.f, self.steps.clone());
+
+        async move {
+            loop {
+                let zero = tokio::select! {
+                    Some(n) = rx.recv() => {
+                        if n != 0 {
+                            let _ = hnd.post_message(f(n)).await;
+                        }
+                        n == 0
+                    }
+                    () = hnd.terminated() => break,
+                };
+                if zero {
+                    hnd.terminate();
+                    return;
+                }
+            }
+            let _ = steps.send(Step::Terminated(index));
+            sleep(Duration::from_millis(50)).await;
+            let _ = steps.send(Step::Finished(index));
+        }
+    }
+}
+
+// Never reads its mailbox.
+struct Stuck;
+
+impl Task for Stuck {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (rx, hnd) = wk_hnd.receiver();
+        async move {
+            hnd.terminated().await;
+            drop(rx);
+        }
+    }
+}
+
+fn pipeline() -> (Pipeline<u32, u32>, UnboundedReceiver<Step>) {
+    let (steps, rx) = unbounded_channel();
+    let stage = |index, f| Stage {
+        index,
+        f,
+        steps: steps.clone(),
+    };
+    let pipeline = Builder::new(stage(0, |n| n.wrapping_add(1)))
+        .then(stage(1, |n| n * 10))
+        .then(stage(2, |n| n + 3))
+        .spawn();
+    (pipeline, rx)
+}
+
+async fn steps(rx: &mut UnboundedReceiver<Step>) -> Vec<Step> {
+    let mut steps = Vec::new();
+    while steps.len() < 6 {
+        steps.push(rx.recv().await.unwrap());
+    }
+    steps
+}
+
+fn in_order() -> Vec<Step> {
+    (0..3)
+        .flat_map(|i| [Step::Terminated(i), Step::Finished(i)])
+        .collect()
+}
+
+#[tokio::test]
+async fn chains_the_stages() {
+    let (pipeline, _steps) = pipeline();
+    let (received, mut rx) = unbounded_channel();
+    let collector = pipeline.on_message(Collect { received });
+    tokio::task::yield_now().await;
+
+    for n in 1..=3 {
+        pipeline.post_message(n).await.unwrap();
+    }
+    for expected in [23, 33, 43] {
+        assert_eq!(rx.recv().await, Some(expected));
+    }
+
+    collector.terminate();
+    pipeline.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn terminates_the_stages_in_order() {
+    let (pipeline, mut rx) = pipeline();
+    pipeline.terminate();
+    assert_eq!(steps(&mut rx).await, in_order());
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_terminated_stage_terminates_the_pipeline() {
+    let (pipeline, mut rx) = pipeline();
+
+    // the first stage sends 0 to the second one, that terminates itself
+    pipeline.post_message(u32::MAX).await.unwrap();
+
+    let mut steps = Vec::new();
+    while steps.len() < 4 {
+        steps.push(rx.recv().await.unwrap());
+    }
+    assert_eq!(
+        steps,
+        [
+            Step::Terminated(0),
+            Step::Finished(0),
+            Step::Terminated(2),
+            Step::Finished(2)
+        ]
+    );
+
+    let e = pipeline.post_message(1).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+    let e = pipeline.pause().await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropping_terminates_the_pipeline() {
+    let (pipeline, mut rx) = pipeline();
+    drop(pipeline);
+    assert_eq!(steps(&mut rx).await, in_order());
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_dropped_builder_spawns_nothing() {
+    let (steps, mut rx) = unbounded_channel();
+    let stage = |index| Stage {
+        index,
+        f: |n| n,
+        steps: steps.clone(),
+    };
+    let builder = Builder::<u32, u32>::new(stage(0)).then(stage(1));
+    drop((builder, steps));
+
+    // a spawned stage would keep its sender until terminated
+    let closed = timeout(Duration::from_secs(1), rx.recv()).await;
+    assert_eq!(closed, Ok(None));
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_slow_stage_blocks_the_posts() {
+    let pipeline = Builder::new(Stuck).spawn();
+
+    for n in 0..BUFFER_CAPACITY as u32 {
+        pipeline.post_message(n).await.unwrap();
+    }
+    let blocked = timeout(Duration::from_secs(1), pipeline.post_message(0)).await;
+    assert!(blocked.is_err());
+
+    pipeline.terminate();
+}
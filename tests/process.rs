@@ -0,0 +1,171 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+// The child processes are this same binary in a worker role, that's why it
+// runs without the test harness: the stdout of a child is reserved for the
+// frames sent to its parent.
+
+use std::{future::Future, os::unix::process::ExitStatusExt, time::Duration};
+
+use opifex::{
+    codec::Codec,
+    handle,
+    process::{self, ProcessWorker, Termination},
+    worker::{OneWay, TwoWay},
+    Task,
+};
+use tokio::time::timeout;
+
+// the signal of a killed process
+const SIGKILL: i32 = 9;
+
+// Sends back the double of every message, and terminates on 0.
+struct Doubler;
+
+impl Task for Doubler {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                let msg = tokio::select! {
+                    Some(msg) = rx.recv() => msg,
+                    () = hnd.terminated() => break,
+                };
+                if msg == 0 {
+                    hnd.terminate();
+                    break;
+                }
+                let _ = hnd.post_message(msg * 2).await;
+            }
+        }
+    }
+}
+
+// Returns the first message it receives.
+struct First;
+
+impl Task for First {
+    type Handle = handle::Worker<handle::OneWay<i32>>;
+    type Output = i32;
+
+    fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = i32> + Send + 'static {
+        let (mut rx, _hnd) = wk_hnd.receiver();
+        async move { rx.recv().await.unwrap_or(-1) }
+    }
+}
+
+fn doubler(termination: Termination) -> ProcessWorker<TwoWay<u32, u32>> {
+    ProcessWorker::<TwoWay<u32, u32>>::spawn_role("doubler", Codec::Bincode, termination).unwrap()
+}
+
+async fn sends_back_the_events() {
+    let worker = doubler(Termination::Message);
+    let mut events = worker.subscribe();
+
+    for n in 1..=3 {
+        worker.post_message(n).await.unwrap();
+        assert_eq!(events.recv().await.unwrap(), n * 2);
+    }
+
+    worker.terminate();
+    assert!(worker.wait().await.unwrap().success());
+}
+
+async fn kills_the_child() {
+    let worker = doubler(Termination::Kill);
+    worker.post_message(1).await.unwrap();
+
+    worker.terminate();
+    let status = worker.wait().await.unwrap();
+    assert_eq!(status.signal(), Some(SIGKILL));
+}
+
+async fn signals_the_child() {
+    let worker = doubler(Termination::Signal);
+    let mut events = worker.subscribe();
+
+    // once the child is serving, it terminates its task on SIGTERM and exits
+    worker.post_message(1).await.unwrap();
+    assert_eq!(events.recv().await.unwrap(), 2);
+    worker.terminate();
+    assert!(worker.wait().await.unwrap().success());
+}
+
+async fn the_child_terminates_the_worker() {
+    let worker = doubler(Termination::Message);
+
+    // the child exits on its own, without the worker terminating it
+    worker.post_message(0).await.unwrap();
+    assert!(worker.wait().await.unwrap().success());
+}
+
+async fn serves_one_way_tasks() {
+    let worker =
+        ProcessWorker::<OneWay<i32>>::spawn_role("first", Codec::Json, Termination::Message)
+            .unwrap();
+
+    worker.post_message(7).await.unwrap();
+    assert_eq!(worker.wait().await.unwrap().code(), Some(7));
+}
+
+async fn passes_only_the_given_arguments() {
+    // the child exits with the number of its arguments
+    let worker =
+        ProcessWorker::<OneWay<i32>>::spawn_role("args", Codec::Json, Termination::Message)
+            .unwrap();
+    assert_eq!(worker.wait().await.unwrap().code(), Some(0));
+
+    let mut command = process::role_command("args").unwrap();
+    command.args(["--first", "--second"]);
+    let worker =
+        ProcessWorker::<OneWay<i32>>::spawn(command, Codec::Json, Termination::Message).unwrap();
+    assert_eq!(worker.wait().await.unwrap().code(), Some(2));
+}
+
+async fn run<F: Future<Output = ()>>(name: &str, test: F) {
+    print!("test {name} ... ");
+    timeout(Duration::from_secs(30), test)
+        .await
+        .unwrap_or_else(|_| panic!("{name} timed out"));
+    println!("ok");
+}
+
+#[tokio::main]
+async fn main() {
+    match process::role().as_deref() {
+        Some("doubler") => {
+            process::serve_two_way(Doubler, Codec::Bincode)
+                .await
+                .unwrap();
+        }
+        Some("first") => {
+            let first = process::serve_one_way(First, Codec::Json).await.unwrap();
+            std::process::exit(first);
+        }
+        Some("args") => std::process::exit(std::env::args().skip(1).count() as i32),
+        _ => {
+            run("sends_back_the_events", sends_back_the_events()).await;
+            run("signals_the_child", signals_the_child()).await;
+            run("kills_the_child", kills_the_child()).await;
+            run(
+                "the_child_terminates_the_worker",
+                the_child_terminates_the_worker(),
+            )
+            .await;
+            run("serves_one_way_tasks", serves_one_way_tasks()).await;
+            run(
+                "passes_only_the_given_arguments",
+                passes_only_the_given_arguments(),
+            )
+            .await;
+        }
+    }
+}
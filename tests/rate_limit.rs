@@ -0,0 +1,147 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+
+use opifex::{
+    dead_letter::{DeadLetterQueue, Reason},
+    handle,
+    rate_limit::{RateLimit, Throttle},
+    worker::OneWay,
+    ErrorKind, Task, Worker,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{sleep, Instant},
+};
+
+// Tells when it receives every message.
+struct Recorder {
+    received: UnboundedSender<(u32, Instant)>,
+}
+
+impl Task for Recorder {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let received = self.received.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(msg) = rx.recv() => { let _ = received.send((msg, Instant::now())); }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+// 10 messages per second, one at a time.
+fn worker(throttle: Throttle) -> (Worker<OneWay<u32>>, UnboundedReceiver<(u32, Instant)>) {
+    let (received, rx) = unbounded_channel();
+    let limit = RateLimit::per_second(10)
+        .with_burst(1)
+        .with_throttle(throttle);
+    let worker = Worker::<OneWay<u32>>::spawn_with_rate_limit(Recorder { received }, limit);
+    (worker, rx)
+}
+
+fn millis(since: Instant, at: Instant) -> u128 {
+    at.duration_since(since).as_millis()
+}
+
+#[tokio::test(start_paused = true)]
+async fn delay_waits_for_the_limit() {
+    let (worker, _received) = worker(Throttle::Delay);
+    let start = Instant::now();
+
+    let mut posted = Vec::new();
+    for n in 0..3 {
+        worker.post_message(n).await.unwrap();
+        posted.push(millis(start, Instant::now()));
+    }
+    assert_eq!(posted, [0, 100, 200]);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn reject_fails_over_the_limit() {
+    let (received, mut rx) = unbounded_channel();
+    let limit = RateLimit::per_second(10)
+        .with_burst(2)
+        .with_throttle(Throttle::Reject);
+    let worker = Worker::<OneWay<u32>>::spawn_with_rate_limit(Recorder { received }, limit);
+
+    worker.post_message(1).await.unwrap();
+    worker.post_message(2).await.unwrap();
+    let e = worker.post_message(3).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::RateLimited);
+
+    // a token is back after 100ms
+    sleep(Duration::from_millis(100)).await;
+    worker.post_message(4).await.unwrap();
+    let e = worker.post_message(5).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::RateLimited);
+
+    // the rejected messages are dropped
+    for expected in [1, 2, 4] {
+        assert_eq!(rx.recv().await.unwrap().0, expected);
+    }
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn smooth_paces_the_delivery() {
+    let (worker, mut received) = worker(Throttle::Smooth);
+    let start = Instant::now();
+
+    // posting doesn't wait...
+    for n in 0..3 {
+        worker.post_message(n).await.unwrap();
+    }
+    assert_eq!(millis(start, Instant::now()), 0);
+
+    // ...the task receives the messages within the limit
+    let mut delivered = Vec::new();
+    for n in 0..3 {
+        let (msg, at) = received.recv().await.unwrap();
+        assert_eq!(msg, n);
+        delivered.push(millis(start, at));
+    }
+    assert_eq!(delivered, [0, 100, 200]);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn smooth_dead_letters_the_queued_messages() {
+    let (worker, mut received) = worker(Throttle::Smooth);
+    let dead_letters = DeadLetterQueue::new(10);
+    worker.set_dead_letters(dead_letters.clone());
+
+    for n in 0..5 {
+        worker.post_message(n).await.unwrap();
+    }
+    assert_eq!(received.recv().await.unwrap().0, 0);
+    worker.terminate();
+    sleep(Duration::from_millis(10)).await;
+
+    let letters: Vec<_> = dead_letters
+        .drain()
+        .iter()
+        .map(|letter| (letter.reason, letter.take_message::<u32>()))
+        .collect();
+    let queued: Vec<_> = (1..5).map(|n| (Reason::Terminated, Some(n))).collect();
+    assert_eq!(letters, queued);
+}
@@ -0,0 +1,296 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+#![cfg(feature = "remote")]
+
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use opifex::{
+    codec::Codec,
+    dead_letter::{DeadLetterQueue, Reason},
+    handle,
+    remote::{Endpoint, RemoteWorker},
+    worker::TwoWay,
+    ErrorKind, Task, Worker,
+};
+use serde::{ser::Error as _, Serialize, Serializer};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+
+// Sends back the double of every message.
+struct Doubler;
+
+impl Task for Doubler {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(&self, wk_hnd: Self::Handle) -> impl Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(msg) = rx.recv() => {
+                        let _ = hnd.post_message(msg * 2).await;
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+// A message that can't be encoded when it's 0, and is a u32 otherwise.
+struct Checked(u32);
+
+impl Serialize for Checked {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            0 => Err(S::Error::custom("0 can't be encoded")),
+            n => serializer.serialize_u32(n),
+        }
+    }
+}
+
+type Served = Worker<TwoWay<u32, u32>>;
+type Remote = RemoteWorker<TwoWay<u32, u32>>;
+
+#[derive(Clone, Copy)]
+enum Socket {
+    Tcp,
+    #[cfg(unix)]
+    Unix,
+}
+
+// Serves a doubler on a loopback `socket`, returning the worker and its
+// endpoint.
+async fn serve(socket: Socket, name: &str) -> (Served, Endpoint) {
+    let worker = Served::spawn(Doubler);
+    let endpoint = match socket {
+        Socket::Tcp => {
+            let addr = worker
+                .serve_tcp("127.0.0.1:0", Codec::Bincode)
+                .await
+                .unwrap();
+            Endpoint::Tcp(addr.to_string())
+        }
+        #[cfg(unix)]
+        Socket::Unix => {
+            let path = unix_path(name);
+            worker.serve_unix(&path, Codec::Bincode).await.unwrap();
+            Endpoint::Unix(path)
+        }
+    };
+    let _ = name;
+    (worker, endpoint)
+}
+
+#[cfg(unix)]
+fn unix_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("opifex-{name}-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// Fails the test if `future` takes too long.
+async fn within<F: Future>(future: F) -> F::Output {
+    tokio::time::timeout(Duration::from_secs(10), future)
+        .await
+        .expect("timed out")
+}
+
+// Waits for the remote worker to be terminated.
+async fn remote_terminated(remote: &Remote) {
+    within(async {
+        loop {
+            match remote.post_message(0).await {
+                Err(e) if e.kind() == ErrorKind::Terminated => break,
+                _ => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+    })
+    .await;
+}
+
+async fn posts_and_subscribes(socket: Socket, name: &str) {
+    let (_served, endpoint) = serve(socket, name).await;
+    let remote = Remote::connect(endpoint, Codec::Bincode);
+    let mut events = remote.subscribe();
+
+    for i in 1..=3 {
+        remote.post_message(i).await.unwrap();
+    }
+    for i in 1..=3 {
+        assert_eq!(within(events.recv()).await.unwrap(), i * 2);
+    }
+}
+
+async fn terminating_the_remote_terminates_the_served(socket: Socket, name: &str) {
+    let (served, endpoint) = serve(socket, name).await;
+    let remote = Remote::connect(endpoint, Codec::Bincode);
+    let mut events = remote.subscribe();
+
+    // connected
+    remote.post_message(1).await.unwrap();
+    assert_eq!(within(events.recv()).await.unwrap(), 2);
+
+    remote.terminate();
+    within(served.finished()).await;
+}
+
+async fn terminating_the_served_terminates_the_remote(socket: Socket, name: &str) {
+    let (served, endpoint) = serve(socket, name).await;
+    let remote = Remote::connect(endpoint, Codec::Bincode);
+    let mut events = remote.subscribe();
+
+    remote.post_message(1).await.unwrap();
+    assert_eq!(within(events.recv()).await.unwrap(), 2);
+
+    served.terminate();
+    remote_terminated(&remote).await;
+}
+
+#[tokio::test]
+async fn tcp_posts_and_subscribes() {
+    posts_and_subscribes(Socket::Tcp, "tcp-post").await;
+}
+
+#[tokio::test]
+async fn tcp_terminating_the_remote_terminates_the_served() {
+    terminating_the_remote_terminates_the_served(Socket::Tcp, "tcp-remote-end").await;
+}
+
+#[tokio::test]
+async fn tcp_terminating_the_served_terminates_the_remote() {
+    terminating_the_served_terminates_the_remote(Socket::Tcp, "tcp-served-end").await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_posts_and_subscribes() {
+    posts_and_subscribes(Socket::Unix, "unix-post").await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_terminating_the_remote_terminates_the_served() {
+    terminating_the_remote_terminates_the_served(Socket::Unix, "unix-remote-end").await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_terminating_the_served_terminates_the_remote() {
+    terminating_the_served_terminates_the_remote(Socket::Unix, "unix-served-end").await;
+}
+
+// // // // // // // // // // // // // // // // // // // // // // // // // // //
+
+#[cfg(unix)]
+#[tokio::test]
+async fn connects_once_the_worker_is_served() {
+    let path = unix_path("late");
+    let remote = Remote::connect(Endpoint::Unix(path.clone()), Codec::Json);
+    let mut events = remote.subscribe();
+
+    // waits in the mailbox of the remote worker
+    remote.post_message(21).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let served = Served::spawn(Doubler);
+    served.serve_unix(&path, Codec::Json).await.unwrap();
+    assert_eq!(within(events.recv()).await.unwrap(), 42);
+}
+
+#[tokio::test]
+async fn dead_letters_the_messages_that_cant_be_encoded() {
+    let (_served, endpoint) = serve(Socket::Tcp, "tcp-unencodable").await;
+    let remote = RemoteWorker::<TwoWay<Checked, u32>>::connect(endpoint, Codec::Bincode);
+    let dead_letters = DeadLetterQueue::new(10);
+    remote.set_dead_letters(dead_letters.clone());
+    let mut events = remote.subscribe();
+
+    // the next messages don't wait behind it
+    remote.post_message(Checked(0)).await.unwrap();
+    remote.post_message(Checked(1)).await.unwrap();
+    remote.post_message(Checked(2)).await.unwrap();
+    assert_eq!(within(events.recv()).await.unwrap(), 2);
+    assert_eq!(within(events.recv()).await.unwrap(), 4);
+
+    let letters = dead_letters.drain();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].reason, Reason::Unencodable);
+    assert_eq!(letters[0].take_message::<Checked>().map(|c| c.0), Some(0));
+}
+
+// A TCP proxy whose connections can be cut.
+struct Proxy {
+    addr: SocketAddr,
+    cut: Arc<Mutex<CancellationToken>>,
+}
+
+impl Proxy {
+    async fn start(target: SocketAddr) -> Proxy {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cut = Arc::new(Mutex::new(CancellationToken::new()));
+
+        let connections = cut.clone();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                let mut server = TcpStream::connect(target).await.unwrap();
+                let cut = connections.lock().unwrap().clone();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = tokio::io::copy_bidirectional(&mut client, &mut server) => {}
+                        () = cut.cancelled() => {}
+                    }
+                });
+            }
+        });
+
+        Proxy { addr, cut }
+    }
+
+    // Drops the open connections.
+    fn cut(&self) {
+        let mut cut = self.cut.lock().unwrap();
+        cut.cancel();
+        *cut = CancellationToken::new();
+    }
+}
+
+#[tokio::test]
+async fn reconnects_when_the_connection_is_lost() {
+    let served = Served::spawn(Doubler);
+    let addr = served
+        .serve_tcp("127.0.0.1:0", Codec::Bincode)
+        .await
+        .unwrap();
+    let proxy = Proxy::start(addr).await;
+
+    let remote = Remote::connect(Endpoint::Tcp(proxy.addr.to_string()), Codec::Bincode);
+    let mut events = remote.subscribe();
+    remote.post_message(1).await.unwrap();
+    assert_eq!(within(events.recv()).await.unwrap(), 2);
+
+    for i in 2..=4 {
+        proxy.cut();
+        // let the remote worker notice
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        remote.post_message(i).await.unwrap();
+        assert_eq!(within(events.recv()).await.unwrap(), i * 2);
+    }
+
+    // nothing has been terminated
+    assert!(!served.is_finished());
+}
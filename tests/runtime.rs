@@ -0,0 +1,105 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Once,
+    },
+    time::Duration,
+};
+
+use opifex::{
+    handle,
+    runtime::{self, BoxFuture, Runtime, Tokio},
+    worker::OneWay,
+    ErrorKind, Task, Worker,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    time::Instant,
+};
+
+static SPAWNED: AtomicUsize = AtomicUsize::new(0);
+static SLEEPS: AtomicUsize = AtomicUsize::new(0);
+
+// Runs on tokio, counting the spawned futures and the timers.
+struct Counting;
+
+impl Runtime for Counting {
+    fn spawn(&self, future: BoxFuture) {
+        SPAWNED.fetch_add(1, Ordering::SeqCst);
+        Tokio.spawn(future);
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture {
+        SLEEPS.fetch_add(1, Ordering::SeqCst);
+        Tokio.sleep_until(deadline)
+    }
+}
+
+// the runtime is global to the test binary
+fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| runtime::set_global(Counting).unwrap());
+}
+
+// Forwards the batches it receives, waiting for more messages up to 100
+// millis.
+struct Batcher {
+    batches: UnboundedSender<Vec<u32>>,
+}
+
+impl Task for Batcher {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.batched(10, Duration::from_millis(100));
+        let batches = self.batches.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(batch) = rx.recv() => { let _ = batches.send(batch); }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_workers_run_on_the_global_runtime() {
+    install();
+    let (spawned, sleeps) = (
+        SPAWNED.load(Ordering::SeqCst),
+        SLEEPS.load(Ordering::SeqCst),
+    );
+
+    let (batches, mut rx) = unbounded_channel();
+    let worker = Worker::<OneWay<u32>>::spawn(Batcher { batches });
+    assert!(SPAWNED.load(Ordering::SeqCst) > spawned);
+
+    // the linger is a timer of the runtime
+    worker.post_message(1).await.unwrap();
+    assert_eq!(rx.recv().await, Some(vec![1]));
+    assert!(SLEEPS.load(Ordering::SeqCst) > sleeps);
+
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn the_runtime_is_set_once() {
+    install();
+
+    let e = runtime::set_global(Tokio).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Other);
+}
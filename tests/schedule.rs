@@ -0,0 +1,188 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use opifex::{
+    handle,
+    schedule::{MissedTick, Schedule},
+    worker::Scheduled,
+    Task, Worker,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{sleep, Instant},
+};
+
+// Tells when every run starts, and takes as many milliseconds as told by
+// `took`, none when it is empty. Returns how many runs were started.
+struct Run {
+    took: Arc<Mutex<VecDeque<u64>>>,
+    started: UnboundedSender<Instant>,
+    runs: Arc<Mutex<u32>>,
+}
+
+impl Task for Run {
+    type Handle = handle::Worker<handle::Isolated>;
+    type Output = u32;
+
+    fn spawn(
+        &self,
+        _wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = u32> + Send + 'static {
+        let took = self.took.lock().unwrap().pop_front().unwrap_or(0);
+        let started = self.started.clone();
+        let runs = self.runs.clone();
+
+        async move {
+            let _ = started.send(Instant::now());
+            sleep(Duration::from_millis(took)).await;
+            let mut runs = runs.lock().unwrap();
+            *runs += 1;
+            *runs
+        }
+    }
+}
+
+fn worker(
+    schedule: Schedule,
+    took: &[u64],
+) -> (Worker<Scheduled<u32>>, UnboundedReceiver<Instant>) {
+    let (started, rx) = unbounded_channel();
+    let task = Run {
+        took: Arc::new(Mutex::new(took.iter().copied().collect())),
+        started,
+        runs: Arc::new(Mutex::new(0)),
+    };
+    (Worker::<Scheduled<u32>>::spawn(task, schedule), rx)
+}
+
+// Returns when the first `n` runs started, in milliseconds since `start`.
+async fn starts(rx: &mut UnboundedReceiver<Instant>, start: Instant, n: usize) -> Vec<u128> {
+    let mut starts = Vec::new();
+    while starts.len() < n {
+        let at = rx.recv().await.unwrap();
+        starts.push(at.duration_since(start).as_millis());
+    }
+    starts
+}
+
+fn missed_ticks(missed_tick: MissedTick) -> (Worker<Scheduled<u32>>, UnboundedReceiver<Instant>) {
+    // the first run takes the time of two and a half runs
+    let schedule = Schedule::interval(Duration::from_millis(100)).with_missed_tick(missed_tick);
+    worker(schedule, &[250])
+}
+
+#[tokio::test(start_paused = true)]
+async fn runs_every_interval() {
+    let start = Instant::now();
+    let (worker, mut rx) = worker(Schedule::interval(Duration::from_millis(100)), &[10, 10]);
+
+    assert_eq!(starts(&mut rx, start, 3).await, [0, 100, 200]);
+
+    // waiting for the fourth run
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(worker.last_result(), Some(3));
+    let next = worker.next_run().unwrap();
+    assert_eq!(
+        next.duration_since(start.into_std()),
+        Duration::from_millis(300)
+    );
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn waits_a_fixed_delay_after_every_run() {
+    let start = Instant::now();
+    let (worker, mut rx) = worker(Schedule::fixed_delay(Duration::from_millis(100)), &[50, 50]);
+
+    assert_eq!(starts(&mut rx, start, 3).await, [0, 150, 300]);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn bursts_the_missed_runs() {
+    let start = Instant::now();
+    let (worker, mut rx) = missed_ticks(MissedTick::Burst);
+
+    assert_eq!(starts(&mut rx, start, 4).await, [0, 250, 250, 300]);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn delays_after_the_missed_runs() {
+    let start = Instant::now();
+    let (worker, mut rx) = missed_ticks(MissedTick::Delay);
+
+    assert_eq!(starts(&mut rx, start, 4).await, [0, 250, 350, 450]);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn skips_the_missed_runs() {
+    let start = Instant::now();
+    let (worker, mut rx) = missed_ticks(MissedTick::Skip);
+
+    assert_eq!(starts(&mut rx, start, 3).await, [0, 300, 400]);
+
+    worker.terminate();
+}
+
+// Returns when the runs start, pausing the worker after the first run and
+// resuming it after the time of 4 runs.
+async fn resumed(missed_tick: MissedTick) -> Vec<u128> {
+    let start = Instant::now();
+    let schedule = Schedule::interval(Duration::from_millis(100)).with_missed_tick(missed_tick);
+    let (worker, mut rx) = worker(schedule, &[]);
+
+    sleep(Duration::from_millis(50)).await;
+    worker.pause().await.unwrap();
+    sleep(Duration::from_millis(500)).await;
+    worker.resume().await.unwrap();
+
+    let starts = starts(&mut rx, start, 3).await;
+    worker.terminate();
+    starts
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_pause_builds_no_backlog_of_runs() {
+    assert_eq!(resumed(MissedTick::Burst).await, [0, 550, 650]);
+    assert_eq!(resumed(MissedTick::Delay).await, [0, 550, 650]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_pause_skips_to_the_next_run() {
+    assert_eq!(resumed(MissedTick::Skip).await, [0, 600, 700]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_jitter_delays_the_runs() {
+    let start = Instant::now();
+    let schedule =
+        Schedule::interval(Duration::from_millis(100)).with_jitter(Duration::from_millis(50));
+    let (worker, mut rx) = worker(schedule, &[]);
+
+    let starts = starts(&mut rx, start, 10).await;
+    for (n, at) in starts.iter().enumerate() {
+        let on_time = 100 * n as u128;
+        assert!((on_time..=on_time + 50).contains(at), "{starts:?}");
+    }
+    // not always the same delay
+    let delays: Vec<_> = starts.iter().map(|at| at % 100).collect();
+    assert!(delays.iter().any(|delay| *delay != delays[0]), "{delays:?}");
+
+    worker.terminate();
+}
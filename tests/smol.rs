@@ -0,0 +1,131 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+#![cfg(feature = "smol")]
+
+mod common;
+
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use async_executor::Executor;
+use opifex::{
+    handle,
+    runtime::{self, Smol},
+    worker::{OneWay, TwoWay},
+    Task, Worker,
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    time::Instant,
+};
+
+use common::Collect;
+
+// the runtime is global to the test binary
+fn executor() -> Arc<Executor<'static>> {
+    static EXECUTOR: OnceLock<Arc<Executor<'static>>> = OnceLock::new();
+    EXECUTOR
+        .get_or_init(|| {
+            let executor = Arc::new(Executor::new());
+            runtime::set_global(Smol::new(executor.clone())).unwrap();
+            executor
+        })
+        .clone()
+}
+
+// Sends back twice the messages it receives.
+struct Doubler;
+
+impl Task for Doubler {
+    type Handle = handle::Worker<handle::TwoWay<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(n) = rx.recv() => { let _ = hnd.post_message(n * 2).await; }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+// Forwards the batches it receives, waiting for more messages up to 100
+// millis.
+struct Batcher {
+    batches: UnboundedSender<Vec<u32>>,
+}
+
+impl Task for Batcher {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.batched(10, Duration::from_millis(100));
+        let batches = self.batches.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(batch) = rx.recv() => { let _ = batches.send(batch); }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn the_workers_run_without_tokio() {
+    let executor = executor();
+
+    async_io::block_on(executor.run(async {
+        let worker = Worker::<TwoWay<u32, u32>>::spawn(Doubler);
+        let (received, mut rx) = unbounded_channel();
+        let subscriber = worker.on_message(Collect { received });
+
+        for n in 1..=3 {
+            worker.post_message(n).await.unwrap();
+        }
+        for expected in [2, 4, 6] {
+            assert_eq!(rx.recv().await, Some(expected));
+        }
+
+        subscriber.terminate();
+        worker.terminate();
+    }));
+}
+
+#[test]
+fn the_timers_run_without_tokio() {
+    let executor = executor();
+
+    async_io::block_on(executor.run(async {
+        let (batches, mut rx) = unbounded_channel();
+        let worker = Worker::<OneWay<u32>>::spawn(Batcher { batches });
+
+        let start = Instant::now();
+        worker.post_message(1).await.unwrap();
+        assert_eq!(rx.recv().await, Some(vec![1]));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        worker.terminate();
+    }));
+}
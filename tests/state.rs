@@ -0,0 +1,134 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use opifex::{control::Control, handle, worker::State, ErrorKind, Task, Worker};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+// Publishes the numbers sent as custom commands, increments the state on
+// flush and finishes on `stop`.
+struct Publisher {
+    stop: u32,
+}
+
+impl Task for Publisher {
+    type Handle = handle::Worker<handle::State<u32>, u32>;
+    type Output = ();
+
+    fn spawn(&self, hnd: Self::Handle) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let stop = self.stop;
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(cmd) = hnd.control() => match cmd {
+                        Control::Custom(n) if n == stop => break,
+                        Control::Custom(n) => hnd.publish(n),
+                        Control::Flush => hnd.modify(|state| *state += 1),
+                        _ => {}
+                    },
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+// Forwards the states it sees.
+struct Watcher {
+    seen: UnboundedSender<u32>,
+}
+
+impl Task for Watcher {
+    type Handle = handle::Worker<handle::OnChange<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let seen = self.seen.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Ok(()) = rx.changed() => { let _ = seen.send(*rx.borrow_and_update()); }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn publishes_the_latest_state() {
+    let mut worker = Worker::<State<u32>, u32>::spawn(Publisher { stop: 0 }, 1);
+    assert_eq!(*worker.borrow(), 1);
+
+    worker.custom(5).await.unwrap();
+    worker.changed().await.unwrap();
+    assert_eq!(*worker.borrow_and_update(), 5);
+
+    worker.flush().await.unwrap();
+    worker.changed().await.unwrap();
+    assert_eq!(*worker.borrow_and_update(), 6);
+
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn the_subscribers_skip_the_intermediate_states() {
+    let mut worker = Worker::<State<u32>, u32>::spawn(Publisher { stop: 0 }, 1);
+    let mut subscriber = worker.subscribe();
+
+    // the current state is seen as changed
+    assert!(subscriber.has_changed().unwrap());
+    assert_eq!(*subscriber.borrow_and_update(), 1);
+
+    for n in 2..=4 {
+        worker.custom(n).await.unwrap();
+    }
+    worker.flush().await.unwrap();
+    while *worker.borrow_and_update() != 5 {
+        worker.changed().await.unwrap();
+    }
+
+    subscriber.changed().await.unwrap();
+    assert_eq!(*subscriber.borrow_and_update(), 5);
+    assert!(!subscriber.has_changed().unwrap());
+
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn the_subscriber_tasks_start_from_the_current_state() {
+    let mut worker = Worker::<State<u32>, u32>::spawn(Publisher { stop: 0 }, 7);
+    let (seen, mut rx) = unbounded_channel();
+    let watcher = worker.on_change(Watcher { seen });
+
+    assert_eq!(rx.recv().await, Some(7));
+    worker.custom(8).await.unwrap();
+    worker.changed().await.unwrap();
+    assert_eq!(rx.recv().await, Some(8));
+
+    watcher.terminate();
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn changed_fails_when_the_task_has_gone() {
+    let mut worker = Worker::<State<u32>, u32>::spawn(Publisher { stop: 0 }, 1);
+
+    worker.custom(0).await.unwrap();
+    worker.finished().await;
+    let e = worker.changed().await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Terminated);
+    assert!(e.to_string().contains(&worker.id().to_string()));
+
+    // the last state is still there
+    assert_eq!(*worker.borrow(), 1);
+}
@@ -0,0 +1,161 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+
+use opifex::{handle, streaming::Request, worker::Streaming, Task, Worker};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::sleep,
+};
+
+#[derive(Debug, PartialEq)]
+enum Step {
+    Sent(u32, u32),
+    Cancelled(u32),
+}
+
+// Answers n with the numbers from 0 to n, each request in its own task. It
+// waits for the stream to be dropped when asked for 0.
+struct Counter {
+    steps: UnboundedSender<Step>,
+}
+
+impl Task for Counter {
+    type Handle = handle::Worker<handle::Streaming<u32, u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let steps = self.steps.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(Request { message, responder }) = rx.recv() => {
+                        let steps = steps.clone();
+                        tokio::spawn(async move {
+                            if message == 0 {
+                                responder.cancelled().await;
+                                assert!(responder.is_cancelled());
+                                let _ = steps.send(Step::Cancelled(message));
+                                return;
+                            }
+                            for n in 0..message {
+                                if responder.send(n).await.is_err() {
+                                    let _ = steps.send(Step::Cancelled(message));
+                                    return;
+                                }
+                                let _ = steps.send(Step::Sent(message, n));
+                            }
+                        });
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn counter() -> (Worker<Streaming<u32, u32>>, UnboundedReceiver<Step>) {
+    let (steps, rx) = unbounded_channel();
+    (Worker::<Streaming<u32, u32>>::spawn(Counter { steps }), rx)
+}
+
+#[tokio::test]
+async fn the_stream_ends_with_the_responder() {
+    let (worker, _steps) = counter();
+
+    let mut responses = worker.ask(3).await.unwrap();
+    for expected in 0..3 {
+        assert_eq!(responses.recv().await, Some(expected));
+    }
+    assert_eq!(responses.recv().await, None);
+
+    worker.terminate();
+}
+
+#[tokio::test]
+async fn every_request_has_its_own_stream() {
+    let (worker, _steps) = counter();
+
+    let mut short = worker.ask(2).await.unwrap();
+    let mut long = worker.ask(4).await.unwrap();
+
+    // read the long one first, the short one is still all there
+    for expected in 0..4 {
+        assert_eq!(long.recv().await, Some(expected));
+    }
+    assert_eq!(long.recv().await, None);
+    for expected in 0..2 {
+        assert_eq!(short.recv().await, Some(expected));
+    }
+    assert_eq!(short.recv().await, None);
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_full_stream_makes_the_task_wait() {
+    let (worker, mut steps) = counter();
+
+    let mut slow = worker.ask_with_capacity(10, 2).await.unwrap();
+    let mut fast = worker.ask_with_capacity(3, 2).await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+
+    // the slow stream buffers 2 responses, the other one isn't slowed down
+    let mut sent = Vec::new();
+    while let Ok(step) = steps.try_recv() {
+        sent.push(step);
+    }
+    assert_eq!(
+        sent.iter()
+            .filter(|s| matches!(s, Step::Sent(10, _)))
+            .count(),
+        2
+    );
+    assert_eq!(
+        sent.iter()
+            .filter(|s| matches!(s, Step::Sent(3, _)))
+            .count(),
+        2
+    );
+    for expected in 0..3 {
+        assert_eq!(fast.recv().await, Some(expected));
+    }
+    assert_eq!(fast.recv().await, None);
+
+    // receiving makes room for the next ones
+    assert_eq!(slow.recv().await, Some(0));
+    assert_eq!(slow.recv().await, Some(1));
+    assert_eq!(slow.recv().await, Some(2));
+
+    worker.terminate();
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropping_the_stream_cancels_the_request() {
+    let (worker, mut steps) = counter();
+
+    // the task is waiting for room in the stream
+    let stream = worker.ask_with_capacity(u32::MAX, 1).await.unwrap();
+    assert_eq!(steps.recv().await, Some(Step::Sent(u32::MAX, 0)));
+    drop(stream);
+    assert_eq!(steps.recv().await, Some(Step::Cancelled(u32::MAX)));
+
+    // the task is waiting for the cancellation
+    let stream = worker.ask(0).await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+    assert!(steps.try_recv().is_err());
+    drop(stream);
+    assert_eq!(steps.recv().await, Some(Step::Cancelled(0)));
+
+    worker.terminate();
+}
@@ -0,0 +1,142 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+
+use opifex::{
+    handle,
+    retry::{Backoff, RetryPolicy, Retrying},
+    supervisor::Supervisor,
+    worker::OneWay,
+    ErrorKind, Task, Worker,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+// Forwards the messages it receives, and finishes when it receives 0.
+#[derive(Clone)]
+struct Crashy {
+    received: UnboundedSender<u32>,
+}
+
+impl Task for Crashy {
+    type Handle = handle::Worker<handle::OneWay<u32>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let received = self.received.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(msg) = rx.recv() => {
+                        if msg == 0 {
+                            break;
+                        }
+                        let _ = received.send(msg);
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn supervisor(delay: Duration) -> (Supervisor<OneWay<u32>>, UnboundedReceiver<u32>) {
+    let (received, rx) = unbounded_channel();
+    let task = Crashy { received };
+    let supervisor = Supervisor::spawn_with_backoff(
+        move || Worker::<OneWay<u32>>::spawn(task.clone()),
+        Backoff::Fixed(delay),
+    );
+    (supervisor, rx)
+}
+
+#[tokio::test(start_paused = true)]
+async fn restarts_the_finished_task() {
+    let (supervisor, mut received) = supervisor(Duration::from_millis(100));
+    let first = supervisor.worker().id();
+
+    supervisor.post_message(1).await.unwrap();
+    assert_eq!(received.recv().await, Some(1));
+
+    supervisor.post_message(0).await.unwrap();
+    supervisor.worker().finished().await;
+
+    // within the restart window
+    let e = supervisor.post_message(2).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Restarting);
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(supervisor.restarts(), 1);
+    assert_ne!(supervisor.worker().id(), first);
+    supervisor.post_message(3).await.unwrap();
+    assert_eq!(received.recv().await, Some(3));
+}
+
+#[tokio::test(start_paused = true)]
+async fn retrying_doesnt_lose_messages_during_a_restart() {
+    let (supervisor, mut received) = supervisor(Duration::from_millis(100));
+    let retrying = Retrying::new(
+        supervisor,
+        RetryPolicy::fixed(Duration::from_millis(30)).with_deadline(Duration::from_secs(1)),
+    );
+
+    retrying.post_message(0).await.unwrap();
+    retrying.worker().worker().finished().await;
+    for i in 1..=5 {
+        retrying.post_message(i).await.unwrap();
+    }
+
+    for i in 1..=5 {
+        assert_eq!(received.recv().await, Some(i));
+    }
+    assert_eq!(retrying.worker().restarts(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn terminate_stops_the_restarts() {
+    let (supervisor, _received) = supervisor(Duration::ZERO);
+    let worker = supervisor.worker();
+
+    supervisor.terminate();
+    worker.finished().await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(
+        worker.post_message(1).await.unwrap_err().kind(),
+        ErrorKind::Terminated
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn backs_off_the_consecutive_restarts() {
+    let (received, _rx) = unbounded_channel();
+    let task = Crashy { received };
+    let supervisor = Supervisor::spawn_with_backoff(
+        move || Worker::<OneWay<u32>>::spawn(task.clone()),
+        Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(400),
+        },
+    );
+
+    // the delays are 100, 200 and 400 ms
+    for (restarts, delay) in [(1, 100), (2, 200), (3, 400)] {
+        supervisor.worker().post_message(0).await.unwrap();
+        supervisor.worker().finished().await;
+        let finished = tokio::time::Instant::now();
+        while supervisor.restarts() < restarts {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let elapsed = finished.elapsed().as_millis();
+        assert!((delay..delay + 5).contains(&elapsed), "{elapsed}");
+    }
+}
@@ -0,0 +1,163 @@
+/*
+SPDX-License-Identifier: GPL-3.0-only
+
+Copyright (C) 2024  Attilio Donà attilio.dona@gmail.com
+Copyright (C) 2024  Claudio Carraro carraro.claudio@gmail.com
+*/
+
+use std::time::Duration;
+
+use opifex::{
+    handle,
+    health::Status,
+    watchdog::{OnStall, Watchdog},
+    worker::OneWay,
+    Task, Worker,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+// Takes as many milliseconds as every message tells, marking it by hand, and
+// reports how it went: true when done, false when cancelled.
+struct Sleeper {
+    done: UnboundedSender<(u64, bool)>,
+}
+
+impl Task for Sleeper {
+    type Handle = handle::Worker<handle::OneWay<u64>>;
+    type Output = ();
+
+    fn spawn(
+        &self,
+        wk_hnd: Self::Handle,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let (mut rx, hnd) = wk_hnd.receiver();
+        let done = self.done.clone();
+
+        async move {
+            loop {
+                tokio::select! {
+                    Some(msg) = rx.recv() => {
+                        let guard = hnd.start_message();
+                        let completed = tokio::select! {
+                            () = tokio::time::sleep(Duration::from_millis(msg)) => true,
+                            () = guard.cancelled() => false,
+                        };
+                        let _ = done.send((msg, completed));
+                    }
+                    () = hnd.terminated() => break,
+                }
+            }
+        }
+    }
+}
+
+fn sleeper(on_stall: OnStall) -> (Worker<OneWay<u64>>, UnboundedReceiver<(u64, bool)>) {
+    let (done, rx) = unbounded_channel();
+    let worker = Worker::<OneWay<u64>>::spawn(Sleeper { done });
+    worker.set_watchdog(Watchdog::new(Duration::from_millis(100)).with_on_stall(on_stall));
+    (worker, rx)
+}
+
+#[tokio::test(start_paused = true)]
+async fn notifies_the_stalls() {
+    let (worker, mut done) = sleeper(OnStall::Notify);
+    let mut stalls = worker.stalls();
+
+    worker.post_message(50).await.unwrap();
+    assert_eq!(done.recv().await, Some((50, true)));
+
+    worker.post_message(300).await.unwrap();
+    let stall = stalls.recv().await.unwrap();
+    assert_eq!(stall.worker, worker.id());
+    assert_eq!(stall.sequence, 2);
+    assert_eq!(stall.action, OnStall::Notify);
+    assert!(stall.elapsed >= Duration::from_millis(100));
+    assert_eq!(worker.health().status, Status::Stalled);
+
+    // the message completes anyway
+    assert_eq!(done.recv().await, Some((300, true)));
+    assert_ne!(worker.health().status, Status::Stalled);
+    assert!(stalls.try_recv().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancels_the_stalled_message() {
+    let (worker, mut done) = sleeper(OnStall::Cancel);
+    let mut stalls = worker.stalls();
+
+    worker.post_message(300).await.unwrap();
+    worker.post_message(10).await.unwrap();
+    assert_eq!(done.recv().await, Some((300, false)));
+    assert_eq!(stalls.recv().await.unwrap().action, OnStall::Cancel);
+
+    // the next message is not affected
+    assert_eq!(done.recv().await, Some((10, true)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn terminates_the_stalled_task() {
+    let (worker, mut done) = sleeper(OnStall::Terminate);
+    let mut stalls = worker.stalls();
+
+    worker.post_message(1000).await.unwrap();
+    assert_eq!(stalls.recv().await.unwrap().action, OnStall::Terminate);
+    worker.finished().await;
+    assert_eq!(worker.health().status, Status::Terminated);
+    assert_eq!(done.recv().await, Some((1000, true)));
+}
+
+#[cfg(feature = "futures")]
+mod stream {
+    use futures::StreamExt;
+
+    use super::*;
+
+    // Like Sleeper, but the messages are marked by the stream.
+    struct StreamSleeper {
+        done: UnboundedSender<(u64, bool)>,
+    }
+
+    impl Task for StreamSleeper {
+        type Handle = handle::Worker<handle::OneWay<u64>>;
+        type Output = ();
+
+        fn spawn(
+            &self,
+            wk_hnd: Self::Handle,
+        ) -> impl std::future::Future<Output = ()> + Send + 'static {
+            let (mut stream, _hnd) = wk_hnd.stream();
+            let done = self.done.clone();
+
+            async move {
+                while let Some(msg) = stream.next().await {
+                    let guard = stream.current_message().unwrap();
+                    let completed = tokio::select! {
+                        () = tokio::time::sleep(Duration::from_millis(msg)) => true,
+                        () = guard.cancelled() => false,
+                    };
+                    let _ = done.send((msg, completed));
+                }
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_stream_marks_the_messages() {
+        let (done, mut rx) = unbounded_channel();
+        let worker = Worker::<OneWay<u64>>::spawn(StreamSleeper { done });
+        worker
+            .set_watchdog(Watchdog::new(Duration::from_millis(100)).with_on_stall(OnStall::Cancel));
+        let mut stalls = worker.stalls();
+
+        worker.post_message(50).await.unwrap();
+        worker.post_message(300).await.unwrap();
+        assert_eq!(rx.recv().await, Some((50, true)));
+        assert_eq!(rx.recv().await, Some((300, false)));
+        assert_eq!(stalls.recv().await.unwrap().sequence, 2);
+
+        // waiting for the next message is not a stall
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(stalls.try_recv().is_err());
+        assert_ne!(worker.health().status, Status::Stalled);
+    }
+}